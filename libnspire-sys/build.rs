@@ -1,10 +1,11 @@
 use globwalk::DirEntry;
 
 fn main() {
+    // Also picks up new source files
+    println!("cargo:rerun-if-changed=libnspire/src");
     let files = globwalk::GlobWalkerBuilder::from_patterns("libnspire/src", &["*.{c,cpp}"])
         .build()
         .unwrap()
-        .into_iter()
         .filter_map(Result::ok)
         .map(DirEntry::into_path)
         .inspect(|path| println!("cargo:rerun-if-changed={}", path.display()));
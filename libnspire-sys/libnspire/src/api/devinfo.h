@@ -92,6 +92,23 @@ struct nspire_devinfo {
 
 	/* Misc */
 	enum nspire_runlevel runlevel;
+
+	/* Hardware revision */
+	uint32_t hw_version;
+
+	/* Where the screen starts on the LCD */
+	struct {
+		uint16_t x, y;
+	} lcd_offset;
+
+	/* Short form of electronic_id */
+	char short_id[18];
+
+	/* The device info reply as received, without its leading byte */
+	struct {
+		uint8_t data[252];
+		uint16_t len;
+	} raw;
 };
 
 int nspire_device_info(nspire_handle_t *handle, struct nspire_devinfo *i);
@@ -0,0 +1,32 @@
+/*
+    This file is part of libnspire.
+
+    libnspire is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    libnspire is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with libnspire.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+
+#ifndef NSP_ECHO_H
+#define NSP_ECHO_H
+
+#include <stddef.h>
+#include "handle.h"
+
+/*
+	Send size bytes to the calculator and wait for it to send them back.
+	This goes through the NNSE echo service on a CX II, and the NavNet one
+	otherwise. At most 254 bytes can be sent, or 1440 to a CX II.
+*/
+int nspire_ping(nspire_handle_t *handle, const void *payload, size_t size);
+
+#endif
@@ -44,6 +44,9 @@ enum {
 	/* OS install */
 	NSPIRE_ERR_OSFAILED,
 
+	/* Not possible on this calculator */
+	NSPIRE_ERR_NOTSUPPORTED,
+
 	/* Number of errors */
 	NSPIRE_ERR_MAX
 };
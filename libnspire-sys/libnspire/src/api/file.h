@@ -29,6 +29,14 @@ int nspire_file_move(nspire_handle_t *handle, const char *src, const char *dst);
 int nspire_file_copy(nspire_handle_t *handle, const char *src, const char *dst);
 int nspire_file_delete(nspire_handle_t *handle, const char *path);
 
+/*
+	Keep the file service connected until nspire_file_session_end, instead
+	of connecting and disconnecting in every file and directory function
+	called in between.
+*/
+int nspire_file_session_begin(nspire_handle_t *handle);
+int nspire_file_session_end(nspire_handle_t *handle);
+
 #define nspire_file_touch(h,p)	nspire_file_write((h), (p), NULL, 0);
 #define nspire_file_rename	nspire_file_move
 
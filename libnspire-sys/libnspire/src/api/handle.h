@@ -19,11 +19,89 @@
 #define NSP_HANDLE_H
 
 #include <stdbool.h>
+#include <stdint.h>
 
 typedef struct nspire_handle nspire_handle_t;
 typedef struct libusb_device_handle libusb_device_handle;
 
+/*
+	A way of exchanging raw packets with a calculator. Both functions
+	return the number of bytes transferred, or a negative NSPIRE_ERR_*
+	code. timeout is in milliseconds.
+*/
+typedef int (*nspire_transport_read)(void *data, void *buf, int len,
+		unsigned int timeout);
+typedef int (*nspire_transport_write)(void *data, const void *buf, int len,
+		unsigned int timeout);
+
+struct nspire_transport {
+	void *data;
+	nspire_transport_read read;
+	nspire_transport_write write;
+};
+
+/*
+	Called with every frame sent or received, as it is on the wire. Frames
+	are either NavNet packets or, on the CX II, the NNSE messages that carry
+	them.
+*/
+enum nspire_tap_proto {
+	NSPIRE_TAP_NAVNET,
+	NSPIRE_TAP_NNSE
+};
+
+typedef void (*nspire_tap)(void *data, enum nspire_tap_proto proto,
+		bool sent, const void *buf, int len);
+
+/*
+	Called with diagnostics from all handles, which are otherwise discarded.
+	Nothing is printed.
+*/
+enum nspire_log_level {
+	NSPIRE_LOG_DEBUG,
+	NSPIRE_LOG_WARN
+};
+
+typedef void (*nspire_log)(void *data, enum nspire_log_level level,
+		const char *message);
+
+/*
+	Answers a CX II's requests for the time, which it makes while
+	connecting. Set sec to the seconds since the Unix epoch and frac to the
+	fraction of a second, in units of 2^-64 seconds, and return true, or
+	return false to leave the calculator's clock alone. The request then goes
+	unanswered, which hasn't been checked against a real calculator.
+*/
+typedef bool (*nspire_clock)(void *data, uint32_t *sec, uint64_t *frac);
+
+/* Settings for connecting. Zero them for the defaults. */
+struct nspire_opts {
+	/* NULL to use the host's time */
+	nspire_clock clock;
+	void *clock_data;
+};
+
 int nspire_init(nspire_handle_t **ptr, libusb_device_handle *dev, bool is_cx2);
+int nspire_init_transport(nspire_handle_t **ptr,
+		const struct nspire_transport *transport, bool is_cx2);
+/* Like the above, but opts may be NULL for the defaults */
+int nspire_init_opts(nspire_handle_t **ptr, libusb_device_handle *dev,
+		bool is_cx2, const struct nspire_opts *opts);
+int nspire_init_transport_opts(nspire_handle_t **ptr,
+		const struct nspire_transport *transport, bool is_cx2,
+		const struct nspire_opts *opts);
 void nspire_free(nspire_handle_t *ptr);
+/* Pass NULL to stop tapping */
+void nspire_set_tap(nspire_handle_t *ptr, nspire_tap tap, void *data);
+/* Pass NULL to discard diagnostics */
+void nspire_set_log(nspire_log log, void *data);
+/*
+	The most data sent in one packet, from 254 to 1440 bytes. Packets of
+	more than 254 bytes use the big-data escape, which a CX II always
+	accepts but other calculators may not. Defaults to 1440 on a CX II and
+	254 otherwise.
+*/
+int nspire_set_packet_size(nspire_handle_t *ptr, uint32_t size);
+uint32_t nspire_packet_size(nspire_handle_t *ptr);
 
 #endif
@@ -0,0 +1,33 @@
+/*
+    This file is part of libnspire.
+
+    libnspire is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    libnspire is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with libnspire.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+#ifndef NSP_KEYS_H
+#define NSP_KEYS_H
+
+#include <inttypes.h>
+#include <stddef.h>
+#include "handle.h"
+
+/*
+	Press keys on the calculator, one after another. Each code has the
+	character the key types, if any, in its high byte and the key's scan code
+	in its low byte.
+*/
+int nspire_send_keys(nspire_handle_t *handle, const uint16_t *keys,
+		size_t count);
+
+#endif
@@ -0,0 +1,32 @@
+/*
+    This file is part of libnspire.
+
+    libnspire is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    libnspire is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with libnspire.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+
+#ifndef NSP_LOGIN_H
+#define NSP_LOGIN_H
+
+#include "handle.h"
+
+/*
+	Tell the calculator the host's name, as the TI software does when it
+	connects. The name can be up to 52 bytes long. Fails with
+	NSPIRE_ERR_NOTSUPPORTED on a CX II, as how it learns the name isn't
+	known.
+*/
+int nspire_login(nspire_handle_t *handle, const char *name);
+
+#endif
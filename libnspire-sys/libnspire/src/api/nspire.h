@@ -20,11 +20,15 @@
 
 #include "usb.h"
 #include "devinfo.h"
+#include "echo.h"
 #include "dir.h"
 #include "error.h"
 #include "file.h"
 #include "handle.h"
+#include "keys.h"
+#include "login.h"
 #include "os.h"
 #include "screenshot.h"
+#include "protocol.h"
 
 #endif
@@ -18,10 +18,21 @@
 #ifndef NSP_OS_H
 #define NSP_OS_H
 
+#include <inttypes.h>
 #include <string.h>
 #include "handle.h"
 
+enum nspire_os_phase {
+	/* The image is being sent; the value is the number of bytes left */
+	NSPIRE_OS_UPLOAD,
+	/* The calculator is installing; the value is its progress in percent */
+	NSPIRE_OS_INSTALL
+};
+
 typedef void (*nspire_callback)(size_t, void*);
+typedef void (*nspire_os_callback)(enum nspire_os_phase, size_t, void*);
 int nspire_os_send(nspire_handle_t *handle, void* data, size_t size, nspire_callback cb, void *cb_data);
+int nspire_os_install(nspire_handle_t *handle, void* data, size_t size,
+		nspire_os_callback cb, void *cb_data, uint8_t *status);
 
 #endif
@@ -0,0 +1,64 @@
+/*
+    This file is part of libnspire.
+
+    libnspire is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    libnspire is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with libnspire.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+#ifndef NSP_PROTOCOL_H
+#define NSP_PROTOCOL_H
+
+/* NavNet, spoken by every calculator */
+enum {
+	NSPIRE_NAVNET_MAGIC		= 0x54FD,
+
+	/* Addresses */
+	NSPIRE_ADDR_HOST		= 0x6400,
+	NSPIRE_ADDR_DEVICE		= 0x6401,
+
+	/* Service IDs */
+	NSPIRE_SID_NACK			= 0x00D3,
+	NSPIRE_SID_ACK_ZERO		= 0x00FE,
+	NSPIRE_SID_ACK			= 0x00FF,
+	NSPIRE_SID_ECHO			= 0x4002,
+	NSPIRE_SID_ADDR_ASSIGN		= 0x4003,
+	NSPIRE_SID_DEVINFO		= 0x4020,
+	NSPIRE_SID_SCREENSHOT		= 0x4024,
+	NSPIRE_SID_KEYS			= 0x4042,
+	NSPIRE_SID_LOGIN		= 0x4050,
+	NSPIRE_SID_FILE			= 0x4060,
+	NSPIRE_SID_OS			= 0x4080,
+	NSPIRE_SID_DISCONNECT		= 0x40DE
+};
+
+/* NavNet SE, which carries NavNet packets to and from a CX II */
+enum {
+	/* Addresses */
+	NSPIRE_NNSE_ADDR_CALC		= 0x01,
+	NSPIRE_NNSE_ADDR_ME		= 0xFE,
+	NSPIRE_NNSE_ADDR_ALL		= 0xFF,
+
+	/* Services */
+	NSPIRE_NNSE_SVC_ADDR_REQ	= 0x01,
+	NSPIRE_NNSE_SVC_TIME		= 0x02,
+	NSPIRE_NNSE_SVC_ECHO		= 0x03,
+	NSPIRE_NNSE_SVC_STREAM		= 0x04,
+	NSPIRE_NNSE_SVC_TRANSMIT	= 0x05,
+	NSPIRE_NNSE_SVC_LOOPBACK	= 0x06,
+	NSPIRE_NNSE_SVC_STATS		= 0x07,
+	NSPIRE_NNSE_SVC_UNKNOWN		= 0x08,
+	/* Set in the service of acknowledgements */
+	NSPIRE_NNSE_ACK_FLAG		= 0x80
+};
+
+#endif
@@ -17,7 +17,6 @@
 
 #include <algorithm>
 #include <cstdint>
-#include <cstdio>
 #include <cstdlib>
 #include <cstring>
 
@@ -40,30 +39,31 @@ int gettimeofday(struct timeval *t, void *timezone) {
 #define PACK( __Declaration__ ) __Declaration__ __attribute__((__packed__))
 #endif
 
-#include <libusb.h>
-
 #include "cx2.h"
 #include "error.h"
+#include "handle.h"
+#include "log.h"
 #include "packet.h"
+#include "protocol.h"
 // Windows...
 #undef min
 
 enum Address {
-	AddrAll		= 0xFF,
-	AddrMe		= 0xFE,
-	AddrCalc	= 0x01
+	AddrAll		= NSPIRE_NNSE_ADDR_ALL,
+	AddrMe		= NSPIRE_NNSE_ADDR_ME,
+	AddrCalc	= NSPIRE_NNSE_ADDR_CALC
 };
 
 enum Service {
-	AddrReqService  = 0x01,
-	TimeService     = 0x02,
-	EchoService     = 0x03,
-	StreamService   = 0x04,
-	TransmitService = 0x05,
-	LoopbackService = 0x06,
-	StatsService    = 0x07,
-	UnknownService  = 0x08,
-	AckFlag         = 0x80
+	AddrReqService  = NSPIRE_NNSE_SVC_ADDR_REQ,
+	TimeService     = NSPIRE_NNSE_SVC_TIME,
+	EchoService     = NSPIRE_NNSE_SVC_ECHO,
+	StreamService   = NSPIRE_NNSE_SVC_STREAM,
+	TransmitService = NSPIRE_NNSE_SVC_TRANSMIT,
+	LoopbackService = NSPIRE_NNSE_SVC_LOOPBACK,
+	StatsService    = NSPIRE_NNSE_SVC_STATS,
+	UnknownService  = NSPIRE_NNSE_SVC_UNKNOWN,
+	AckFlag         = NSPIRE_NNSE_ACK_FLAG
 };
 
 // Big endian!
@@ -112,27 +112,6 @@ static uint8_t *getPacketData(const NNSEMessage *c) {
 	return ((uint8_t *)c) + sizeof(NNSEMessage);
 }
 
-#ifdef DEBUG
-static void dumpPacket(const NNSEMessage *message)
-{
-	printf("Misc:   \t%02x\n", message->misc);
-	printf("Service:\t%02x\n", message->service);
-	printf("Dest:   \t%02x\n", message->dest);
-	printf("Src:    \t%02x\n", message->src);
-	printf("Unknown:\t%02x\n", message->unknown);
-	printf("ReqAck: \t%02x\n", message->reqAck);
-	printf("Length: \t%04x\n", ntohs(message->length));
-	printf("SeqNo:  \t%04x\n", ntohs(message->seqno));
-	printf("Csum:   \t%04x\n", ntohs(message->csum));
-
-	auto datalen = ntohs(message->length) - sizeof(NNSEMessage);
-	for(int i = 0; i < datalen; ++i)
-		printf("%02x ", getPacketData(message)[i]);
-
-	printf("\n");
-}
-#endif
-
 static uint16_t compute_checksum(const uint8_t *data, uint32_t size)
 {
 	uint32_t acc = 0;
@@ -155,17 +134,16 @@ static uint16_t compute_checksum(const uint8_t *data, uint32_t size)
 	return acc;
 }
 
-static bool readPacket(libusb_device_handle *handle, NNSEMessage *message, int maxlen)
+static bool readPacket(struct nspire_handle *handle, NNSEMessage *message, int maxlen)
 {
 	if(maxlen < sizeof(NNSEMessage))
 		return false;
 
-	int transferred = 0;
+	const struct nspire_transport &transport = handle->transport;
 	memset(message, 0, sizeof(NNSEMessage));
-	int r = libusb_bulk_transfer(handle, 0x81, reinterpret_cast<unsigned char*>(message), maxlen, &transferred, 60000);
+	int transferred = transport.read(transport.data, message, maxlen, 60000);
 
-	if(r < 0
-		|| transferred < sizeof(NNSEMessage))
+	if(transferred < int(sizeof(NNSEMessage)))
 		return false;
 
 	const auto completeLength = ntohs(message->length);
@@ -174,30 +152,30 @@ static bool readPacket(libusb_device_handle *handle, NNSEMessage *message, int m
 		|| completeLength > maxlen)
 		return false;
 
+	// Anything past the end of the message isn't part of it
+	transferred = std::min(transferred, int(completeLength));
+
 	uint8_t *data = reinterpret_cast<uint8_t*>(message) + transferred;
 	auto remainingLength = completeLength - transferred;
 	while(remainingLength > 0)
 	{
-		r = libusb_bulk_transfer(handle, 0x81, data, remainingLength, &transferred, 1000);
-		if(r < 0)
+		transferred = transport.read(transport.data, data, remainingLength, 1000);
+		if(transferred <= 0)
 			return false;
 
 		data += transferred;
 		remainingLength -= transferred;
 	}
 
-#ifdef DEBUG
-	printf("Got packet:\n");
-	dumpPacket(message);
-#endif
+	handle_tap(handle, NSPIRE_TAP_NNSE, false, message, completeLength);
 
-	if(compute_checksum(reinterpret_cast<uint8_t*>(message), transferred) != 0xFFFF)
+	if(compute_checksum(reinterpret_cast<uint8_t*>(message), completeLength) != 0xFFFF)
 		return false;
 
 	return true;
 }
 
-static bool writePacket(libusb_device_handle *handle, NNSEMessage *message)
+static bool writePacket(struct nspire_handle *handle, NNSEMessage *message)
 {
 	auto length = ntohs(message->length);
 
@@ -207,32 +185,27 @@ static bool writePacket(libusb_device_handle *handle, NNSEMessage *message)
 	if(compute_checksum(reinterpret_cast<uint8_t*>(message), length) != 0xFFFF)
 		return false;
 
-#ifdef DEBUG
-	printf("Sending packet:\n");
-	dumpPacket(message);
-#endif
+	handle_tap(handle, NSPIRE_TAP_NNSE, true, message, length);
 
-	int transferred = 0;
-	int r = libusb_bulk_transfer(handle, 0x01, reinterpret_cast<unsigned char*>(message), length, &transferred, 1000);
-	if(r < 0
-		|| length != transferred)
+	const struct nspire_transport &transport = handle->transport;
+	int transferred = transport.write(transport.data, message, length, 1000);
+	if(length != transferred)
 		return false;
 
 	return true;
 }
 
-static uint16_t nextSeqno()
+static uint16_t nextSeqno(struct nspire_handle *handle)
 {
-	static uint16_t seqno = 0;
-	return seqno++;
+	return handle->cx2_seqno++;
 }
 
-template <typename T> bool sendMessage(libusb_device_handle *handle, T &message)
+template <typename T> bool sendMessage(struct nspire_handle *handle, T &message)
 {
 	message.hdr.src = AddrMe;
 	message.hdr.dest = AddrCalc;
 	message.hdr.length = htons(sizeof(T));
-	message.hdr.seqno = htons(nextSeqno());
+	message.hdr.seqno = htons(nextSeqno(handle));
 
 	return writePacket(handle, &message.hdr);
 }
@@ -245,41 +218,64 @@ template <typename T> T* messageCast(NNSEMessage *message)
 	return reinterpret_cast<T*>(message);
 }
 
-static void handlePacket(struct nspire_handle *nsp_handle, NNSEMessage *message, uint8_t **streamdata = nullptr, int *streamsize = nullptr)
+static void ackPacket(struct nspire_handle *nsp_handle, const NNSEMessage *message)
 {
-	auto *handle = nsp_handle->device.dev;
+	if(!(message->reqAck & 1))
+		return;
+
+	NNSEMessage ack = {};
+	ack.misc = message->misc;
+	ack.service = uint8_t(message->service | AckFlag);
+	ack.src = message->dest;
+	ack.dest = message->src;
+	ack.unknown = message->unknown;
+	ack.reqAck = uint8_t(message->reqAck & ~1);
+	ack.length = htons(sizeof(NNSEMessage));
+	ack.seqno = message->seqno;
+
+	if(!writePacket(nsp_handle, &ack))
+		log_message(NSPIRE_LOG_WARN, "Failed to ack %04x",
+				ntohs(message->seqno));
+}
 
+// Send an echo request or reply carrying size bytes of data.
+static bool sendEcho(struct nspire_handle *nsp_handle, const void *data, int size, uint8_t reqAck)
+{
+	int len = sizeof(NNSEMessage) + size;
+	NNSEMessage *msg = reinterpret_cast<NNSEMessage*>(calloc(1, len));
+	if(!msg)
+		return false;
+
+	msg->service = EchoService;
+	msg->src = AddrMe;
+	msg->dest = AddrCalc;
+	msg->reqAck = reqAck;
+	msg->length = htons(len);
+	msg->seqno = htons(nextSeqno(nsp_handle));
+	memcpy(getPacketData(msg), data, size);
+
+	bool ok = writePacket(nsp_handle, msg);
+	free(msg);
+	return ok;
+}
+
+static void handlePacket(struct nspire_handle *nsp_handle, NNSEMessage *message, uint8_t **streamdata = nullptr, int *streamsize = nullptr)
+{
 	if(message->dest != AddrMe && message->dest != AddrAll)
 	{
-#ifdef DEBUG
-		printf("Not for me?\n");
-#endif
+		log_message(NSPIRE_LOG_DEBUG, "Ignoring message for address %02x",
+				message->dest);
 		return;
 	}
 
 	if(message->service & AckFlag)
 	{
-#ifdef DEBUG
-		printf("Got ack for %02x\n", ntohs(message->seqno));
-#endif
+		log_message(NSPIRE_LOG_DEBUG, "Got ack for %04x",
+				ntohs(message->seqno));
 		return;
 	}
 
-	if(message->reqAck & 1)
-	{
-		NNSEMessage ack = {};
-		ack.misc = message->misc;
-		ack.service = uint8_t(message->service | AckFlag);
-		ack.src = message->dest;
-		ack.dest = message->src;
-		ack.unknown = message->unknown;
-		ack.reqAck = uint8_t(message->reqAck & ~1);
-		ack.length = htons(sizeof(NNSEMessage));
-		ack.seqno = message->seqno;
-
-		if(!writePacket(handle, &ack))
-			printf("Failed to ack\n");
-	}
+	ackPacket(nsp_handle, message);
 
 	switch(message->service & ~AckFlag)
 	{
@@ -289,23 +285,23 @@ static void handlePacket(struct nspire_handle *nsp_handle, NNSEMessage *message,
 			if(!req || req->code != 0)
 				goto drop;
 
-#ifdef DEBUG
-			printf("Got request from client %s (product id %c%c)\n", &req->clientID[12], req->clientID[10], req->clientID[11]);
-#endif
+			log_message(NSPIRE_LOG_DEBUG,
+					"Got address request from client %.52s (product id %c%c)",
+					&req->clientID[12], req->clientID[10], req->clientID[11]);
 
 			NNSEMessage_AddrResp resp = {};
 			resp.hdr.service = message->service;
 			resp.addr = AddrCalc;
 
-			if(!sendMessage(handle, resp))
-				printf("Failed to send message\n");
+			if(!sendMessage(nsp_handle, resp))
+				log_message(NSPIRE_LOG_WARN, "Failed to send message");
 
 			NNSEMessage_AddrResp resp2 = {};
 			resp2.hdr.service = message->service;
 			resp2.addr = 0x80; // No idea
 
-			if(!sendMessage(handle, resp2))
-				printf("Failed to send message\n");
+			if(!sendMessage(nsp_handle, resp2))
+				log_message(NSPIRE_LOG_WARN, "Failed to send message");
 
 			break;
 		}
@@ -315,21 +311,36 @@ static void handlePacket(struct nspire_handle *nsp_handle, NNSEMessage *message,
 			if(!req || req->code != 0)
 				goto drop;
 
-#ifdef DEBUG
-			printf("Got time request\n");
-#endif
-
-			struct timeval val;
-			gettimeofday(&val, nullptr);
+			log_message(NSPIRE_LOG_DEBUG, "Got time request");
+
+			uint32_t sec;
+			uint64_t frac;
+			const struct nspire_opts &opts = nsp_handle->opts;
+			if(!opts.clock)
+			{
+				struct timeval val;
+				gettimeofday(&val, nullptr);
+				sec = uint32_t(val.tv_sec);
+				// Microseconds, in units of 2^-64 seconds
+				frac = ((uint64_t(val.tv_usec) << 32) / 1000000) << 32;
+			}
+			else if(!opts.clock(opts.clock_data, &sec, &frac))
+			{
+				log_message(NSPIRE_LOG_DEBUG, "Leaving the calculator's clock alone");
+				// Assumes the calculator doesn't wait for an answer.
+				// Unchecked against a real calculator.
+				nsp_handle->cx2_handshake_complete = true;
+				break;
+			}
 
 			NNSEMessage_TimeResp resp = {};
 			resp.hdr.service = message->service;
 			resp.noidea = 0x80;
-			resp.sec = htonl(uint32_t(val.tv_sec));
-			resp.frac = 0;
+			resp.sec = htonl(sec);
+			resp.frac = dcpu64(frac);
 
-			if(!sendMessage(handle, resp))
-				printf("Failed to send message\n");
+			if(!sendMessage(nsp_handle, resp))
+				log_message(NSPIRE_LOG_WARN, "Failed to send message");
 
 			nsp_handle->cx2_handshake_complete = true;
 			break;
@@ -339,17 +350,25 @@ static void handlePacket(struct nspire_handle *nsp_handle, NNSEMessage *message,
 			if(ntohs(message->length) != sizeof(NNSEMessage) + 1 || getPacketData(message)[0] != 0x01)
 				goto drop;
 
-#ifdef DEBUG
-			printf("Got packet for unknown service\n");
-#endif
+			log_message(NSPIRE_LOG_DEBUG, "Got message for unknown service");
 
 			NNSEMessage_UnkResp resp = {};
 			resp.hdr.service = message->service;
 			resp.noidea[0] = 0x81;
 			resp.noidea[1] = 0x03;
 
-			if(!sendMessage(handle, resp))
-				printf("Failed to send message\n");
+			if(!sendMessage(nsp_handle, resp))
+				log_message(NSPIRE_LOG_WARN, "Failed to send message");
+
+			break;
+		}
+		case EchoService:
+		{
+			log_message(NSPIRE_LOG_DEBUG, "Got echo request");
+
+			if(!sendEcho(nsp_handle, getPacketData(message),
+					ntohs(message->length) - sizeof(NNSEMessage), 0))
+				log_message(NSPIRE_LOG_WARN, "Failed to send message");
 
 			break;
 		}
@@ -363,13 +382,16 @@ static void handlePacket(struct nspire_handle *nsp_handle, NNSEMessage *message,
 			break;
 		}
 		default:
-			printf("Unhandled service %02x\n", message->service & ~AckFlag);
+			log_message(NSPIRE_LOG_WARN, "Unhandled service %02x",
+					message->service & ~AckFlag);
 	}
 
 	return;
 
 	drop:
-	printf("Ignoring packet.\n");
+	log_message(NSPIRE_LOG_WARN,
+			"Ignoring malformed message for service %02x",
+			message->service & ~AckFlag);
 }
 
 static bool assureReady(struct nspire_handle *nsp_handle)
@@ -377,13 +399,11 @@ static bool assureReady(struct nspire_handle *nsp_handle)
 	if(nsp_handle->cx2_handshake_complete)
 		return true;
 
-	auto *handle = nsp_handle->device.dev;
-
 	const int maxlen = sizeof(NNSEMessage) + 1472;
 	NNSEMessage * const message = reinterpret_cast<NNSEMessage*>(malloc(maxlen));
 	for(int i = 10; i-- && !nsp_handle->cx2_handshake_complete;)
 	{
-		if(!readPacket(handle, message, maxlen))
+		if(!readPacket(nsp_handle, message, maxlen))
 			continue;
 
 		handlePacket(nsp_handle, message);
@@ -393,12 +413,38 @@ static bool assureReady(struct nspire_handle *nsp_handle)
 	return nsp_handle->cx2_handshake_complete;
 }
 
+// Wait for the calculator to ack msg, handling whatever else it sends.
+static bool waitForAck(struct nspire_handle *nsp_handle, const NNSEMessage *msg)
+{
+	const int maxlen = sizeof(NNSEMessage) + 1472;
+	NNSEMessage * const message = reinterpret_cast<NNSEMessage*>(malloc(maxlen));
+	if(!message)
+		return false;
+
+	bool acked = false;
+	for(int i = 10; i-- && !acked;)
+	{
+		if(!readPacket(nsp_handle, message, maxlen))
+			continue;
+
+		handlePacket(nsp_handle, message);
+
+		if(message->dest == AddrMe
+			&& message->service == (msg->service | AckFlag)
+			&& message->seqno == msg->seqno)
+			acked = true;
+	}
+
+	free(message);
+
+	return acked;
+}
+
 int packet_send_cx2(struct nspire_handle *nsp_handle, char *data, int size)
 {
 	if(!assureReady(nsp_handle))
 		return -NSPIRE_ERR_BUSY;
 
-	auto *handle = nsp_handle->device.dev;
 
 	int len = sizeof(NNSEMessage) + size;
 	NNSEMessage *msg = reinterpret_cast<NNSEMessage*>(malloc(len));
@@ -408,37 +454,13 @@ int packet_send_cx2(struct nspire_handle *nsp_handle, char *data, int size)
 	msg->dest = AddrCalc;
 	msg->reqAck = 1;
 	msg->length = htons(len);
-	msg->seqno = htons(nextSeqno());
+	msg->seqno = htons(nextSeqno(nsp_handle));
 
 	memcpy(getPacketData(msg), data, size);
 
 	int ret = -NSPIRE_ERR_SUCCESS;
-	if(!writePacket(handle, msg))
+	if(!writePacket(nsp_handle, msg) || !waitForAck(nsp_handle, msg))
 		ret = -NSPIRE_ERR_BUSY;
-	else
-	{
-		const int maxlen = sizeof(NNSEMessage) + 1472;
-		NNSEMessage * const message = reinterpret_cast<NNSEMessage*>(malloc(maxlen));
-
-		bool acked = false;
-		for(int i = 10; i-- && !ret && !acked;)
-		{
-			if(!readPacket(handle, message, maxlen))
-				continue;
-
-			handlePacket(nsp_handle, message);
-
-			if(message->dest == AddrMe
-				&& message->service == (StreamService | AckFlag)
-				&& message->seqno == msg->seqno)
-				acked = true;
-		}
-
-		if(!acked)
-			ret = -NSPIRE_ERR_BUSY;
-
-		free(message);
-	}
 
 	free(msg);
 
@@ -450,25 +472,67 @@ int packet_recv_cx2(struct nspire_handle *nsp_handle, char *data, int size)
 	if(!assureReady(nsp_handle))
 		return -NSPIRE_ERR_BUSY;
 
-	auto *handle = nsp_handle->device.dev;
 
 	const int maxlen = sizeof(NNSEMessage) + 1472;
 	NNSEMessage * const message = reinterpret_cast<NNSEMessage*>(malloc(maxlen));
+	if(!message)
+		return -NSPIRE_ERR_NOMEM;
 
 	uint8_t *streamdata = nullptr;
 	int streamsize = 0;
 	for(int i = 10; i-- && !streamdata;)
 	{
-		if(!readPacket(handle, message, maxlen))
+		if(!readPacket(nsp_handle, message, maxlen))
 			continue;
 
 		handlePacket(nsp_handle, message, &streamdata, &streamsize);
 	}
 
+	int ret = -NSPIRE_ERR_INVALPKT;
 	if(streamdata)
-		memcpy(data, streamdata, std::min(size, streamsize));
+	{
+		ret = std::min(size, streamsize);
+		memcpy(data, streamdata, ret);
+	}
+
+	free(message);
+
+	return ret;
+}
+
+int packet_echo_cx2(struct nspire_handle *nsp_handle, const void *data, int size)
+{
+	if(!assureReady(nsp_handle))
+		return -NSPIRE_ERR_BUSY;
+
+	if(!sendEcho(nsp_handle, data, size, 1))
+		return -NSPIRE_ERR_BUSY;
+
+	const int maxlen = sizeof(NNSEMessage) + 1472;
+	NNSEMessage * const message = reinterpret_cast<NNSEMessage*>(malloc(maxlen));
+	if(!message)
+		return -NSPIRE_ERR_NOMEM;
+
+	bool echoed = false;
+	for(int i = 10; i-- && !echoed;)
+	{
+		if(!readPacket(nsp_handle, message, maxlen))
+			continue;
+
+		// The reply isn't a request of its own, don't echo it back
+		if(message->dest == AddrMe
+			&& message->service == EchoService
+			&& ntohs(message->length) == sizeof(NNSEMessage) + size
+			&& memcmp(getPacketData(message), data, size) == 0)
+		{
+			ackPacket(nsp_handle, message);
+			echoed = true;
+		}
+		else
+			handlePacket(nsp_handle, message);
+	}
 
 	free(message);
 
-	return streamdata ? -NSPIRE_ERR_SUCCESS : -NSPIRE_ERR_INVALPKT;
+	return echoed ? NSPIRE_ERR_SUCCESS : -NSPIRE_ERR_TIMEOUT;
 }
@@ -21,7 +21,6 @@
 #ifdef _WIN32
 #include <winsock2.h>
 #endif
-#include <libusb.h>
 
 #ifdef __cplusplus
 extern "C" {
@@ -31,10 +30,13 @@ struct nspire_handle;
 
 // Receive a NavNet packet wrapped in the NavNet SE protocol.
 // Takes care of the handshake and other NNSE stuff like acking.
+// Returns the size of the packet, or a negative error code.
 int packet_recv_cx2(struct nspire_handle *handle, char *data, int size);
 // Send a NavNet packet wrapped in the NavNet SE protocol and wait for an ack.
 // Takes care of the handshake and other NNSE stuff like acking.
 int packet_send_cx2(struct nspire_handle *handle, char *data, int size);
+// Send an NNSE echo request and wait for the calculator to send it back.
+int packet_echo_cx2(struct nspire_handle *handle, const void *data, int size);
 
 #ifdef __cplusplus
 }
@@ -22,6 +22,7 @@
 #include "error.h"
 #include "packet.h"
 #include "handle.h"
+#include "protocol.h"
 
 static int handle_unknown(nspire_handle_t *handle, struct packet p) {
 	int ret;
@@ -37,23 +38,23 @@ static int handle_unknown(nspire_handle_t *handle, struct packet p) {
 	return ret;
 }
 
-int data_write_special(nspire_handle_t *handle, void *ptr, size_t len,
-		void (*packet_callback)(struct packet *p)) {
-	int ret;
-	struct packet p = packet_new(handle);
-
+/* Make room for len bytes of data in p, returning where they go */
+static uint8_t *data_reserve(nspire_handle_t *handle, struct packet *p,
+		size_t len) {
 	if(len < 0xFF) {
-		memcpy(p.data, ptr, len);
-		p.data_size = len;
+		p->data_size = len;
+		return p->data;
 	} else if(len <= packet_max_datasize(handle)) {
-		memcpy(p.bigdata, ptr, len);
-		p.bigdatasize = dcpu32(len);
-		p.data_size = 0xFF;
+		p->bigdatasize = dcpu32(len);
+		p->data_size = 0xFF;
+		return p->bigdata;
 	} else
-		return NSPIRE_ERR_NOMEM;
+		return NULL;
+}
 
-	if (packet_callback)
-		packet_callback(&p);
+/* Send p and wait for the device to acknowledge it */
+static int data_send(nspire_handle_t *handle, struct packet p) {
+	int ret;
 
 	if ((ret = packet_send(handle, p)))
 		return ret;
@@ -74,12 +75,12 @@ int data_write_special(nspire_handle_t *handle, void *ptr, size_t len,
 			return ret;
 
 		if (p.dst_sid == handle->host_sid) {
-			if ((p.src_sid == 0xFF || p.src_sid == 0xFE)) {
+			if ((p.src_sid == NSPIRE_SID_ACK || p.src_sid == NSPIRE_SID_ACK_ZERO)) {
 				handle->seq++;
 				if (!handle->seq) handle->seq++;
 				return NSPIRE_ERR_SUCCESS;
 			} else
-			if (p.src_sid == 0xD3) {
+			if (p.src_sid == NSPIRE_SID_NACK) {
 				return -NSPIRE_ERR_INVALPKT;
 			}
 		} else {
@@ -88,6 +89,36 @@ int data_write_special(nspire_handle_t *handle, void *ptr, size_t len,
 	}
 }
 
+int data_write_special(nspire_handle_t *handle, void *ptr, size_t len,
+		void (*packet_callback)(struct packet *p)) {
+	struct packet p = packet_new(handle);
+	uint8_t *data = data_reserve(handle, &p, len);
+
+	if (!data)
+		return -NSPIRE_ERR_NOMEM;
+
+	memcpy(data, ptr, len);
+
+	if (packet_callback)
+		packet_callback(&p);
+
+	return data_send(handle, p);
+}
+
+int data_write_prefixed(nspire_handle_t *handle, uint8_t prefix,
+		const void *ptr, size_t len) {
+	struct packet p = packet_new(handle);
+	uint8_t *data = data_reserve(handle, &p, len + 1);
+
+	if (!data)
+		return -NSPIRE_ERR_NOMEM;
+
+	data[0] = prefix;
+	memcpy(data + 1, ptr, len);
+
+	return data_send(handle, p);
+}
+
 int data_write(nspire_handle_t *handle, void *ptr, size_t len) {
 	return data_write_special(handle, ptr, len, NULL);
 }
@@ -221,6 +252,10 @@ int data_scan(const char *format, const void *buffer, size_t len, ...) {
 			/* Strings are padded with zeros until len >= 8 */
 			/* Take this into account */
 			if (next - ptr < 8) {
+				if (len < 9) {
+					ret = -NSPIRE_ERR_INVALID;
+					goto end;
+				}
 				ptr = next + (8 - (next - ptr));
 			} else {
 				ptr = next;
@@ -256,6 +291,12 @@ int data_scan(const char *format, const void *buffer, size_t len, ...) {
 		format++;
 	}
 
+	/* The buffer ran out before the format did */
+	if (*format) {
+		ret = -NSPIRE_ERR_INVALID;
+		goto end;
+	}
+
 	ret = NSPIRE_ERR_SUCCESS;
 end:
 	va_end(ap);
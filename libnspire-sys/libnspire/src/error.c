@@ -31,7 +31,8 @@ static const char *error_msg[NSPIRE_ERR_MAX] = {
 	[NSPIRE_ERR_INVALID]	= "Invalid input",
 	[NSPIRE_ERR_EXISTS]	= "Already exists",
 	[NSPIRE_ERR_NONEXIST]	= "Path does not exist",
-
+	[NSPIRE_ERR_OSFAILED]	= "OS installation failed",
+	[NSPIRE_ERR_NOTSUPPORTED]	= "Not supported by this calculator",
 };
 
 static const char* unknown_err = "Unknown error";
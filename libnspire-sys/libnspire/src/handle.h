@@ -25,14 +25,33 @@
 #include "usb.h"
 
 struct nspire_handle {
+	struct nspire_transport transport;
+	/* Only used if opened with nspire_init */
 	usb_device_t device;
+	bool owns_device;
 
 	uint16_t host_addr, device_addr;
 	uint16_t host_sid, device_sid;
 	uint8_t seq, connected;
+	/* Whether the connected service stays connected between calls */
+	bool held;
 
 	bool is_cx2;
+	/* The most data sent in one packet */
+	uint32_t max_datasize;
 	bool cx2_handshake_complete;
+	uint16_t cx2_seqno;
+
+	nspire_tap tap;
+	void *tap_data;
+
+	struct nspire_opts opts;
 };
 
+static inline void handle_tap(struct nspire_handle *h,
+		enum nspire_tap_proto proto, bool sent, const void *buf, int len) {
+	if (h->tap)
+		h->tap(h->tap_data, proto, sent, buf, len);
+}
+
 #endif
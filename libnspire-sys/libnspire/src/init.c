@@ -21,44 +21,72 @@
 #include "packet.h"
 #include "error.h"
 #include "usb.h"
+#include "protocol.h"
 
-int nspire_init(nspire_handle_t **ptr, libusb_device_handle *dev, bool is_cx2) {
+static int nspire_connect(nspire_handle_t *h, bool is_cx2,
+		const struct nspire_opts *opts) {
 	int ret;
 	struct packet p;
-	nspire_handle_t *h = malloc(sizeof(*h));
-
-	if (!h)
-		return -NSPIRE_ERR_NOMEM;
-
-	if ( (ret = usb_init()) )
-		goto error;
 
 	h->is_cx2 = is_cx2;
-	if ( (ret = usb_get_device(&h->device, dev)) ) {
-		goto error;
-	}
-
-	h->host_addr = 0x6400;
-	h->device_addr = 0x6401;
-	h->host_sid = 0x4003;
-	h->device_sid = 0x4003;
+	h->max_datasize = is_cx2 ? 1440 : 254;
+	if (opts)
+		h->opts = *opts;
+	else
+		memset(&h->opts, 0, sizeof(h->opts));
+	h->tap = NULL;
+	h->tap_data = NULL;
+	h->host_addr = NSPIRE_ADDR_HOST;
+	h->device_addr = NSPIRE_ADDR_DEVICE;
+	h->host_sid = NSPIRE_SID_ADDR_ASSIGN;
+	h->device_sid = NSPIRE_SID_ADDR_ASSIGN;
 	h->connected = 0;
+	h->held = false;
 	h->seq = 1;
 	h->cx2_handshake_complete = false;
+	h->cx2_seqno = 0;
 
 	if (!h->is_cx2) {
 		// Wait for an address request
 		if ( (ret = packet_recv(h, NULL)) )
-			goto error_free_usb;
+			return ret;
 	}
 
 	p = packet_new(h);
 	packet_set_data(p, 0x64, 0x01, 0xFF, 0x00);
 	if ( (ret = packet_send(h, p)) )
-		goto error_free_usb;
+		return ret;
 
 	h->host_sid = 0x8000;
 
+	return NSPIRE_ERR_SUCCESS;
+}
+
+int nspire_init(nspire_handle_t **ptr, libusb_device_handle *dev, bool is_cx2) {
+	return nspire_init_opts(ptr, dev, is_cx2, NULL);
+}
+
+int nspire_init_opts(nspire_handle_t **ptr, libusb_device_handle *dev,
+		bool is_cx2, const struct nspire_opts *opts) {
+	int ret;
+	nspire_handle_t *h = malloc(sizeof(*h));
+
+	if (!h)
+		return -NSPIRE_ERR_NOMEM;
+
+	if ( (ret = usb_init()) )
+		goto error;
+
+	if ( (ret = usb_get_device(&h->device, dev)) ) {
+		goto error;
+	}
+
+	h->transport = usb_transport(&h->device);
+	h->owns_device = true;
+
+	if ( (ret = nspire_connect(h, is_cx2, opts)) )
+		goto error_free_usb;
+
 	*ptr = h;
 
 	return NSPIRE_ERR_SUCCESS;
@@ -70,7 +98,53 @@ error:
 	return ret;
 }
 
+int nspire_init_transport(nspire_handle_t **ptr,
+		const struct nspire_transport *transport, bool is_cx2) {
+	return nspire_init_transport_opts(ptr, transport, is_cx2, NULL);
+}
+
+int nspire_init_transport_opts(nspire_handle_t **ptr,
+		const struct nspire_transport *transport, bool is_cx2,
+		const struct nspire_opts *opts) {
+	int ret;
+	nspire_handle_t *h = malloc(sizeof(*h));
+
+	if (!h)
+		return -NSPIRE_ERR_NOMEM;
+
+	h->transport = *transport;
+	h->owns_device = false;
+
+	if ( (ret = nspire_connect(h, is_cx2, opts)) ) {
+		free(h);
+		return ret;
+	}
+
+	*ptr = h;
+
+	return NSPIRE_ERR_SUCCESS;
+}
+
+void nspire_set_tap(nspire_handle_t *ptr, nspire_tap tap, void *data) {
+	ptr->tap = tap;
+	ptr->tap_data = data;
+}
+
+int nspire_set_packet_size(nspire_handle_t *ptr, uint32_t size) {
+	if (size < 254 || size > 1440)
+		return -NSPIRE_ERR_INVALID;
+
+	ptr->max_datasize = size;
+
+	return NSPIRE_ERR_SUCCESS;
+}
+
+uint32_t nspire_packet_size(nspire_handle_t *ptr) {
+	return ptr->max_datasize;
+}
+
 void nspire_free(nspire_handle_t *ptr) {
-	usb_free_device(&ptr->device);
+	if (ptr->owns_device)
+		usb_free_device(&ptr->device);
 	free(ptr);
 }
@@ -0,0 +1,42 @@
+/*
+    This file is part of libnspire.
+
+    libnspire is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    libnspire is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with libnspire.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+#include <stdarg.h>
+#include <stdio.h>
+
+#include "log.h"
+
+static nspire_log log_callback = NULL;
+static void *log_data = NULL;
+
+void nspire_set_log(nspire_log log, void *data) {
+	log_callback = log;
+	log_data = data;
+}
+
+void log_message(enum nspire_log_level level, const char *format, ...) {
+	char message[256];
+	va_list args;
+
+	if (!log_callback)
+		return;
+
+	va_start(args, format);
+	vsnprintf(message, sizeof(message), format, args);
+	va_end(args);
+	log_callback(log_data, level, message);
+}
@@ -0,0 +1,34 @@
+/*
+    This file is part of libnspire.
+
+    libnspire is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    libnspire is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with libnspire.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+#ifndef _LOG_H
+#define _LOG_H
+
+#include "api/handle.h"
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Pass a diagnostic to the function set with nspire_set_log, if any */
+void log_message(enum nspire_log_level level, const char *format, ...);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
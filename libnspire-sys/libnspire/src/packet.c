@@ -22,11 +22,11 @@
 #include "handle.h"
 #include "packet.h"
 #include "error.h"
-#include "usb.h"
 #include "endianconv.h"
+#include "protocol.h"
 
-#define PACKET_CONSTANT 0x54FD
 #define HEADER_SIZE offsetof(struct packet, data)
+#define PACKET_TIMEOUT 10000
 
 static uint8_t calculate_header_checksum(uint8_t *data, uint8_t size) {
 	uint8_t chksum = 0;
@@ -60,7 +60,7 @@ static void fix_endian(struct packet *p) {
 }
 
 static void finalize_packet(struct packet *p) {
-	p->magic = PACKET_CONSTANT;
+	p->magic = NSPIRE_NAVNET_MAGIC;
 
 	/* Calculate checksums */
 	p->data_checksum = calculate_data_checksum(p->fulldata, packet_fulldatasize(p));
@@ -79,7 +79,7 @@ static int is_header_valid(struct packet *p) {
 		HEADER_SIZE - 1
 	);
 
-	if (p->magic != PACKET_CONSTANT)		return 0;
+	if (p->magic != NSPIRE_NAVNET_MAGIC)		return 0;
 	if (p->header_checksum != header_chksum)	return 0;
 	return 1;
 }
@@ -90,57 +90,24 @@ static int is_data_valid(struct packet *p) {
 	return 1;
 }
 
-#ifdef DEBUG
-#include <stdio.h>
-static void dump_packet(const struct packet *p) {
-	printf(
-		"magic		= %04x\n"
-		"src_addr	= %04x\n"
-		"src_sid	= %04x\n"
-		"dst_addr	= %04x\n"
-		"dst_sid	= %04x\n"
-		"data_chksm	= %04x\n"
-		"size		= %02x\n"
-		"ack		= %02x\n"
-		"seq		= %02x\n"
-		"hdr_chksm	= %02x\n"
-		"data		= ",
-		p->magic,
-		p->src_addr,
-		p->src_sid,
-		p->dst_addr,
-		p->dst_sid,
-		p->data_checksum,
-		p->data_size,
-		p->ack,
-		p->seq,
-		p->header_checksum);
-
-	int i;
-	for (i=0; i<(packet_fulldatasize(p)); i++) {
-		printf("%02x ", p->fulldata[i]);
-	}
-	printf("\n");
-}
-#endif
-
 int packet_send(nspire_handle_t *h, struct packet p) {
+	int ret;
 	int size = HEADER_SIZE + packet_fulldatasize(&p);
 
-#ifdef DEBUG
-	printf("\nOUT ===>\n");
-	dump_packet(&p);
-#endif
-
 	finalize_packet(&p);
+	handle_tap(h, NSPIRE_TAP_NAVNET, true, &p, size);
 	if(h->is_cx2)
 		return packet_send_cx2(h, (char*)&p, size);
-	else
-		return usb_write(&h->device, (char*)&p, size);
+
+	ret = h->transport.write(h->transport.data, &p, size, PACKET_TIMEOUT);
+	if (ret < 0)
+		return ret;
+	return size - ret;
 }
 
 int packet_recv(nspire_handle_t *h, struct packet *p) {
 	int ret;
+	uint32_t size;
 	struct packet unused;
 
 	/* if user passes in NULL, receive packet but ignore it */
@@ -150,20 +117,25 @@ int packet_recv(nspire_handle_t *h, struct packet *p) {
 	if(h->is_cx2)
 		ret = packet_recv_cx2(h, (char*)p, sizeof(*p));
 	else
-		ret = usb_read(&h->device, p, sizeof(*p));
+		ret = h->transport.read(h->transport.data, p, sizeof(*p),
+				PACKET_TIMEOUT);
 
 	if (ret < 0)
 		return ret;
 
+	/* bigdatasize is never byte-swapped, so this works before fix_endian */
+	size = HEADER_SIZE + packet_fulldatasize(p);
+	handle_tap(h, NSPIRE_TAP_NAVNET, false, p,
+			size < (uint32_t)ret ? size : (uint32_t)ret);
+
+	/* Don't trust the sizes in the header beyond what actually arrived */
+	if (ret < (int)HEADER_SIZE || size > (uint32_t)ret)
+		return -NSPIRE_ERR_INVALPKT;
+
 	fix_endian(p);
 	if (!is_header_valid(p) || !is_data_valid(p))
 		return -NSPIRE_ERR_INVALPKT;
 
-#ifdef DEBUG
-	printf("\nIN  <===\n");
-	dump_packet(p);
-#endif
-
 	return -NSPIRE_ERR_SUCCESS;
 }
 
@@ -182,7 +154,7 @@ struct packet packet_new(nspire_handle_t *h) {
 static struct packet packet_new_ack(nspire_handle_t *h, struct packet p) {
 	struct packet ack = packet_new(h);
 
-	ack.src_sid = p.seq ? 0xFF : 0xFE;
+	ack.src_sid = p.seq ? NSPIRE_SID_ACK : NSPIRE_SID_ACK_ZERO;
 	ack.dst_sid = p.src_sid;
 
 	ack.seq = p.seq;
@@ -202,7 +174,7 @@ int packet_ack(nspire_handle_t *h, struct packet p) {
 
 int packet_nack(nspire_handle_t *h, struct packet p) {
 	struct packet nack = packet_new_ack(h, p);
-	nack.src_sid = 0xD3;
+	nack.src_sid = NSPIRE_SID_NACK;
 
 	return packet_send(h, nack);
 }
@@ -216,7 +188,7 @@ uint32_t packet_datasize(const struct packet *p) {
 }
 
 uint32_t packet_max_datasize(nspire_handle_t *h) {
-	return h->is_cx2 ? 1440 : 254;
+	return h->max_datasize;
 }
 
 uint32_t packet_fulldatasize(const struct packet *p) {
@@ -18,10 +18,14 @@
 #include "error.h"
 #include "data.h"
 #include "packet.h"
+#include "protocol.h"
 
 int service_connect(nspire_handle_t *handle, uint16_t sid) {
-	if (handle->connected)
+	if (handle->connected) {
+		if (handle->held && handle->device_sid == sid)
+			return NSPIRE_ERR_SUCCESS;
 		return -NSPIRE_ERR_BUSY;
+	}
 
 	handle->connected = 1;
 	handle->device_sid = sid;
@@ -30,7 +34,7 @@ int service_connect(nspire_handle_t *handle, uint16_t sid) {
 }
 
 static void mod_src(struct packet *p) {
-	p->src_sid = 0x40DE;
+	p->src_sid = NSPIRE_SID_DISCONNECT;
 }
 
 int service_disconnect(nspire_handle_t *handle) {
@@ -40,7 +44,7 @@ int service_disconnect(nspire_handle_t *handle) {
 		(handle->host_sid>>8) & 0xFF,
 		(handle->host_sid>>0) & 0xFF };
 
-	if (!handle->connected)
+	if (!handle->connected || handle->held)
 		return NSPIRE_ERR_SUCCESS;
 
 	p = packet_new(handle);
@@ -53,3 +57,20 @@ int service_disconnect(nspire_handle_t *handle) {
 
 	return NSPIRE_ERR_SUCCESS;
 }
+
+int service_hold(nspire_handle_t *handle, uint16_t sid) {
+	int ret;
+
+	if ( (ret = service_connect(handle, sid)) )
+		return ret;
+
+	handle->held = true;
+
+	return NSPIRE_ERR_SUCCESS;
+}
+
+int service_release(nspire_handle_t *handle) {
+	handle->held = false;
+
+	return service_disconnect(handle);
+}
@@ -23,5 +23,8 @@
 
 int service_connect(nspire_handle_t *handle, uint16_t sid);
 int service_disconnect(nspire_handle_t *handle);
+/* Keep a service connected until service_release */
+int service_hold(nspire_handle_t *handle, uint16_t sid);
+int service_release(nspire_handle_t *handle);
 
 #endif
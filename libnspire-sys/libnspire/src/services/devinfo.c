@@ -21,24 +21,31 @@
 #include "error.h"
 #include "data.h"
 #include "service.h"
+#include "protocol.h"
 #include "endianconv.h"
 #include "devinfo.h"
 
 int nspire_device_info(nspire_handle_t *handle, struct nspire_devinfo *i) {
 	int ret;
+	size_t len;
 	uint8_t buffer[253];
 	char *devname, *file, *os;
 	struct deviceinfo_01 devinfo;
 
-	if ( (ret = service_connect(handle, 0x4020)) )
+	if ( (ret = service_connect(handle, NSPIRE_SID_DEVINFO)) )
 		return ret;
 
 	if ( (ret = data_write8(handle, 0x01)) )
 		goto end;
 
-	if ( (ret = data_read(handle, buffer, sizeof(buffer), NULL)) )
+	/* Zero whatever a short reply leaves out */
+	memset(buffer, 0, sizeof(buffer));
+	if ( (ret = data_read(handle, buffer, sizeof(buffer), &len)) )
 		goto end;
 
+	i->raw.len = len ? len - 1 : 0;
+	memcpy(i->raw.data, buffer + 1, i->raw.len);
+
 	/* Shift up one byte to align */
 	memmove(&devinfo, buffer+1, sizeof(devinfo));
 
@@ -69,6 +76,8 @@ int nspire_device_info(nspire_handle_t *handle, struct nspire_devinfo *i) {
 	i->batt.is_charging	= devinfo.is_charging;
 	i->clock_speed		= devinfo.clock_speed;
 
+	i->lcd_offset.x		= dcpu16(devinfo.lcd_x);
+	i->lcd_offset.y		= dcpu16(devinfo.lcd_y);
 	i->lcd.width		= dcpu16(devinfo.lcd_width);
 	i->lcd.height		= dcpu16(devinfo.lcd_height);
 	i->lcd.bbp		= devinfo.lcd_bbp;
@@ -78,15 +87,20 @@ int nspire_device_info(nspire_handle_t *handle, struct nspire_devinfo *i) {
 			sizeof(i->electronic_id) - 1);
 	i->electronic_id[sizeof(i->electronic_id) - 1] = '\0';
 
+	memcpy(i->short_id, devinfo.electronic_id,
+			sizeof(i->short_id) - 1);
+	i->short_id[sizeof(i->short_id) - 1] = '\0';
+
 	i->runlevel		= dcpu16(devinfo.run_level);
+	i->hw_version		= dcpu32(devinfo.h_version);
 
 	if ( (ret = data_write8(handle, 0x02)) )
 		goto end;
 
-	if ( (ret = data_read(handle, buffer, sizeof(buffer), NULL)) )
+	if ( (ret = data_read(handle, buffer, sizeof(buffer), &len)) )
 		goto end;
 
-	if ( (ret = data_scan("bS", buffer, sizeof(buffer),
+	if ( (ret = data_scan("bS", buffer, len,
 			NULL, &devname)) )
 		goto end;
 
@@ -96,10 +110,10 @@ int nspire_device_info(nspire_handle_t *handle, struct nspire_devinfo *i) {
 	if ( (ret = data_write8(handle, 0x03)) )
 		goto end;
 
-	if ( (ret = data_read(handle, buffer, sizeof(buffer), NULL)) )
+	if ( (ret = data_read(handle, buffer, sizeof(buffer), &len)) )
 		goto end;
 
-	if ( (ret = data_scan("bSS", buffer, sizeof(buffer),
+	if ( (ret = data_scan("bSS", buffer, len,
 			NULL, &file, &os)) )
 		goto end;
 
@@ -22,11 +22,13 @@
 #include "error.h"
 #include "data.h"
 #include "service.h"
+#include "protocol.h"
 #include "dir.h"
 
 static int dir_enum(nspire_handle_t *handle, struct nspire_dir_info **d) {
 	int ret;
 	char *name;
+	size_t len;
 	uint32_t size, date;
 	uint8_t is_dir;
 	struct nspire_dir_info *new_dir;
@@ -36,13 +38,13 @@ static int dir_enum(nspire_handle_t *handle, struct nspire_dir_info **d) {
 
 	if ( (ret = data_write8(handle, 0x0E)) )
 		return ret;
-	if ( (ret = data_read(handle, buffer, sizeof(buffer), NULL)) )
+	if ( (ret = data_read(handle, buffer, sizeof(buffer), &len)) )
 		return ret;
 
-	if (buffer[0] == 0xFF)
+	if (len && buffer[0] == 0xFF)
 		return 1;
 
-	if ( (ret = data_scan("hbswwb0", buffer, sizeof(buffer),
+	if ( (ret = data_scan("hbswwb0", buffer, len,
 			NULL, NULL, &name, &size, &date, &is_dir)) )
 		return ret;
 
@@ -58,7 +60,7 @@ static int dir_enum(nspire_handle_t *handle, struct nspire_dir_info **d) {
 	current->name[sizeof(current->name)-1] = '\0';
 	current->size = size;
 	current->date = date;
-	current->type = is_dir;
+	current->type = is_dir ? NSPIRE_DIR : NSPIRE_FILE;
 
 	return NSPIRE_ERR_SUCCESS;
 }
@@ -71,7 +73,7 @@ int nspire_dirlist(nspire_handle_t *handle, const char *path,
 	uint16_t result;
 	struct nspire_dir_info *d;
 
-	if ( (ret = service_connect(handle, 0x4060)) )
+	if ( (ret = service_connect(handle, NSPIRE_SID_FILE)) )
 		return ret;
 
 	/* Begin dir enum */
@@ -99,20 +101,18 @@ int nspire_dirlist(nspire_handle_t *handle, const char *path,
 	}
 
 	d = malloc(sizeof(struct nspire_dir_info));
-	(d)->num = 0;
 	if (!d) {
 		ret = -NSPIRE_ERR_NOMEM;
 		goto end;
 	}
+	(d)->num = 0;
 
 	*info_ptr = d;
 	/* Start enumerating */
 	while (1) {
 		ret = dir_enum(handle, info_ptr);
-		if (ret < 0) {
-			free(*info_ptr);
-			goto end;
-		}
+		if (ret < 0)
+			goto error_free;
 
 		if (ret)
 			break;
@@ -121,21 +121,24 @@ int nspire_dirlist(nspire_handle_t *handle, const char *path,
 	/* End dir enum */
 	if ( (ret = data_build("b", buffer, sizeof(buffer), &len,
 			0x0F)) )
-		goto end;
+		goto error_free;
 
 	if ( (ret = data_write(handle, buffer, len)) )
-		goto end;
+		goto error_free;
 
 	if ( (ret = data_read(handle, buffer, 2, NULL)) )
-		goto end;
+		goto error_free;
 
 	if ( (ret = data_scan("h", buffer, sizeof(buffer),
 			NULL, &result)) )
-		goto end;
+		goto error_free;
 
 	// TODO: result ignored here, what to do on failure anyway?
 
 	ret = NSPIRE_ERR_SUCCESS;
+	goto end;
+error_free:
+	free(*info_ptr);
 end:
 	service_disconnect(handle);
 	return ret;
@@ -151,7 +154,7 @@ int nspire_dir_create(nspire_handle_t *handle, const char *path) {
 	uint16_t result;
 	uint8_t buffer[254];
 
-	if ( (ret = service_connect(handle, 0x4060)) )
+	if ( (ret = service_connect(handle, NSPIRE_SID_FILE)) )
 		return ret;
 
 	if ( (ret = data_build("hs", buffer, sizeof(buffer), &len,
@@ -180,7 +183,7 @@ int nspire_dir_delete(nspire_handle_t *handle, const char *path) {
 	uint8_t buffer[254];
 
 
-	if ( (ret = service_connect(handle, 0x4060)) )
+	if ( (ret = service_connect(handle, NSPIRE_SID_FILE)) )
 		return ret;
 
 	if ( (ret = data_build("hs", buffer, sizeof(buffer), &len,
@@ -209,7 +212,7 @@ int nspire_attr(nspire_handle_t *handle, const char *path,
 	uint8_t is_dir, buffer[254];
 	uint32_t size, date;
 
-	if ( (ret = service_connect(handle, 0x4060)) )
+	if ( (ret = service_connect(handle, NSPIRE_SID_FILE)) )
 		return ret;
 
 	if ( (ret = data_build("hs0", buffer, sizeof(buffer), &len,
@@ -235,7 +238,7 @@ int nspire_attr(nspire_handle_t *handle, const char *path,
 	info->name[sizeof(info->name)-1] = '\0';
 	info->size = size;
 	info->date = date;
-	info->type = is_dir;
+	info->type = is_dir ? NSPIRE_DIR : NSPIRE_FILE;
 
 	ret = NSPIRE_ERR_SUCCESS;
 end:
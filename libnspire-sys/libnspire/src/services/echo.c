@@ -0,0 +1,57 @@
+/*
+    This file is part of libnspire.
+
+    libnspire is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    libnspire is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with libnspire.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+
+#include <string.h>
+
+#include "handle.h"
+#include "error.h"
+#include "data.h"
+#include "service.h"
+#include "protocol.h"
+#include "cx2.h"
+#include "echo.h"
+
+int nspire_ping(nspire_handle_t *handle, const void *payload, size_t size) {
+	int ret;
+	size_t len;
+	uint8_t buffer[1440];
+
+	if (size > packet_max_datasize(handle))
+		return -NSPIRE_ERR_INVALID;
+
+	if (handle->is_cx2)
+		return packet_echo_cx2(handle, payload, (int)size);
+
+	if ( (ret = service_connect(handle, NSPIRE_SID_ECHO)) )
+		return ret;
+
+	if ( (ret = data_write(handle, (void*)payload, size)) )
+		goto end;
+
+	if ( (ret = data_read(handle, buffer, sizeof(buffer), &len)) )
+		goto end;
+
+	if (len != size || memcmp(buffer, payload, size))
+		ret = -NSPIRE_ERR_INVALPKT;
+	else
+		ret = NSPIRE_ERR_SUCCESS;
+
+end:
+	service_disconnect(handle);
+	return ret;
+}
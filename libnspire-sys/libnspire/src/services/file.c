@@ -21,6 +21,7 @@
 #include "error.h"
 #include "data.h"
 #include "service.h"
+#include "protocol.h"
 
 typedef void (*nspire_callback)(size_t, void*);
 int nspire_file_write(nspire_handle_t *handle, const char *path,
@@ -30,7 +31,7 @@ int nspire_file_write(nspire_handle_t *handle, const char *path,
 	uint8_t buffer[sizeof(struct packet)], *ptr = data;
 	uint16_t result;
 
-	if ( (ret = service_connect(handle, 0x4060)) )
+	if ( (ret = service_connect(handle, NSPIRE_SID_FILE)) )
 		return ret;
 
 	if ( (ret = data_build("hsw", buffer, sizeof(buffer), &len,
@@ -50,12 +51,10 @@ int nspire_file_write(nspire_handle_t *handle, const char *path,
 
 	size_t datasize = packet_max_datasize(handle) - 1;
 
-	buffer[0] = 0x05;
 	while (size) {
 		len = (datasize < size) ? datasize : size;
 
-		memcpy(buffer + 1, ptr, len);
-		if ( (ret = data_write(handle, buffer, len+1)) )
+		if ( (ret = data_write_prefixed(handle, 0x05, ptr, len)) )
 			goto end;
 
 		size -= len;
@@ -84,7 +83,7 @@ int nspire_file_read(nspire_handle_t *handle, const char *path,
 	uint16_t result;
 	uint32_t data_len;
 
-	if ( (ret = service_connect(handle, 0x4060)) )
+	if ( (ret = service_connect(handle, NSPIRE_SID_FILE)) )
 		return ret;
 
 	if ( (ret = data_build("hs", buffer, packet_max_datasize(handle), &len,
@@ -94,10 +93,10 @@ int nspire_file_read(nspire_handle_t *handle, const char *path,
 	if ( (ret = data_write(handle, buffer, len)) )
 		goto end;
 
-	if ( (ret = data_read(handle, buffer, packet_max_datasize(handle), NULL)) )
+	if ( (ret = data_read(handle, buffer, packet_max_datasize(handle), &len)) )
 		goto end;
 
-	if ( (ret = data_scan("h000000000w", buffer, packet_max_datasize(handle),
+	if ( (ret = data_scan("h000000000w", buffer, len,
 			&result, &data_len)) )
 		goto end;
 
@@ -111,25 +110,26 @@ int nspire_file_read(nspire_handle_t *handle, const char *path,
 
 	if (total_bytes) *total_bytes = 0;
 
-	size_t maxsize = packet_max_datasize(handle) - 1;
-
 	while (data_len) {
-		len = (maxsize < data_len) ? maxsize : data_len;
-
-		if ( (ret = data_read(handle, buffer, len+1, &len)) )
+		/* The device picks the chunk size, which may be bigger than ours */
+		if ( (ret = data_read(handle, buffer, sizeof(buffer), &len)) )
 			goto end;
 
-		size_t to_copy = len - 1;
-		memcpy(ptr, buffer + 1, (size < to_copy) ? size : to_copy);
-		if (total_bytes) *total_bytes += (size < to_copy) ? size : to_copy;
-		size -= (size < to_copy) ? size : to_copy;
-		if(!size) {
+		/* Every chunk starts with a byte we skip */
+		if (!len || len - 1 > data_len) {
+			ret = -NSPIRE_ERR_INVALPKT;
 			goto end;
 		}
 
+		/* Whatever doesn't fit is read anyway and dropped, so that the
+		 * transfer ends cleanly and the service can be used again */
+		size_t to_copy = (size < len - 1) ? size : len - 1;
+		memcpy(ptr, buffer + 1, to_copy);
+		if (total_bytes) *total_bytes += to_copy;
+		size -= to_copy;
+		data_len -= len - 1;
 		ptr += to_copy;
 
-		data_len -= len - 1;
 		cb(size, cb_data);
 	}
 
@@ -150,7 +150,7 @@ int nspire_file_move(nspire_handle_t *handle,
 	uint8_t buffer[254];
 
 
-	if ( (ret = service_connect(handle, 0x4060)) )
+	if ( (ret = service_connect(handle, NSPIRE_SID_FILE)) )
 		return ret;
 
 	if ( (ret = data_build("hss0", buffer, sizeof(buffer), &len,
@@ -180,7 +180,7 @@ int nspire_file_copy(nspire_handle_t *handle,
 	uint8_t buffer[254];
 
 
-	if ( (ret = service_connect(handle, 0x4060)) )
+	if ( (ret = service_connect(handle, NSPIRE_SID_FILE)) )
 		return ret;
 
 	if ( (ret = data_build("hss0", buffer, sizeof(buffer), &len,
@@ -209,7 +209,7 @@ int nspire_file_delete(nspire_handle_t *handle, const char *path) {
 	uint8_t buffer[254];
 
 
-	if ( (ret = service_connect(handle, 0x4060)) )
+	if ( (ret = service_connect(handle, NSPIRE_SID_FILE)) )
 		return ret;
 
 	if ( (ret = data_build("hs0", buffer, sizeof(buffer), &len,
@@ -232,3 +232,11 @@ end:
 }
 
 
+
+int nspire_file_session_begin(nspire_handle_t *handle) {
+	return service_hold(handle, NSPIRE_SID_FILE);
+}
+
+int nspire_file_session_end(nspire_handle_t *handle) {
+	return service_release(handle);
+}
@@ -0,0 +1,64 @@
+/*
+    This file is part of libnspire.
+
+    libnspire is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    libnspire is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with libnspire.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+#include <string.h>
+
+#include "handle.h"
+#include "error.h"
+#include "data.h"
+#include "service.h"
+#include "protocol.h"
+#include "keys.h"
+
+int nspire_send_keys(nspire_handle_t *handle, const uint16_t *keys,
+		size_t count) {
+	int ret;
+	size_t i;
+	uint8_t buffer[26];
+
+	if ( (ret = service_connect(handle, NSPIRE_SID_KEYS)) )
+		return ret;
+
+	/*
+		The layout of these packets hasn't been checked against a capture of
+		TI's software: the session is started with 0x80 at [3] of a 4-byte
+		packet, then each key is sent in its own 26-byte packet with 0x80 at
+		[4] and the key code in the last two bytes.
+	*/
+
+	/* Start a keypress session */
+	memset(buffer, 0, sizeof(buffer));
+	buffer[0] = 0x01;
+	buffer[3] = 0x80;
+	if ( (ret = data_write(handle, buffer, 4)) )
+		goto end;
+
+	for (i = 0; i < count; i++) {
+		memset(buffer, 0, sizeof(buffer));
+		buffer[0] = 0x01;
+		buffer[4] = 0x80;
+		buffer[24] = keys[i] >> 8;
+		buffer[25] = keys[i] & 0xFF;
+		if ( (ret = data_write(handle, buffer, sizeof(buffer))) )
+			goto end;
+	}
+
+	ret = NSPIRE_ERR_SUCCESS;
+end:
+	service_disconnect(handle);
+	return ret;
+}
@@ -0,0 +1,63 @@
+/*
+    This file is part of libnspire.
+
+    libnspire is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    libnspire is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with libnspire.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+
+#include <string.h>
+
+#include "handle.h"
+#include "error.h"
+#include "data.h"
+#include "service.h"
+#include "protocol.h"
+#include "login.h"
+
+int nspire_login(nspire_handle_t *handle, const char *name) {
+	int ret;
+	size_t len = strlen(name);
+	uint8_t buffer[54];
+	uint16_t result;
+
+	if (len > 52)
+		return -NSPIRE_ERR_INVALID;
+
+	/* How a CX II is told the host's name isn't known */
+	if (handle->is_cx2)
+		return -NSPIRE_ERR_NOTSUPPORTED;
+
+	if ( (ret = service_connect(handle, NSPIRE_SID_LOGIN)) )
+		return ret;
+
+	buffer[0] = 0x01;
+	memcpy(buffer + 1, name, len + 1);
+	if ( (ret = data_write(handle, buffer, len + 2)) )
+		goto end;
+
+	/*
+		Assumed to be a status like the file service's, which hasn't been
+		checked against a capture
+	*/
+	if ( (ret = data_read(handle, buffer, 2, NULL)) )
+		goto end;
+
+	if ( (ret = data_scan("h", buffer, sizeof(buffer), &result)) )
+		goto end;
+
+	ret = (result == 0xFF00) ? NSPIRE_ERR_SUCCESS : -NSPIRE_ERR_INVALID;
+end:
+	service_disconnect(handle);
+	return ret;
+}
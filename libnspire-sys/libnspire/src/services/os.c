@@ -22,14 +22,18 @@
 #include "error.h"
 #include "data.h"
 #include "service.h"
+#include "protocol.h"
+#include "os.h"
 
-typedef void (*nspire_callback)(size_t, void*);
-int nspire_os_send(nspire_handle_t *handle, void* data, size_t size, nspire_callback cb, void *cb_data) {
+int nspire_os_install(nspire_handle_t *handle, void* data, size_t size,
+		nspire_os_callback cb, void *cb_data, uint8_t *status) {
 	int ret;
 	size_t len;
 	uint8_t buffer[sizeof(struct packet)], *ptr = data;
 
-	if ( (ret = service_connect(handle, 0x4080)) )
+	if (status) *status = 0;
+
+	if ( (ret = service_connect(handle, NSPIRE_SID_OS)) )
 		return ret;
 
 	if ( (ret = data_build("bw", buffer, sizeof(buffer), &len,
@@ -43,17 +47,16 @@ int nspire_os_send(nspire_handle_t *handle, void* data, size_t size, nspire_call
 		goto end;
 
 	if (buffer[0] != 0x04) {
+		if (status) *status = buffer[0];
 		ret = -NSPIRE_ERR_OSFAILED;
 		goto end;
 	}
 
 	size_t datasize = packet_max_datasize(handle) - 1;
-	buffer[0] = 0x05;
 	while (size) {
 		len = (datasize < size) ? datasize : size;
 
-		memcpy(buffer + 1, ptr, len);
-		if ( (ret = data_write(handle, buffer, len+1)) )
+		if ( (ret = data_write_prefixed(handle, 0x05, ptr, len)) )
 			goto end;
 
 		if (ptr == data) {
@@ -63,6 +66,7 @@ int nspire_os_send(nspire_handle_t *handle, void* data, size_t size, nspire_call
 				goto end;
 
 			if (dcpu16(code) != 0xFF00 && dcpu16(code) != 0x0400) {
+				if (status) *status = dcpu16(code) & 0xFF;
 				ret = -NSPIRE_ERR_OSFAILED;
 				goto end;
 			}
@@ -70,7 +74,7 @@ int nspire_os_send(nspire_handle_t *handle, void* data, size_t size, nspire_call
 
 		size -= len;
 		ptr += len;
-		cb(size, cb_data);
+		cb(NSPIRE_OS_UPLOAD, size, cb_data);
 	}
 
 	while (1) {
@@ -78,10 +82,13 @@ int nspire_os_send(nspire_handle_t *handle, void* data, size_t size, nspire_call
 			goto end;
 
 		if (buffer[0] == 0xFF) {
+			if (status) *status = buffer[1];
 			ret = -NSPIRE_ERR_OSFAILED;
 			goto end;
 		}
 
+		cb(NSPIRE_OS_INSTALL, buffer[1], cb_data);
+
 		// Yes, over 100% is actually possible...
 		if (buffer[1] >= 100)
 			break;
@@ -92,3 +99,21 @@ end:
 	service_disconnect(handle);
 	return ret;
 }
+
+struct os_send_cb {
+	nspire_callback cb;
+	void *cb_data;
+};
+
+static void os_send_progress(enum nspire_os_phase phase, size_t value, void *data) {
+	struct os_send_cb *wrapped = data;
+
+	if (phase == NSPIRE_OS_UPLOAD)
+		wrapped->cb(value, wrapped->cb_data);
+}
+
+int nspire_os_send(nspire_handle_t *handle, void* data, size_t size, nspire_callback cb, void *cb_data) {
+	struct os_send_cb wrapped = { cb, cb_data };
+
+	return nspire_os_install(handle, data, size, os_send_progress, &wrapped, NULL);
+}
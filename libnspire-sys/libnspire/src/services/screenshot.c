@@ -22,6 +22,7 @@
 #include "error.h"
 #include "data.h"
 #include "service.h"
+#include "protocol.h"
 #include "screenshot.h"
 
 struct rle {
@@ -39,7 +40,7 @@ static void rle_decode(const void *in, void *_out,
 		if (ptr->len < 0) {
 			len = -(ptr->len) + 1;
 			len = len < out_size ? len : out_size;
-			len = len < in_size ? len : in_size;
+			len = len < in_size - 1 ? len : in_size - 1;
 
 			memcpy(out, &ptr->byte, len);
 
@@ -57,38 +58,63 @@ static void rle_decode(const void *in, void *_out,
 		out_size -= len;
 		out += len;
 	}
+
+	/* Blank out anything the data didn't cover */
+	memset(out, 0, out_size);
 }
 
 int nspire_screenshot(nspire_handle_t *handle, struct nspire_image **ptr) {
 	int ret;
 	size_t len, in_len, out_len;
-	uint8_t buffer[254], bbp, *tmp = NULL, *tmp_ptr = NULL;
+	uint8_t buffer[254], bbp, *tmp = NULL, *tmp_ptr;
 	uint16_t width, height;
 	uint32_t size;
 	struct nspire_image *i;
 
 
-	if ( (ret = service_connect(handle, 0x4024)) )
+	if ( (ret = service_connect(handle, NSPIRE_SID_SCREENSHOT)) )
 		return ret;
 
 	if ( (ret = data_write8(handle, 0x00)) )
 		return ret;
 
-	if ( (ret = data_read(handle, buffer, sizeof(buffer), NULL)) )
+	if ( (ret = data_read(handle, buffer, sizeof(buffer), &len)) )
 		goto end;
 
-	if ( (ret = data_scan("bwhhhhbb", buffer, sizeof(buffer),
+	if ( (ret = data_scan("bwhhhhbb", buffer, len,
 			NULL, &size, NULL, NULL, &width, &height, &bbp, NULL)) )
 		goto end;
 
-	tmp_ptr = tmp = malloc(size);
-	if (!tmp) {
-		ret = -NSPIRE_ERR_NOMEM;
+	in_len = 0;
+	out_len = ((size_t)width * height * bbp) / 8;
+
+	/* Every two bytes of data decode to at most 128 bytes */
+	if (out_len / 64 > size) {
+		ret = -NSPIRE_ERR_INVALID;
 		goto end;
 	}
 
-	in_len = size;
-	out_len = (width * height * bbp) / 8;
+	/* Grow the buffer as data arrives, rather than trusting size */
+	while (size) {
+		if ( (ret = data_read(handle, buffer, sizeof(buffer), &len)) )
+			goto end;
+
+		if (len < 2) {
+			ret = -NSPIRE_ERR_INVALPKT;
+			goto end;
+		}
+
+		len = len - 1 < size ? len - 1 : size;
+		tmp_ptr = realloc(tmp, in_len + len);
+		if (!tmp_ptr) {
+			ret = -NSPIRE_ERR_NOMEM;
+			goto end;
+		}
+		tmp = tmp_ptr;
+		memcpy(tmp + in_len, buffer + 1, len);
+		in_len += len;
+		size -= len;
+	}
 
 	i = malloc(sizeof(*i) + out_len);
 	if (!i) {
@@ -100,22 +126,9 @@ int nspire_screenshot(nspire_handle_t *handle, struct nspire_image **ptr) {
 	i->height = height;
 	i->bbp = bbp;
 
-	while (size) {
-		if ( (ret = data_read(handle, buffer, sizeof(buffer), NULL)) )
-			goto error_free;
-
-		len = 253 < size ? 253 : size;
-		memcpy(tmp_ptr, buffer + 1, len);
-		tmp_ptr += len;
-		size -= len;
-	}
-
 	rle_decode(tmp, i->data, in_len, out_len);
 	*ptr = i;
 	ret = NSPIRE_ERR_SUCCESS;
-	goto end;
-error_free:
-	if (i) free(i);
 end:
 	if (tmp) free(tmp);
 	service_disconnect(handle);
@@ -22,7 +22,6 @@
 
 #define NSP_DEFAULT_CONFIG 1
 #define NSP_DEFAULT_IFACE 0
-#define NSP_TIMEOUT 10000
 
 static libusb_context * usb_ctx = NULL;
 
@@ -93,28 +92,36 @@ void usb_free_device(usb_device_t *handle) {
 }
 
 static inline int usb_xfer(libusb_device_handle *handle, unsigned char ep,
-		void *ptr, int len) {
+		void *ptr, int len, unsigned int timeout) {
 	int ret, transferred = 0;
 	ret = libusb_bulk_transfer(handle,
 		ep,
 		ptr,
 		len,
 		&transferred,
-		NSP_TIMEOUT);
+		timeout);
 
 	switch (ret) {
-	case 0:				return (len - transferred);
+	case 0:				return transferred;
 	case LIBUSB_ERROR_NO_DEVICE:	return -NSPIRE_ERR_NODEVICE;
 	case LIBUSB_ERROR_TIMEOUT:	return -NSPIRE_ERR_TIMEOUT;
 	default:			return -NSPIRE_ERR_LIBUSB;
 	}
 }
 
-int usb_write(usb_device_t *handle, void *ptr, int len) {
-	return usb_xfer(handle->dev, handle->ep_out, ptr, len);
+static int usb_write(void *data, const void *ptr, int len,
+		unsigned int timeout) {
+	usb_device_t *handle = data;
+	return usb_xfer(handle->dev, handle->ep_out, (void*)ptr, len, timeout);
 }
 
-int usb_read(usb_device_t *handle, void *ptr, int len) {
-	return usb_xfer(handle->dev, handle->ep_in, ptr, len);
+static int usb_read(void *data, void *ptr, int len, unsigned int timeout) {
+	usb_device_t *handle = data;
+	return usb_xfer(handle->dev, handle->ep_in, ptr, len, timeout);
+}
+
+struct nspire_transport usb_transport(usb_device_t *handle) {
+	struct nspire_transport transport = { handle, usb_read, usb_write };
+	return transport;
 }
 
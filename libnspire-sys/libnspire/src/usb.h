@@ -19,6 +19,7 @@
 #define _USB_H
 
 #include "endianconv.h"
+#include "api/handle.h"
 #include <libusb.h>
 
 #define NSP_VID 0x0451
@@ -34,7 +35,6 @@ int usb_init();
 void usb_finish();
 int usb_get_device(usb_device_t *handle, libusb_device_handle *dev);
 void usb_free_device(usb_device_t *handle);
-int usb_write(usb_device_t *handle, void *ptr, int len);
-int usb_read(usb_device_t *handle, void *ptr, int len);
+struct nspire_transport usb_transport(usb_device_t *handle);
 
 #endif
@@ -47,6 +47,136 @@ pub type nspire_handle_t = nspire_handle;
 pub struct libusb_device_handle {
     _unused: [u8; 0],
 }
+pub type nspire_transport_read = ::std::option::Option<
+    unsafe extern "C" fn(
+        data: *mut ::std::os::raw::c_void,
+        buf: *mut ::std::os::raw::c_void,
+        len: ::std::os::raw::c_int,
+        timeout: ::std::os::raw::c_uint,
+    ) -> ::std::os::raw::c_int,
+>;
+pub type nspire_transport_write = ::std::option::Option<
+    unsafe extern "C" fn(
+        data: *mut ::std::os::raw::c_void,
+        buf: *const ::std::os::raw::c_void,
+        len: ::std::os::raw::c_int,
+        timeout: ::std::os::raw::c_uint,
+    ) -> ::std::os::raw::c_int,
+>;
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct nspire_transport {
+    pub data: *mut ::std::os::raw::c_void,
+    pub read: nspire_transport_read,
+    pub write: nspire_transport_write,
+}
+#[test]
+fn bindgen_test_layout_nspire_transport() {
+    assert_eq!(
+        ::std::mem::size_of::<nspire_transport>(),
+        24usize,
+        concat!("Size of: ", stringify!(nspire_transport))
+    );
+    assert_eq!(
+        ::std::mem::align_of::<nspire_transport>(),
+        8usize,
+        concat!("Alignment of ", stringify!(nspire_transport))
+    );
+    assert_eq!(
+        ::std::mem::offset_of!(nspire_transport, data),
+        0usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(nspire_transport),
+            "::",
+            stringify!(data)
+        )
+    );
+    assert_eq!(
+        ::std::mem::offset_of!(nspire_transport, read),
+        8usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(nspire_transport),
+            "::",
+            stringify!(read)
+        )
+    );
+    assert_eq!(
+        ::std::mem::offset_of!(nspire_transport, write),
+        16usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(nspire_transport),
+            "::",
+            stringify!(write)
+        )
+    );
+}
+pub const nspire_tap_proto_NSPIRE_TAP_NAVNET: nspire_tap_proto = 0;
+pub const nspire_tap_proto_NSPIRE_TAP_NNSE: nspire_tap_proto = 1;
+pub type nspire_tap_proto = ::std::os::raw::c_uint;
+pub type nspire_tap = ::std::option::Option<
+    unsafe extern "C" fn(
+        data: *mut ::std::os::raw::c_void,
+        proto: nspire_tap_proto,
+        sent: bool,
+        buf: *const ::std::os::raw::c_void,
+        len: ::std::os::raw::c_int,
+    ),
+>;
+pub const nspire_log_level_NSPIRE_LOG_DEBUG: nspire_log_level = 0;
+pub const nspire_log_level_NSPIRE_LOG_WARN: nspire_log_level = 1;
+pub type nspire_log_level = ::std::os::raw::c_uint;
+pub type nspire_log = ::std::option::Option<
+    unsafe extern "C" fn(
+        data: *mut ::std::os::raw::c_void,
+        level: nspire_log_level,
+        message: *const ::std::os::raw::c_char,
+    ),
+>;
+pub type nspire_clock = ::std::option::Option<
+    unsafe extern "C" fn(data: *mut ::std::os::raw::c_void, sec: *mut u32, frac: *mut u64) -> bool,
+>;
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct nspire_opts {
+    pub clock: nspire_clock,
+    pub clock_data: *mut ::std::os::raw::c_void,
+}
+#[test]
+fn bindgen_test_layout_nspire_opts() {
+    assert_eq!(
+        ::std::mem::size_of::<nspire_opts>(),
+        16usize,
+        concat!("Size of: ", stringify!(nspire_opts))
+    );
+    assert_eq!(
+        ::std::mem::align_of::<nspire_opts>(),
+        8usize,
+        concat!("Alignment of ", stringify!(nspire_opts))
+    );
+    assert_eq!(
+        ::std::mem::offset_of!(nspire_opts, clock),
+        0usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(nspire_opts),
+            "::",
+            stringify!(clock)
+        )
+    );
+    assert_eq!(
+        ::std::mem::offset_of!(nspire_opts, clock_data),
+        8usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(nspire_opts),
+            "::",
+            stringify!(clock_data)
+        )
+    );
+}
 extern "C" {
     pub fn nspire_init(
         ptr: *mut *mut nspire_handle_t,
@@ -54,9 +184,48 @@ extern "C" {
         is_cx2: bool,
     ) -> ::std::os::raw::c_int;
 }
+extern "C" {
+    pub fn nspire_init_transport(
+        ptr: *mut *mut nspire_handle_t,
+        transport: *const nspire_transport,
+        is_cx2: bool,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn nspire_init_opts(
+        ptr: *mut *mut nspire_handle_t,
+        dev: *mut libusb_device_handle,
+        is_cx2: bool,
+        opts: *const nspire_opts,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn nspire_init_transport_opts(
+        ptr: *mut *mut nspire_handle_t,
+        transport: *const nspire_transport,
+        is_cx2: bool,
+        opts: *const nspire_opts,
+    ) -> ::std::os::raw::c_int;
+}
 extern "C" {
     pub fn nspire_free(ptr: *mut nspire_handle_t);
 }
+extern "C" {
+    pub fn nspire_set_tap(
+        ptr: *mut nspire_handle_t,
+        tap: nspire_tap,
+        data: *mut ::std::os::raw::c_void,
+    );
+}
+extern "C" {
+    pub fn nspire_set_log(log: nspire_log, data: *mut ::std::os::raw::c_void);
+}
+extern "C" {
+    pub fn nspire_set_packet_size(ptr: *mut nspire_handle_t, size: u32) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn nspire_packet_size(ptr: *mut nspire_handle_t) -> u32;
+}
 pub const nspire_battery_NSPIRE_BATT_POWERED: nspire_battery = 0;
 pub const nspire_battery_NSPIRE_BATT_LOW: nspire_battery = 241;
 pub const nspire_battery_NSPIRE_BATT_OK: nspire_battery = 127;
@@ -89,6 +258,10 @@ pub struct nspire_devinfo {
     pub device_name: [::std::os::raw::c_char; 20usize],
     pub electronic_id: [::std::os::raw::c_char; 28usize],
     pub runlevel: nspire_runlevel,
+    pub hw_version: u32,
+    pub lcd_offset: nspire_devinfo__bindgen_ty_7,
+    pub short_id: [::std::os::raw::c_char; 18usize],
+    pub raw: nspire_devinfo__bindgen_ty_8,
 }
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
@@ -109,9 +282,7 @@ fn bindgen_test_layout_nspire_devinfo__bindgen_ty_1() {
         concat!("Alignment of ", stringify!(nspire_devinfo__bindgen_ty_1))
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<nspire_devinfo__bindgen_ty_1>())).free as *const _ as usize
-        },
+        ::std::mem::offset_of!(nspire_devinfo__bindgen_ty_1, free),
         0usize,
         concat!(
             "Offset of field: ",
@@ -121,9 +292,7 @@ fn bindgen_test_layout_nspire_devinfo__bindgen_ty_1() {
         )
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<nspire_devinfo__bindgen_ty_1>())).total as *const _ as usize
-        },
+        ::std::mem::offset_of!(nspire_devinfo__bindgen_ty_1, total),
         8usize,
         concat!(
             "Offset of field: ",
@@ -152,9 +321,7 @@ fn bindgen_test_layout_nspire_devinfo__bindgen_ty_2() {
         concat!("Alignment of ", stringify!(nspire_devinfo__bindgen_ty_2))
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<nspire_devinfo__bindgen_ty_2>())).free as *const _ as usize
-        },
+        ::std::mem::offset_of!(nspire_devinfo__bindgen_ty_2, free),
         0usize,
         concat!(
             "Offset of field: ",
@@ -164,9 +331,7 @@ fn bindgen_test_layout_nspire_devinfo__bindgen_ty_2() {
         )
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<nspire_devinfo__bindgen_ty_2>())).total as *const _ as usize
-        },
+        ::std::mem::offset_of!(nspire_devinfo__bindgen_ty_2, total),
         8usize,
         concat!(
             "Offset of field: ",
@@ -196,9 +361,7 @@ fn bindgen_test_layout_nspire_devinfo__bindgen_ty_3() {
         concat!("Alignment of ", stringify!(nspire_devinfo__bindgen_ty_3))
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<nspire_devinfo__bindgen_ty_3>())).major as *const _ as usize
-        },
+        ::std::mem::offset_of!(nspire_devinfo__bindgen_ty_3, major),
         0usize,
         concat!(
             "Offset of field: ",
@@ -208,9 +371,7 @@ fn bindgen_test_layout_nspire_devinfo__bindgen_ty_3() {
         )
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<nspire_devinfo__bindgen_ty_3>())).minor as *const _ as usize
-        },
+        ::std::mem::offset_of!(nspire_devinfo__bindgen_ty_3, minor),
         1usize,
         concat!(
             "Offset of field: ",
@@ -220,9 +381,7 @@ fn bindgen_test_layout_nspire_devinfo__bindgen_ty_3() {
         )
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<nspire_devinfo__bindgen_ty_3>())).build as *const _ as usize
-        },
+        ::std::mem::offset_of!(nspire_devinfo__bindgen_ty_3, build),
         2usize,
         concat!(
             "Offset of field: ",
@@ -251,9 +410,7 @@ fn bindgen_test_layout_nspire_devinfo__bindgen_ty_4() {
         concat!("Alignment of ", stringify!(nspire_devinfo__bindgen_ty_4))
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<nspire_devinfo__bindgen_ty_4>())).status as *const _ as usize
-        },
+        ::std::mem::offset_of!(nspire_devinfo__bindgen_ty_4, status),
         0usize,
         concat!(
             "Offset of field: ",
@@ -263,10 +420,7 @@ fn bindgen_test_layout_nspire_devinfo__bindgen_ty_4() {
         )
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<nspire_devinfo__bindgen_ty_4>())).is_charging as *const _
-                as usize
-        },
+        ::std::mem::offset_of!(nspire_devinfo__bindgen_ty_4, is_charging),
         4usize,
         concat!(
             "Offset of field: ",
@@ -297,9 +451,7 @@ fn bindgen_test_layout_nspire_devinfo__bindgen_ty_5() {
         concat!("Alignment of ", stringify!(nspire_devinfo__bindgen_ty_5))
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<nspire_devinfo__bindgen_ty_5>())).width as *const _ as usize
-        },
+        ::std::mem::offset_of!(nspire_devinfo__bindgen_ty_5, width),
         0usize,
         concat!(
             "Offset of field: ",
@@ -309,9 +461,7 @@ fn bindgen_test_layout_nspire_devinfo__bindgen_ty_5() {
         )
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<nspire_devinfo__bindgen_ty_5>())).height as *const _ as usize
-        },
+        ::std::mem::offset_of!(nspire_devinfo__bindgen_ty_5, height),
         2usize,
         concat!(
             "Offset of field: ",
@@ -321,9 +471,7 @@ fn bindgen_test_layout_nspire_devinfo__bindgen_ty_5() {
         )
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<nspire_devinfo__bindgen_ty_5>())).bbp as *const _ as usize
-        },
+        ::std::mem::offset_of!(nspire_devinfo__bindgen_ty_5, bbp),
         4usize,
         concat!(
             "Offset of field: ",
@@ -333,10 +481,7 @@ fn bindgen_test_layout_nspire_devinfo__bindgen_ty_5() {
         )
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<nspire_devinfo__bindgen_ty_5>())).sample_mode as *const _
-                as usize
-        },
+        ::std::mem::offset_of!(nspire_devinfo__bindgen_ty_5, sample_mode),
         5usize,
         concat!(
             "Offset of field: ",
@@ -365,9 +510,7 @@ fn bindgen_test_layout_nspire_devinfo__bindgen_ty_6() {
         concat!("Alignment of ", stringify!(nspire_devinfo__bindgen_ty_6))
     );
     assert_eq!(
-        unsafe {
-            &(*(::std::ptr::null::<nspire_devinfo__bindgen_ty_6>())).file as *const _ as usize
-        },
+        ::std::mem::offset_of!(nspire_devinfo__bindgen_ty_6, file),
         0usize,
         concat!(
             "Offset of field: ",
@@ -377,7 +520,7 @@ fn bindgen_test_layout_nspire_devinfo__bindgen_ty_6() {
         )
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<nspire_devinfo__bindgen_ty_6>())).os as *const _ as usize },
+        ::std::mem::offset_of!(nspire_devinfo__bindgen_ty_6, os),
         8usize,
         concat!(
             "Offset of field: ",
@@ -387,11 +530,89 @@ fn bindgen_test_layout_nspire_devinfo__bindgen_ty_6() {
         )
     );
 }
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct nspire_devinfo__bindgen_ty_7 {
+    pub x: u16,
+    pub y: u16,
+}
+#[test]
+fn bindgen_test_layout_nspire_devinfo__bindgen_ty_7() {
+    assert_eq!(
+        ::std::mem::size_of::<nspire_devinfo__bindgen_ty_7>(),
+        4usize,
+        concat!("Size of: ", stringify!(nspire_devinfo__bindgen_ty_7))
+    );
+    assert_eq!(
+        ::std::mem::align_of::<nspire_devinfo__bindgen_ty_7>(),
+        2usize,
+        concat!("Alignment of ", stringify!(nspire_devinfo__bindgen_ty_7))
+    );
+    assert_eq!(
+        ::std::mem::offset_of!(nspire_devinfo__bindgen_ty_7, x),
+        0usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(nspire_devinfo__bindgen_ty_7),
+            "::",
+            stringify!(x)
+        )
+    );
+    assert_eq!(
+        ::std::mem::offset_of!(nspire_devinfo__bindgen_ty_7, y),
+        2usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(nspire_devinfo__bindgen_ty_7),
+            "::",
+            stringify!(y)
+        )
+    );
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct nspire_devinfo__bindgen_ty_8 {
+    pub data: [u8; 252usize],
+    pub len: u16,
+}
+#[test]
+fn bindgen_test_layout_nspire_devinfo__bindgen_ty_8() {
+    assert_eq!(
+        ::std::mem::size_of::<nspire_devinfo__bindgen_ty_8>(),
+        254usize,
+        concat!("Size of: ", stringify!(nspire_devinfo__bindgen_ty_8))
+    );
+    assert_eq!(
+        ::std::mem::align_of::<nspire_devinfo__bindgen_ty_8>(),
+        2usize,
+        concat!("Alignment of ", stringify!(nspire_devinfo__bindgen_ty_8))
+    );
+    assert_eq!(
+        ::std::mem::offset_of!(nspire_devinfo__bindgen_ty_8, data),
+        0usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(nspire_devinfo__bindgen_ty_8),
+            "::",
+            stringify!(data)
+        )
+    );
+    assert_eq!(
+        ::std::mem::offset_of!(nspire_devinfo__bindgen_ty_8, len),
+        252usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(nspire_devinfo__bindgen_ty_8),
+            "::",
+            stringify!(len)
+        )
+    );
+}
 #[test]
 fn bindgen_test_layout_nspire_devinfo() {
     assert_eq!(
         ::std::mem::size_of::<nspire_devinfo>(),
-        136usize,
+        416usize,
         concat!("Size of: ", stringify!(nspire_devinfo))
     );
     assert_eq!(
@@ -400,7 +621,7 @@ fn bindgen_test_layout_nspire_devinfo() {
         concat!("Alignment of ", stringify!(nspire_devinfo))
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<nspire_devinfo>())).storage as *const _ as usize },
+        ::std::mem::offset_of!(nspire_devinfo, storage),
         0usize,
         concat!(
             "Offset of field: ",
@@ -410,7 +631,7 @@ fn bindgen_test_layout_nspire_devinfo() {
         )
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<nspire_devinfo>())).ram as *const _ as usize },
+        ::std::mem::offset_of!(nspire_devinfo, ram),
         16usize,
         concat!(
             "Offset of field: ",
@@ -420,7 +641,7 @@ fn bindgen_test_layout_nspire_devinfo() {
         )
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<nspire_devinfo>())).versions as *const _ as usize },
+        ::std::mem::offset_of!(nspire_devinfo, versions),
         32usize,
         concat!(
             "Offset of field: ",
@@ -430,7 +651,7 @@ fn bindgen_test_layout_nspire_devinfo() {
         )
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<nspire_devinfo>())).hw_type as *const _ as usize },
+        ::std::mem::offset_of!(nspire_devinfo, hw_type),
         44usize,
         concat!(
             "Offset of field: ",
@@ -440,7 +661,7 @@ fn bindgen_test_layout_nspire_devinfo() {
         )
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<nspire_devinfo>())).batt as *const _ as usize },
+        ::std::mem::offset_of!(nspire_devinfo, batt),
         48usize,
         concat!(
             "Offset of field: ",
@@ -450,7 +671,7 @@ fn bindgen_test_layout_nspire_devinfo() {
         )
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<nspire_devinfo>())).clock_speed as *const _ as usize },
+        ::std::mem::offset_of!(nspire_devinfo, clock_speed),
         56usize,
         concat!(
             "Offset of field: ",
@@ -460,7 +681,7 @@ fn bindgen_test_layout_nspire_devinfo() {
         )
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<nspire_devinfo>())).lcd as *const _ as usize },
+        ::std::mem::offset_of!(nspire_devinfo, lcd),
         58usize,
         concat!(
             "Offset of field: ",
@@ -470,7 +691,7 @@ fn bindgen_test_layout_nspire_devinfo() {
         )
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<nspire_devinfo>())).extensions as *const _ as usize },
+        ::std::mem::offset_of!(nspire_devinfo, extensions),
         64usize,
         concat!(
             "Offset of field: ",
@@ -480,7 +701,7 @@ fn bindgen_test_layout_nspire_devinfo() {
         )
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<nspire_devinfo>())).device_name as *const _ as usize },
+        ::std::mem::offset_of!(nspire_devinfo, device_name),
         80usize,
         concat!(
             "Offset of field: ",
@@ -490,7 +711,7 @@ fn bindgen_test_layout_nspire_devinfo() {
         )
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<nspire_devinfo>())).electronic_id as *const _ as usize },
+        ::std::mem::offset_of!(nspire_devinfo, electronic_id),
         100usize,
         concat!(
             "Offset of field: ",
@@ -500,7 +721,7 @@ fn bindgen_test_layout_nspire_devinfo() {
         )
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<nspire_devinfo>())).runlevel as *const _ as usize },
+        ::std::mem::offset_of!(nspire_devinfo, runlevel),
         128usize,
         concat!(
             "Offset of field: ",
@@ -509,6 +730,46 @@ fn bindgen_test_layout_nspire_devinfo() {
             stringify!(runlevel)
         )
     );
+    assert_eq!(
+        ::std::mem::offset_of!(nspire_devinfo, hw_version),
+        132usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(nspire_devinfo),
+            "::",
+            stringify!(hw_version)
+        )
+    );
+    assert_eq!(
+        ::std::mem::offset_of!(nspire_devinfo, lcd_offset),
+        136usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(nspire_devinfo),
+            "::",
+            stringify!(lcd_offset)
+        )
+    );
+    assert_eq!(
+        ::std::mem::offset_of!(nspire_devinfo, short_id),
+        140usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(nspire_devinfo),
+            "::",
+            stringify!(short_id)
+        )
+    );
+    assert_eq!(
+        ::std::mem::offset_of!(nspire_devinfo, raw),
+        158usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(nspire_devinfo),
+            "::",
+            stringify!(raw)
+        )
+    );
 }
 extern "C" {
     pub fn nspire_device_info(
@@ -518,6 +779,13 @@ extern "C" {
 }
 pub type nspire_callback =
     ::std::option::Option<unsafe extern "C" fn(arg1: usize, arg2: *mut ::std::os::raw::c_void)>;
+extern "C" {
+    pub fn nspire_ping(
+        handle: *mut nspire_handle_t,
+        payload: *const ::std::os::raw::c_void,
+        size: usize,
+    ) -> ::std::os::raw::c_int;
+}
 extern "C" {
     pub fn nspire_file_write(
         arg1: *mut nspire_handle_t,
@@ -559,6 +827,12 @@ extern "C" {
         path: *const ::std::os::raw::c_char,
     ) -> ::std::os::raw::c_int;
 }
+extern "C" {
+    pub fn nspire_file_session_begin(handle: *mut nspire_handle_t) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn nspire_file_session_end(handle: *mut nspire_handle_t) -> ::std::os::raw::c_int;
+}
 pub const nspire_dir_type_NSPIRE_FILE: nspire_dir_type = 0;
 pub const nspire_dir_type_NSPIRE_DIR: nspire_dir_type = 1;
 pub type nspire_dir_type = ::std::os::raw::c_uint;
@@ -583,7 +857,7 @@ fn bindgen_test_layout_nspire_dir_item() {
         concat!("Alignment of ", stringify!(nspire_dir_item))
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<nspire_dir_item>())).name as *const _ as usize },
+        ::std::mem::offset_of!(nspire_dir_item, name),
         0usize,
         concat!(
             "Offset of field: ",
@@ -593,7 +867,7 @@ fn bindgen_test_layout_nspire_dir_item() {
         )
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<nspire_dir_item>())).size as *const _ as usize },
+        ::std::mem::offset_of!(nspire_dir_item, size),
         240usize,
         concat!(
             "Offset of field: ",
@@ -603,7 +877,7 @@ fn bindgen_test_layout_nspire_dir_item() {
         )
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<nspire_dir_item>())).date as *const _ as usize },
+        ::std::mem::offset_of!(nspire_dir_item, date),
         248usize,
         concat!(
             "Offset of field: ",
@@ -613,7 +887,7 @@ fn bindgen_test_layout_nspire_dir_item() {
         )
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<nspire_dir_item>())).type_ as *const _ as usize },
+        ::std::mem::offset_of!(nspire_dir_item, type_),
         256usize,
         concat!(
             "Offset of field: ",
@@ -641,7 +915,7 @@ fn bindgen_test_layout_nspire_dir_info() {
         concat!("Alignment of ", stringify!(nspire_dir_info))
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<nspire_dir_info>())).num as *const _ as usize },
+        ::std::mem::offset_of!(nspire_dir_info, num),
         0usize,
         concat!(
             "Offset of field: ",
@@ -651,7 +925,7 @@ fn bindgen_test_layout_nspire_dir_info() {
         )
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<nspire_dir_info>())).items as *const _ as usize },
+        ::std::mem::offset_of!(nspire_dir_info, items),
         8usize,
         concat!(
             "Offset of field: ",
@@ -702,11 +976,18 @@ pub const NSPIRE_ERR_BUSY: ::std::os::raw::c_uint = 8;
 pub const NSPIRE_ERR_EXISTS: ::std::os::raw::c_uint = 9;
 pub const NSPIRE_ERR_NONEXIST: ::std::os::raw::c_uint = 10;
 pub const NSPIRE_ERR_OSFAILED: ::std::os::raw::c_uint = 11;
-pub const NSPIRE_ERR_MAX: ::std::os::raw::c_uint = 12;
+pub const NSPIRE_ERR_NOTSUPPORTED: ::std::os::raw::c_uint = 12;
+pub const NSPIRE_ERR_MAX: ::std::os::raw::c_uint = 13;
 pub type _bindgen_ty_5 = ::std::os::raw::c_uint;
 extern "C" {
     pub fn nspire_strerror(error: ::std::os::raw::c_int) -> *const ::std::os::raw::c_char;
 }
+pub const nspire_os_phase_NSPIRE_OS_UPLOAD: nspire_os_phase = 0;
+pub const nspire_os_phase_NSPIRE_OS_INSTALL: nspire_os_phase = 1;
+pub type nspire_os_phase = ::std::os::raw::c_uint;
+pub type nspire_os_callback = ::std::option::Option<
+    unsafe extern "C" fn(arg1: nspire_os_phase, arg2: usize, arg3: *mut ::std::os::raw::c_void),
+>;
 extern "C" {
     pub fn nspire_os_send(
         handle: *mut nspire_handle_t,
@@ -716,6 +997,29 @@ extern "C" {
         cb_data: *mut ::std::os::raw::c_void,
     ) -> ::std::os::raw::c_int;
 }
+extern "C" {
+    pub fn nspire_send_keys(
+        handle: *mut nspire_handle_t,
+        keys: *const u16,
+        count: usize,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn nspire_login(
+        handle: *mut nspire_handle_t,
+        name: *const ::std::os::raw::c_char,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn nspire_os_install(
+        handle: *mut nspire_handle_t,
+        data: *mut ::std::os::raw::c_void,
+        size: usize,
+        cb: nspire_os_callback,
+        cb_data: *mut ::std::os::raw::c_void,
+        status: *mut u8,
+    ) -> ::std::os::raw::c_int;
+}
 #[repr(C)]
 #[derive(Debug)]
 pub struct nspire_image {
@@ -737,7 +1041,7 @@ fn bindgen_test_layout_nspire_image() {
         concat!("Alignment of ", stringify!(nspire_image))
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<nspire_image>())).width as *const _ as usize },
+        ::std::mem::offset_of!(nspire_image, width),
         0usize,
         concat!(
             "Offset of field: ",
@@ -747,7 +1051,7 @@ fn bindgen_test_layout_nspire_image() {
         )
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<nspire_image>())).height as *const _ as usize },
+        ::std::mem::offset_of!(nspire_image, height),
         2usize,
         concat!(
             "Offset of field: ",
@@ -757,7 +1061,7 @@ fn bindgen_test_layout_nspire_image() {
         )
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<nspire_image>())).bbp as *const _ as usize },
+        ::std::mem::offset_of!(nspire_image, bbp),
         4usize,
         concat!(
             "Offset of field: ",
@@ -767,7 +1071,7 @@ fn bindgen_test_layout_nspire_image() {
         )
     );
     assert_eq!(
-        unsafe { &(*(::std::ptr::null::<nspire_image>())).data as *const _ as usize },
+        ::std::mem::offset_of!(nspire_image, data),
         5usize,
         concat!(
             "Offset of field: ",
@@ -783,3 +1087,32 @@ extern "C" {
         ptr: *mut *mut nspire_image,
     ) -> ::std::os::raw::c_int;
 }
+pub const NSPIRE_NAVNET_MAGIC: ::std::os::raw::c_uint = 21757;
+pub const NSPIRE_ADDR_HOST: ::std::os::raw::c_uint = 25600;
+pub const NSPIRE_ADDR_DEVICE: ::std::os::raw::c_uint = 25601;
+pub const NSPIRE_SID_NACK: ::std::os::raw::c_uint = 211;
+pub const NSPIRE_SID_ACK_ZERO: ::std::os::raw::c_uint = 254;
+pub const NSPIRE_SID_ACK: ::std::os::raw::c_uint = 255;
+pub const NSPIRE_SID_ECHO: ::std::os::raw::c_uint = 16386;
+pub const NSPIRE_SID_ADDR_ASSIGN: ::std::os::raw::c_uint = 16387;
+pub const NSPIRE_SID_DEVINFO: ::std::os::raw::c_uint = 16416;
+pub const NSPIRE_SID_SCREENSHOT: ::std::os::raw::c_uint = 16420;
+pub const NSPIRE_SID_KEYS: ::std::os::raw::c_uint = 16450;
+pub const NSPIRE_SID_LOGIN: ::std::os::raw::c_uint = 16464;
+pub const NSPIRE_SID_FILE: ::std::os::raw::c_uint = 16480;
+pub const NSPIRE_SID_OS: ::std::os::raw::c_uint = 16512;
+pub const NSPIRE_SID_DISCONNECT: ::std::os::raw::c_uint = 16606;
+pub type _bindgen_ty_6 = ::std::os::raw::c_uint;
+pub const NSPIRE_NNSE_ADDR_CALC: ::std::os::raw::c_uint = 1;
+pub const NSPIRE_NNSE_ADDR_ME: ::std::os::raw::c_uint = 254;
+pub const NSPIRE_NNSE_ADDR_ALL: ::std::os::raw::c_uint = 255;
+pub const NSPIRE_NNSE_SVC_ADDR_REQ: ::std::os::raw::c_uint = 1;
+pub const NSPIRE_NNSE_SVC_TIME: ::std::os::raw::c_uint = 2;
+pub const NSPIRE_NNSE_SVC_ECHO: ::std::os::raw::c_uint = 3;
+pub const NSPIRE_NNSE_SVC_STREAM: ::std::os::raw::c_uint = 4;
+pub const NSPIRE_NNSE_SVC_TRANSMIT: ::std::os::raw::c_uint = 5;
+pub const NSPIRE_NNSE_SVC_LOOPBACK: ::std::os::raw::c_uint = 6;
+pub const NSPIRE_NNSE_SVC_STATS: ::std::os::raw::c_uint = 7;
+pub const NSPIRE_NNSE_SVC_UNKNOWN: ::std::os::raw::c_uint = 8;
+pub const NSPIRE_NNSE_ACK_FLAG: ::std::os::raw::c_uint = 128;
+pub type _bindgen_ty_7 = ::std::os::raw::c_uint;
@@ -1,5 +1,7 @@
 #![allow(non_camel_case_types)]
 #![allow(non_upper_case_globals)]
+#![allow(non_snake_case)]
+#![allow(clippy::missing_safety_doc)]
 
 extern crate libusb1_sys;
 include!("bindings.rs");
@@ -0,0 +1,24 @@
+//! Share the first connected calculator over TCP, for use with
+//! `libnspire::bridge::Client`.
+//!
+//! cargo run --example bridge -- 0.0.0.0:7878
+
+use std::net::TcpListener;
+
+use libnspire::transport::UsbTransport;
+use libnspire::{Error, PID, PID_CX2, VID};
+
+fn main() {
+    let addr = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "127.0.0.1:7878".to_string());
+    let listener = TcpListener::bind(&addr).unwrap();
+    eprintln!("Listening on {}", addr);
+    libnspire::bridge::serve(listener, || {
+        let dev = rusb::open_device_with_vid_pid(VID, PID)
+            .or_else(|| rusb::open_device_with_vid_pid(VID, PID_CX2))
+            .ok_or(Error::NoDevice)?;
+        UsbTransport::new(dev)
+    })
+    .unwrap();
+}
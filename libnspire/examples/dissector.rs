@@ -0,0 +1,5 @@
+//! Print a Wireshark dissector for the calculator protocols.
+
+fn main() {
+    print!("{}", libnspire::dissector::lua());
+}
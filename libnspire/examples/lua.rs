@@ -0,0 +1,19 @@
+use std::env;
+use std::fs;
+
+use libnspire::tns::Builder;
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let script = args.next().expect("usage: lua <script.lua> [name.tns]");
+    let name = args.next().unwrap_or_else(|| "script.tns".to_string());
+    let tns = Builder::new(fs::read_to_string(script).unwrap())
+        .build()
+        .unwrap();
+
+    let dev = rusb::open_device_with_vid_pid(0x0451, 0xe012).unwrap();
+    let handle = libnspire::Handle::new(dev).unwrap();
+    handle
+        .write_file(&name, &tns, &mut |remaining| println!("{}", remaining))
+        .unwrap();
+}
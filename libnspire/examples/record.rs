@@ -0,0 +1,33 @@
+//! Record a session with the first connected calculator, for replaying with
+//! `libnspire::replay::ReplayTransport`.
+//!
+//! cargo run --example record -- session.pcapng
+
+use std::fs::File;
+
+use libnspire::capture::LinkType;
+use libnspire::replay::Recording;
+use libnspire::transport::UsbTransport;
+use libnspire::{Handle, PID, PID_CX2, VID};
+
+fn main() {
+    let path = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "session.pcapng".to_string());
+    let dev = rusb::open_device_with_vid_pid(VID, PID)
+        .or_else(|| rusb::open_device_with_vid_pid(VID, PID_CX2))
+        .expect("no calculator found");
+    let recording = Recording::default();
+    let transport = recording.record(UsbTransport::new(dev).unwrap());
+    let handle = Handle::<rusb::GlobalContext>::from_transport(transport).unwrap();
+    for item in handle.list_dir("/").unwrap().iter() {
+        println!("{}", item.name().to_string_lossy());
+    }
+    drop(handle);
+    let file = File::create(&path).unwrap();
+    recording
+        .capture()
+        .write_pcapng(file, LinkType::User)
+        .unwrap();
+    eprintln!("Saved to {}", path);
+}
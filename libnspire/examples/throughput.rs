@@ -0,0 +1,49 @@
+//! Compare upload speeds with and without big packets, against the simulator
+//! with a USB-like delay on every packet. This shows the effect of sending
+//! fewer packets, not how fast a real calculator is.
+//!
+//! cargo run --release --example throughput -- [kilobytes] [latency in µs]
+
+use std::time::Duration;
+
+use libnspire::sim::Simulator;
+use libnspire::transfer::Transfer;
+use libnspire::{Handle, HandleBuilder};
+
+fn upload(sim: &Simulator, big_packets: bool, data: &[u8]) -> Transfer {
+    sim.set_big_packets(big_packets);
+    let handle: Handle<rusb::GlobalContext> = HandleBuilder::new()
+        .big_packets(big_packets)
+        .open_transport(sim.clone())
+        .unwrap();
+    let upload = handle
+        .upload("/documents/throughput.tns", data, &mut |_| {})
+        .unwrap();
+    assert_eq!(
+        sim.file("/documents/throughput.tns").as_deref(),
+        Some(data),
+        "arrived wrong"
+    );
+    handle.delete_file("/documents/throughput.tns").unwrap();
+    upload
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let kilobytes: usize = args.first().map_or(256, |n| n.parse().unwrap());
+    let latency = args.get(1).map_or(125, |n| n.parse().unwrap());
+
+    let sim = Simulator::new();
+    sim.set_latency(Duration::from_micros(latency));
+    let data: Vec<u8> = (0..kilobytes * 1000).map(|i| i as u8).collect();
+
+    let small = upload(&sim, false, &data);
+    let big = upload(&sim, true, &data);
+    for transfer in &[small, big] {
+        println!("{:>4}-byte packets: {}", transfer.packet_size, transfer);
+    }
+    println!(
+        "speedup: {:.1}x",
+        big.bytes_per_sec() / small.bytes_per_sec()
+    );
+}
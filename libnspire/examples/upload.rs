@@ -1,4 +1,3 @@
-use std::convert::TryFrom;
 use std::fs::File;
 use std::io::Read;
 
@@ -10,5 +9,7 @@ fn main() {
         .unwrap()
         .read_to_end(&mut buf)
         .unwrap();
-    handle.write_file("test.tns", &buf, &mut |prog| println!("{}", prog));
+    handle
+        .write_file("test.tns", &buf, &mut |prog| println!("{}", prog))
+        .unwrap();
 }
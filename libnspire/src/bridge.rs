@@ -0,0 +1,373 @@
+//! Using a calculator attached to another machine over TCP.
+//!
+//! The machine with the calculator runs [`serve`], which relays raw packets
+//! between each client and a [`Transport`] (usually a
+//! [`UsbTransport`][crate::transport::UsbTransport]). Elsewhere, a [`Client`]
+//! makes the remote calculator look local to a [`Handle`][crate::Handle]:
+//!
+//! ```
+//! use libnspire::{bridge, sim::Simulator, Handle};
+//! use std::net::TcpListener;
+//! # fn main() -> libnspire::Result<()> {
+//! let listener = TcpListener::bind("127.0.0.1:0").map_err(|_| libnspire::Error::Io)?;
+//! let addr = listener.local_addr().unwrap();
+//!
+//! // On the machine with the calculator
+//! let sim = Simulator::new();
+//! std::thread::spawn(move || bridge::serve(listener, || Ok(sim.clone())));
+//!
+//! // On the machine without
+//! let handle = Handle::<rusb::GlobalContext>::from_transport(bridge::Client::connect(addr)?)?;
+//! assert_eq!(handle.info()?.name, "Simulator");
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The protocol has no authentication or encryption, so only use it on
+//! trusted networks.
+
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::os::raw::c_int;
+use std::time::Duration;
+
+use crate::error::err;
+use crate::transport::Transport;
+use crate::{Error, Result};
+
+/// Sent by the server when a client connects.
+const MAGIC: &[u8; 4] = b"NSPB";
+const VERSION: u8 = 1;
+
+// Every message is a kind byte, a 32-bit length and that many bytes.
+/// Server to client: `MAGIC`, `VERSION`, then 1 if the calculator is a CX II.
+const HELLO: u8 = 0;
+/// Client to server: the most to read (32 bits), then the timeout in ms (32
+/// bits).
+const READ: u8 = 1;
+/// Client to server: the timeout in ms (32 bits), then the packet.
+const WRITE: u8 = 2;
+/// Server to client: the packet read, or for a write, the number of bytes
+/// written (32 bits).
+const OK: u8 = 3;
+/// Server to client: a libnspire error code (32 bits).
+const ERROR: u8 = 4;
+
+/// Larger than any packet.
+const MAX_MESSAGE: usize = 64 * 1024;
+
+/// Accept clients one at a time, calling `connect` to get a fresh connection
+/// to the calculator for each one. Only returns if accepting fails.
+///
+/// A client that disconnects ends its session; errors while serving it are
+/// not fatal.
+pub fn serve<T, F>(listener: TcpListener, mut connect: F) -> Result<()>
+where
+    T: Transport,
+    F: FnMut() -> Result<T>,
+{
+    loop {
+        let (mut stream, _) = listener.accept().map_err(|_| Error::Io)?;
+        let _ = stream.set_nodelay(true);
+        let _ = match connect() {
+            Ok(mut transport) => relay(&mut stream, &mut transport),
+            Err(e) => send(&mut stream, ERROR, &e.code().to_be_bytes()),
+        };
+    }
+}
+
+/// Serve one client until it disconnects.
+fn relay(stream: &mut TcpStream, transport: &mut impl Transport) -> Result<()> {
+    let mut hello = MAGIC.to_vec();
+    hello.extend_from_slice(&[VERSION, transport.is_cx_ii() as u8]);
+    send(stream, HELLO, &hello)?;
+
+    let mut buf = vec![0; MAX_MESSAGE];
+    loop {
+        let (kind, msg) = match receive(stream) {
+            Ok(msg) => msg,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(_) => return Err(Error::Io),
+        };
+        let result = match (kind, split_u32(&msg)) {
+            (READ, Some((len, rest))) => {
+                let timeout = match split_u32(rest) {
+                    Some((timeout, [])) => timeout,
+                    _ => return Err(Error::InvalidPacket),
+                };
+                let len = (len as usize).min(buf.len());
+                transport
+                    .read(&mut buf[..len], millis_to_duration(timeout))
+                    .map(|len| buf[..len].to_vec())
+            }
+            (WRITE, Some((timeout, data))) => transport
+                .write(data, millis_to_duration(timeout))
+                .map(|len| (len as u32).to_be_bytes().to_vec()),
+            _ => return Err(Error::InvalidPacket),
+        };
+        match result {
+            Ok(reply) => send(stream, OK, &reply)?,
+            Err(e) => send(stream, ERROR, &e.code().to_be_bytes())?,
+        }
+    }
+}
+
+/// A calculator attached to another machine running [`serve`].
+pub struct Client {
+    stream: TcpStream,
+    is_cx_ii: bool,
+}
+
+impl Client {
+    pub fn connect(addr: impl ToSocketAddrs) -> Result<Self> {
+        let mut stream = TcpStream::connect(addr).map_err(|_| Error::NoDevice)?;
+        let _ = stream.set_nodelay(true);
+        let hello = match receive(&mut stream).map_err(|_| Error::Io)? {
+            (HELLO, hello) => hello,
+            (ERROR, code) => return Err(error(&code)),
+            _ => return Err(Error::InvalidPacket),
+        };
+        match &hello[..] {
+            [magic @ .., VERSION, is_cx_ii] if magic == MAGIC => Ok(Client {
+                stream,
+                is_cx_ii: *is_cx_ii != 0,
+            }),
+            _ => Err(Error::InvalidPacket),
+        }
+    }
+
+    fn call(&mut self, kind: u8, msg: &[u8]) -> Result<Vec<u8>> {
+        send(&mut self.stream, kind, msg)?;
+        match receive(&mut self.stream).map_err(|_| Error::Io)? {
+            (OK, reply) => Ok(reply),
+            (ERROR, code) => Err(error(&code)),
+            _ => Err(Error::InvalidPacket),
+        }
+    }
+}
+
+impl Transport for Client {
+    fn read(&mut self, buf: &mut [u8], timeout: Duration) -> Result<usize> {
+        let mut msg = (buf.len() as u32).to_be_bytes().to_vec();
+        msg.extend_from_slice(&millis(timeout).to_be_bytes());
+        let reply = self.call(READ, &msg)?;
+        let len = reply.len().min(buf.len());
+        buf[..len].copy_from_slice(&reply[..len]);
+        Ok(len)
+    }
+
+    fn write(&mut self, buf: &[u8], timeout: Duration) -> Result<usize> {
+        let mut msg = millis(timeout).to_be_bytes().to_vec();
+        msg.extend_from_slice(buf);
+        let reply = self.call(WRITE, &msg)?;
+        match split_u32(&reply) {
+            Some((len, _)) => Ok(len as usize),
+            None => Err(Error::InvalidPacket),
+        }
+    }
+
+    fn is_cx_ii(&self) -> bool {
+        self.is_cx_ii
+    }
+}
+
+fn send(stream: &mut TcpStream, kind: u8, msg: &[u8]) -> Result<()> {
+    let mut buf = vec![kind];
+    buf.extend_from_slice(&(msg.len() as u32).to_be_bytes());
+    buf.extend_from_slice(msg);
+    stream.write_all(&buf).map_err(|_| Error::Io)
+}
+
+fn receive(stream: &mut TcpStream) -> io::Result<(u8, Vec<u8>)> {
+    let mut header = [0; 5];
+    stream.read_exact(&mut header)?;
+    let [kind, len @ ..] = header;
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_MESSAGE {
+        return Err(io::ErrorKind::InvalidData.into());
+    }
+    let mut msg = vec![0; len];
+    stream.read_exact(&mut msg)?;
+    Ok((kind, msg))
+}
+
+fn error(code: &[u8]) -> Error {
+    match split_u32(code) {
+        Some((code, [])) => err(code as c_int).err().unwrap_or(Error::Unknown),
+        _ => Error::InvalidPacket,
+    }
+}
+
+/// Split a 32-bit number off the start of a message, or return `None` if
+/// the message is too short.
+fn split_u32(msg: &[u8]) -> Option<(u32, &[u8])> {
+    match msg {
+        [a, b, c, d, rest @ ..] => Some((u32::from_be_bytes([*a, *b, *c, *d]), rest)),
+        _ => None,
+    }
+}
+
+fn millis(timeout: Duration) -> u32 {
+    timeout.as_millis().min(u32::MAX.into()) as u32
+}
+
+fn millis_to_duration(millis: u32) -> Duration {
+    Duration::from_millis(millis.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Shutdown, SocketAddr};
+    use std::thread;
+
+    use super::*;
+    use crate::sim::Simulator;
+
+    /// A calculator that fails everything.
+    struct Failing;
+
+    impl Transport for Failing {
+        fn read(&mut self, _buf: &mut [u8], _timeout: Duration) -> Result<usize> {
+            Err(Error::Timeout)
+        }
+        fn write(&mut self, _buf: &[u8], _timeout: Duration) -> Result<usize> {
+            Err(Error::Busy)
+        }
+    }
+
+    fn listener() -> (TcpListener, SocketAddr) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        (listener, addr)
+    }
+
+    /// Connect a client to a server that sends `reply` and hangs up.
+    fn connect_to(reply: Vec<u8>) -> Result<Client> {
+        let (listener, addr) = listener();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.write_all(&reply).unwrap();
+        });
+        let client = Client::connect(addr);
+        server.join().unwrap();
+        client
+    }
+
+    fn message(kind: u8, msg: &[u8]) -> Vec<u8> {
+        let mut buf = vec![kind];
+        buf.extend_from_slice(&(msg.len() as u32).to_be_bytes());
+        buf.extend_from_slice(msg);
+        buf
+    }
+
+    /// Relay for a simulator, sending `request` to it as a client, and
+    /// return how the relay ended.
+    fn relay_after(request: Vec<u8>) -> Result<()> {
+        let (listener, addr) = listener();
+        let client = thread::spawn(move || {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            assert_eq!(receive(&mut stream).unwrap().0, HELLO);
+            stream.write_all(&request).unwrap();
+            stream.shutdown(Shutdown::Write).unwrap();
+            // Wait for the relay to hang up
+            let _ = stream.read_to_end(&mut vec![]);
+        });
+        let (mut stream, _) = listener.accept().unwrap();
+        let result = relay(&mut stream, &mut Simulator::new());
+        drop(stream);
+        client.join().unwrap();
+        result
+    }
+
+    #[test]
+    fn failed_connect() {
+        let (listener, addr) = listener();
+        thread::spawn(move || serve(listener, || Err::<Failing, _>(Error::NoDevice)));
+        for _ in 0..2 {
+            assert!(matches!(Client::connect(addr), Err(Error::NoDevice)));
+        }
+    }
+
+    #[test]
+    fn device_errors() {
+        let (listener, addr) = listener();
+        thread::spawn(move || serve(listener, || Ok(Failing)));
+        let mut client = Client::connect(addr).unwrap();
+        let timeout = Duration::from_millis(10);
+        assert!(matches!(
+            client.read(&mut [0; 16], timeout),
+            Err(Error::Timeout)
+        ));
+        assert!(matches!(
+            client.write(&[1, 2, 3], timeout),
+            Err(Error::Busy)
+        ));
+    }
+
+    #[test]
+    fn bad_hello() {
+        let mut hello = MAGIC.to_vec();
+        hello.extend_from_slice(&[VERSION, 0]);
+        assert!(connect_to(message(HELLO, &hello)).is_ok());
+
+        let bad = [
+            message(HELLO, b"NSPX\x01\x00"),
+            message(HELLO, &[MAGIC, &[VERSION + 1, 0][..]].concat()),
+            message(HELLO, MAGIC),
+            message(OK, &hello),
+            message(ERROR, &[0, 0]),
+        ];
+        for reply in bad {
+            assert!(matches!(connect_to(reply), Err(Error::InvalidPacket)));
+        }
+        // Too long, or cut short
+        let mut oversized = vec![HELLO];
+        oversized.extend_from_slice(&(MAX_MESSAGE as u32 + 1).to_be_bytes());
+        assert!(matches!(connect_to(oversized), Err(Error::Io)));
+        let mut truncated = message(HELLO, &hello);
+        truncated.pop();
+        assert!(matches!(connect_to(truncated), Err(Error::Io)));
+    }
+
+    #[test]
+    fn bad_requests() {
+        assert!(relay_after(vec![]).is_ok());
+        let bad = [
+            message(READ, &[0, 0, 1, 0]),
+            message(READ, &[0, 0, 1, 0, 0, 0, 0, 10, 0]),
+            message(WRITE, &[0, 0]),
+            message(OK, &[]),
+            message(9, &[0; 8]),
+        ];
+        for request in bad {
+            assert!(matches!(relay_after(request), Err(Error::InvalidPacket)));
+        }
+        let mut oversized = vec![WRITE];
+        oversized.extend_from_slice(&(MAX_MESSAGE as u32 + 1).to_be_bytes());
+        assert!(matches!(relay_after(oversized), Err(Error::Io)));
+    }
+
+    #[test]
+    fn short_write_reply() {
+        let (listener, addr) = listener();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let hello = [MAGIC, &[VERSION, 0][..]].concat();
+            send(&mut stream, HELLO, &hello).unwrap();
+            receive(&mut stream).unwrap();
+            send(&mut stream, OK, &[0, 1]).unwrap();
+            receive(&mut stream).unwrap();
+            send(&mut stream, ERROR, &[0]).unwrap();
+        });
+        let mut client = Client::connect(addr).unwrap();
+        let timeout = Duration::from_millis(10);
+        assert!(matches!(
+            client.write(&[1], timeout),
+            Err(Error::InvalidPacket)
+        ));
+        assert!(matches!(
+            client.write(&[1], timeout),
+            Err(Error::InvalidPacket)
+        ));
+    }
+}
@@ -1,4 +1,21 @@
-use std::os::raw::c_void;
+use std::ffi::CStr;
+use std::fmt;
+use std::os::raw::{c_char, c_int, c_uint, c_void};
+use std::ptr::null_mut;
+use std::slice;
+use std::sync::{Mutex, Once};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use libnspire_sys::{
+    nspire_log_level, nspire_log_level_NSPIRE_LOG_WARN, nspire_opts, nspire_os_phase,
+    nspire_os_phase_NSPIRE_OS_INSTALL, nspire_set_log, nspire_tap_proto,
+    nspire_tap_proto_NSPIRE_TAP_NNSE, nspire_transport,
+};
+
+use crate::capture::{Direction, Frame, Protocol};
+use crate::os_image::OsProgress;
+use crate::transport::Transport;
+use crate::ClockSource;
 
 pub struct CallbackData<'a>(pub &'a mut dyn FnMut(usize));
 
@@ -11,3 +28,222 @@ impl CallbackData<'_> {
         self as *mut CallbackData as *mut c_void
     }
 }
+
+pub struct OsCallbackData<'a> {
+    pub total: usize,
+    pub progress: &'a mut dyn FnMut(OsProgress),
+}
+
+impl OsCallbackData<'_> {
+    pub unsafe extern "C" fn callback(phase: nspire_os_phase, value: usize, data: *mut c_void) {
+        let data = &mut *(data as *mut OsCallbackData);
+        (data.progress)(if phase == nspire_os_phase_NSPIRE_OS_INSTALL {
+            OsProgress::Installing(value as u8)
+        } else {
+            OsProgress::Uploading {
+                remaining: value,
+                total: data.total,
+            }
+        });
+    }
+    pub fn as_mut_void(&mut self) -> *mut c_void {
+        self as *mut OsCallbackData as *mut c_void
+    }
+}
+
+pub struct TransportData(pub Box<dyn Transport>);
+
+impl TransportData {
+    pub unsafe extern "C" fn read(
+        data: *mut c_void,
+        buf: *mut c_void,
+        len: c_int,
+        timeout: c_uint,
+    ) -> c_int {
+        let data = &mut *(data as *mut TransportData);
+        let buf = slice::from_raw_parts_mut(buf as *mut u8, len as usize);
+        match data.0.read(buf, Duration::from_millis(timeout.into())) {
+            Ok(len) => len as c_int,
+            Err(e) => e.code(),
+        }
+    }
+    pub unsafe extern "C" fn write(
+        data: *mut c_void,
+        buf: *const c_void,
+        len: c_int,
+        timeout: c_uint,
+    ) -> c_int {
+        let data = &mut *(data as *mut TransportData);
+        let buf = slice::from_raw_parts(buf as *const u8, len as usize);
+        match data.0.write(buf, Duration::from_millis(timeout.into())) {
+            Ok(len) => len as c_int,
+            Err(e) => e.code(),
+        }
+    }
+    pub fn as_raw(&mut self) -> nspire_transport {
+        nspire_transport {
+            data: self as *mut TransportData as *mut c_void,
+            read: Some(TransportData::read),
+            write: Some(TransportData::write),
+        }
+    }
+}
+
+/// The frames captured so far, or `None` when not capturing.
+#[derive(Default)]
+pub struct TapData(pub Mutex<Option<Vec<Frame>>>);
+
+impl TapData {
+    pub unsafe extern "C" fn tap(
+        data: *mut c_void,
+        proto: nspire_tap_proto,
+        sent: bool,
+        buf: *const c_void,
+        len: c_int,
+    ) {
+        let data = &*(data as *const TapData);
+        let buf = slice::from_raw_parts(buf as *const u8, len.max(0) as usize);
+        let direction = if sent {
+            Direction::Sent
+        } else {
+            Direction::Received
+        };
+        let protocol = if proto == nspire_tap_proto_NSPIRE_TAP_NNSE {
+            Protocol::Nnse
+        } else {
+            Protocol::NavNet
+        };
+        tracing::trace!(?protocol, ?direction, len = buf.len(), data = %Hex(buf), "packet");
+
+        // Never panic across the FFI boundary
+        let mut frames = match data.0.lock() {
+            Ok(frames) => frames,
+            Err(_) => return,
+        };
+        if let Some(frames) = &mut *frames {
+            frames.push(Frame {
+                time: SystemTime::now(),
+                direction,
+                protocol,
+                data: buf.to_vec(),
+            });
+        }
+    }
+    pub fn as_mut_void(&self) -> *mut c_void {
+        self as *const TapData as *mut c_void
+    }
+}
+
+pub struct ClockData(pub ClockSource);
+
+impl ClockData {
+    pub unsafe extern "C" fn now(data: *mut c_void, sec: *mut u32, frac: *mut u64) -> bool {
+        let data = &*(data as *const ClockData);
+        match data.0.now().and_then(nnse_time) {
+            Some((now_sec, now_frac)) => {
+                *sec = now_sec;
+                *frac = now_frac;
+                true
+            }
+            None => false,
+        }
+    }
+    /// Options using `clock`, or libnspire's default clock if `None`.
+    pub fn opts(clock: Option<&mut ClockData>) -> nspire_opts {
+        match clock {
+            Some(clock) => nspire_opts {
+                clock: Some(ClockData::now),
+                clock_data: clock as *mut ClockData as *mut c_void,
+            },
+            None => nspire_opts {
+                clock: None,
+                clock_data: null_mut(),
+            },
+        }
+    }
+}
+
+/// `time` as a CX II is sent it: the seconds since the Unix epoch, and the
+/// fraction of a second in units of 2^-64 seconds. `None` before the epoch.
+fn nnse_time(time: SystemTime) -> Option<(u32, u64)> {
+    let since_epoch = time.duration_since(UNIX_EPOCH).ok()?;
+    let frac = (u128::from(since_epoch.subsec_nanos()) << 64) / 1_000_000_000;
+    Some((since_epoch.as_secs() as u32, frac as u64))
+}
+
+/// Route libnspire's diagnostics through `tracing`. Only the first call does
+/// anything.
+pub fn init_log() {
+    static INIT: Once = Once::new();
+    INIT.call_once(|| unsafe { nspire_set_log(Some(log), null_mut()) });
+}
+
+unsafe extern "C" fn log(_data: *mut c_void, level: nspire_log_level, message: *const c_char) {
+    let message = CStr::from_ptr(message).to_string_lossy();
+    if level == nspire_log_level_NSPIRE_LOG_WARN {
+        tracing::warn!("{}", message);
+    } else {
+        tracing::debug!("{}", message);
+    }
+}
+
+/// Bytes, formatted as hex only if they are logged.
+struct Hex<'a>(&'a [u8]);
+
+impl fmt::Display for Hex<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for byte in self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(secs: u64, nanos: u32) -> SystemTime {
+        UNIX_EPOCH + Duration::new(secs, nanos)
+    }
+
+    #[test]
+    fn nnse_times() {
+        assert_eq!(nnse_time(at(0, 0)), Some((0, 0)));
+        assert_eq!(nnse_time(at(1_600_000_000, 0)), Some((1_600_000_000, 0)));
+        assert_eq!(nnse_time(at(5, 500_000_000)), Some((5, 1 << 63)));
+        // 2^64 * (1 - 10^-9), rounded down, so a nanosecond short of the
+        // next second is 2^64 / 10^9 short of overflowing
+        let (sec, frac) = nnse_time(at(5, 999_999_999)).unwrap();
+        assert_eq!(sec, 5);
+        assert_eq!(frac, 18_446_744_055_262_807_542);
+        assert_eq!(u64::MAX - frac, 18_446_744_073);
+        assert_eq!(nnse_time(UNIX_EPOCH - Duration::from_secs(1)), None);
+    }
+
+    #[test]
+    fn clock_sources() {
+        let fixed = at(1_600_000_000, 123);
+        assert_eq!(ClockSource::Fixed(fixed).now(), Some(fixed));
+        assert_eq!(ClockSource::DontSet.now(), None);
+
+        for &secs in &[0, 3600, -3600] {
+            let clock = match secs {
+                0 => ClockSource::Host,
+                secs => ClockSource::UtcOffset(secs),
+            };
+            let offset = Duration::from_secs(secs.unsigned_abs().into());
+            let shift = |time: SystemTime| {
+                if secs < 0 {
+                    time - offset
+                } else {
+                    time + offset
+                }
+            };
+            let before = SystemTime::now();
+            let now = clock.now().unwrap();
+            let after = SystemTime::now();
+            assert!(shift(before) <= now && now <= shift(after), "{:?}", clock);
+        }
+    }
+}
@@ -0,0 +1,440 @@
+//! Recording the packets exchanged with a calculator.
+//!
+//! [`Handle::start_capture`][crate::Handle::start_capture] records every frame
+//! sent or received until
+//! [`Handle::stop_capture`][crate::Handle::stop_capture], which returns them
+//! as a [`Capture`]. That can be saved as pcapng to open in Wireshark:
+//!
+//! ```
+//! use libnspire::capture::{Direction, LinkType};
+//! use libnspire::{sim::Simulator, Handle};
+//! # fn main() -> libnspire::Result<()> {
+//! let handle = Handle::<rusb::GlobalContext>::from_transport(Simulator::new())?;
+//! handle.start_capture();
+//! handle.info()?;
+//! let capture = handle.stop_capture();
+//! // Each request is acked, as is each reply
+//! assert_eq!(capture.frames()[0].direction, Direction::Sent);
+//! assert_eq!(capture.frames()[1].direction, Direction::Received);
+//!
+//! let mut pcapng = vec![];
+//! capture.write_pcapng(&mut pcapng, LinkType::User)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Frames are captured as they are on the wire. On a CX II, each NavNet
+//! packet is captured twice: once by itself, and once inside the NNSE message
+//! that carries it.
+
+use std::convert::TryInto;
+use std::io::{Read, Write};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::{Error, Result};
+
+/// The pcapng link type of NavNet packets with [`LinkType::User`]
+/// (`LINKTYPE_USER0`).
+pub const LINKTYPE_NAVNET: u16 = 147;
+/// The pcapng link type of NNSE messages with [`LinkType::User`]
+/// (`LINKTYPE_USER1`).
+pub const LINKTYPE_NNSE: u16 = 148;
+/// The pcapng link type of [`LinkType::Usb`] (`LINKTYPE_USB_LINUX_MMAPPED`).
+pub const LINKTYPE_USB: u16 = 220;
+/// The older usbmon link type, which [`Capture::read_pcapng`] also reads
+/// (`LINKTYPE_USB_LINUX`).
+const LINKTYPE_USB_OLD: u16 = 189;
+
+/// Which protocol a frame belongs to.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Protocol {
+    /// A packet in the protocol spoken by all calculators.
+    NavNet,
+    /// A message in the CX II's outer protocol, which carries NavNet
+    /// packets.
+    Nnse,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Direction {
+    /// From the computer to the calculator.
+    Sent,
+    /// From the calculator to the computer.
+    Received,
+}
+
+/// A packet or message, with when and which way it went.
+#[derive(Clone, Debug)]
+pub struct Frame {
+    pub time: SystemTime,
+    pub direction: Direction,
+    pub protocol: Protocol,
+    pub data: Vec<u8>,
+}
+
+/// How to present frames in a pcapng file.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LinkType {
+    /// Every frame, with NavNet packets on an interface with link type
+    /// [`LINKTYPE_NAVNET`] and NNSE messages on one with [`LINKTYPE_NNSE`].
+    /// Wireshark needs the [dissector][crate::dissector] for these.
+    User,
+    /// Only the frames that went over USB, as Linux usbmon bulk transfers.
+    /// This can be opened alongside real USB captures, but loses the NavNet
+    /// packets inside NNSE messages.
+    Usb,
+}
+
+/// Frames recorded by [`Handle::stop_capture`][crate::Handle::stop_capture].
+#[derive(Clone, Debug, Default)]
+pub struct Capture {
+    frames: Vec<Frame>,
+}
+
+impl Capture {
+    pub(crate) fn new(frames: Vec<Frame>) -> Self {
+        Capture { frames }
+    }
+
+    /// The frames, oldest first.
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    pub fn into_frames(self) -> Vec<Frame> {
+        self.frames
+    }
+
+    /// The frames that went over USB: NNSE messages from a CX II, or NavNet
+    /// packets from anything else.
+    pub fn wire_frames(&self) -> impl Iterator<Item = &Frame> {
+        let has_nnse = self.frames.iter().any(|f| f.protocol == Protocol::Nnse);
+        self.frames
+            .iter()
+            .filter(move |f| !has_nnse || f.protocol == Protocol::Nnse)
+    }
+
+    /// Read a pcapng file, such as one written by
+    /// [`write_pcapng`][Capture::write_pcapng] or a Wireshark capture of a
+    /// calculator on Linux. Only bulk transfers with data are kept from USB
+    /// captures.
+    // `usize::is_multiple_of` needs Rust 1.87
+    #[allow(clippy::manual_is_multiple_of)]
+    pub fn read_pcapng(mut r: impl Read) -> Result<Capture> {
+        let mut buf = vec![];
+        r.read_to_end(&mut buf).map_err(|_| Error::Io)?;
+        let mut buf = &buf[..];
+        let mut big_endian = false;
+        // The link type and timestamp units of each interface
+        let mut interfaces: Vec<(u16, f64)> = vec![];
+        let mut frames = vec![];
+        while !buf.is_empty() {
+            let kind = u32_at(buf, 0, false)?;
+            if kind == 0x0A0D_0D0A {
+                big_endian = match buf.get(8..12) {
+                    Some([0x1A, 0x2B, 0x3C, 0x4D]) => true,
+                    Some([0x4D, 0x3C, 0x2B, 0x1A]) => false,
+                    _ => return Err(Error::InvalidCapture),
+                };
+                interfaces.clear();
+            }
+            let len = u32_at(buf, 4, big_endian)? as usize;
+            if len < 12 || len % 4 != 0 || len > buf.len() {
+                return Err(Error::InvalidCapture);
+            }
+            let body = &buf[8..len - 4];
+            buf = &buf[len..];
+            match kind {
+                1 => {
+                    let link_type = u32_at(body, 0, big_endian)? as u16;
+                    let mut units = 1e-6;
+                    for (code, value) in options(body.get(8..).unwrap_or(&[]), big_endian) {
+                        // if_tsresol, when a power of 10
+                        if let (9, [resolution]) = (code, value) {
+                            if resolution & 0x80 == 0 {
+                                units = 10f64.powi(-i32::from(*resolution));
+                            }
+                        }
+                    }
+                    interfaces.push((link_type, units));
+                }
+                6 => {
+                    let interface = u32_at(body, 0, big_endian)? as usize;
+                    let &(link_type, units) =
+                        interfaces.get(interface).ok_or(Error::InvalidCapture)?;
+                    let ticks = (u64::from(u32_at(body, 4, big_endian)?) << 32)
+                        | u64::from(u32_at(body, 8, big_endian)?);
+                    let time = Duration::try_from_secs_f64(ticks as f64 * units)
+                        .ok()
+                        .and_then(|since| UNIX_EPOCH.checked_add(since))
+                        .ok_or(Error::InvalidCapture)?;
+                    let captured = u32_at(body, 12, big_endian)? as usize;
+                    let data = body.get(20..20 + captured).ok_or(Error::InvalidCapture)?;
+                    let mut direction = None;
+                    let padded = 20 + captured + (4 - captured % 4) % 4;
+                    for (code, value) in options(body.get(padded..).unwrap_or(&[]), big_endian) {
+                        match (code, u32_at(value, 0, big_endian).map(|flags| flags & 0b11)) {
+                            (2, Ok(0b01)) => direction = Some(Direction::Received),
+                            (2, Ok(0b10)) => direction = Some(Direction::Sent),
+                            _ => {}
+                        }
+                    }
+                    let frame = match link_type {
+                        LINKTYPE_NAVNET | LINKTYPE_NNSE => direction.map(|direction| Frame {
+                            time,
+                            direction,
+                            protocol: if link_type == LINKTYPE_NAVNET {
+                                Protocol::NavNet
+                            } else {
+                                Protocol::Nnse
+                            },
+                            data: data.to_vec(),
+                        }),
+                        LINKTYPE_USB => from_usbmon(data, 64, time),
+                        LINKTYPE_USB_OLD => from_usbmon(data, 48, time),
+                        _ => None,
+                    };
+                    frames.extend(frame);
+                }
+                _ => {}
+            }
+        }
+        Ok(Capture { frames })
+    }
+
+    /// Write the frames as a pcapng file.
+    pub fn write_pcapng(&self, mut w: impl Write, link_type: LinkType) -> Result<()> {
+        let mut buf = vec![];
+        // Section header: byte order magic, version 1.0, unknown length
+        let mut shb = vec![];
+        shb.extend_from_slice(&0x1A2B_3C4Du32.to_le_bytes());
+        shb.extend_from_slice(&1u16.to_le_bytes());
+        shb.extend_from_slice(&0u16.to_le_bytes());
+        shb.extend_from_slice(&(-1i64).to_le_bytes());
+        block(&mut buf, 0x0A0D_0D0A, &shb);
+
+        let interfaces: &[(u16, &str)] = match link_type {
+            LinkType::User => &[(LINKTYPE_NAVNET, "navnet"), (LINKTYPE_NNSE, "nnse")],
+            LinkType::Usb => &[(LINKTYPE_USB, "usb")],
+        };
+        for (link_type, name) in interfaces {
+            let mut idb = vec![];
+            idb.extend_from_slice(&link_type.to_le_bytes());
+            idb.extend_from_slice(&0u16.to_le_bytes());
+            // No snapshot length limit
+            idb.extend_from_slice(&0u32.to_le_bytes());
+            option(&mut idb, 2, name.as_bytes()); // if_name
+            option(&mut idb, 0, &[]);
+            block(&mut buf, 1, &idb);
+        }
+
+        let frames: Vec<_> = match link_type {
+            LinkType::User => self.frames.iter().collect(),
+            LinkType::Usb => self.wire_frames().collect(),
+        };
+        for (id, frame) in frames.into_iter().enumerate() {
+            let (interface, data) = match (link_type, frame.protocol) {
+                (LinkType::User, Protocol::NavNet) => (0u32, frame.data.clone()),
+                (LinkType::User, Protocol::Nnse) => (1, frame.data.clone()),
+                (LinkType::Usb, _) => (0, usbmon(id as u64, frame)),
+            };
+            let micros = frame
+                .time
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_micros() as u64;
+            let mut epb = vec![];
+            epb.extend_from_slice(&interface.to_le_bytes());
+            epb.extend_from_slice(&((micros >> 32) as u32).to_le_bytes());
+            epb.extend_from_slice(&(micros as u32).to_le_bytes());
+            epb.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            epb.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            pad(&mut epb, &data);
+            let flags: u32 = match frame.direction {
+                Direction::Received => 0b01,
+                Direction::Sent => 0b10,
+            };
+            option(&mut epb, 2, &flags.to_le_bytes()); // epb_flags
+            option(&mut epb, 0, &[]);
+            block(&mut buf, 6, &epb);
+        }
+        w.write_all(&buf).map_err(|_| Error::Io)
+    }
+}
+
+/// Append a pcapng block whose body is `body`, already padded.
+fn block(buf: &mut Vec<u8>, kind: u32, body: &[u8]) {
+    let len = (body.len() + 12) as u32;
+    buf.extend_from_slice(&kind.to_le_bytes());
+    buf.extend_from_slice(&len.to_le_bytes());
+    buf.extend_from_slice(body);
+    buf.extend_from_slice(&len.to_le_bytes());
+}
+
+/// Append a pcapng option. Code 0 ends the list.
+fn option(buf: &mut Vec<u8>, code: u16, value: &[u8]) {
+    buf.extend_from_slice(&code.to_le_bytes());
+    buf.extend_from_slice(&(value.len() as u16).to_le_bytes());
+    pad(buf, value);
+}
+
+/// Append `data`, padded to a multiple of 4 bytes.
+fn pad(buf: &mut Vec<u8>, data: &[u8]) {
+    buf.extend_from_slice(data);
+    buf.resize(buf.len() + (4 - data.len() % 4) % 4, 0);
+}
+
+fn u32_at(buf: &[u8], offset: usize, big_endian: bool) -> Result<u32> {
+    let bytes = buf
+        .get(offset..offset + 4)
+        .ok_or(Error::InvalidCapture)?
+        .try_into()
+        .unwrap();
+    Ok(if big_endian {
+        u32::from_be_bytes(bytes)
+    } else {
+        u32::from_le_bytes(bytes)
+    })
+}
+
+/// The code and value of each pcapng option in `buf`.
+fn options(mut buf: &[u8], big_endian: bool) -> Vec<(u16, &[u8])> {
+    let mut options = vec![];
+    while buf.len() >= 4 {
+        let (code, len) = if big_endian {
+            (
+                u16::from_be_bytes([buf[0], buf[1]]),
+                u16::from_be_bytes([buf[2], buf[3]]),
+            )
+        } else {
+            (
+                u16::from_le_bytes([buf[0], buf[1]]),
+                u16::from_le_bytes([buf[2], buf[3]]),
+            )
+        };
+        let len = len as usize;
+        match buf.get(4..4 + len) {
+            Some(value) if code != 0 => options.push((code, value)),
+            _ => break,
+        }
+        buf = &buf[(4 + len + (4 - len % 4) % 4).min(buf.len())..];
+    }
+    options
+}
+
+/// The data of a Linux usbmon bulk transfer with a `header_len`-byte header,
+/// as a frame. Returns `None` for other events.
+fn from_usbmon(event: &[u8], header_len: usize, time: SystemTime) -> Option<Frame> {
+    let data = event.get(header_len..).filter(|data| !data.is_empty())?;
+    let (kind, transfer, endpoint) = (event[8], event[9], event[10]);
+    let direction = match (kind, endpoint & 0x80) {
+        (b'S', 0) => Direction::Sent,
+        (b'C', 0x80) => Direction::Received,
+        _ => return None,
+    };
+    if transfer != 3 {
+        return None;
+    }
+    let protocol = match data.get(..2) {
+        Some(magic) if magic == crate::navnet::MAGIC.to_be_bytes() => Protocol::NavNet,
+        _ => Protocol::Nnse,
+    };
+    Some(Frame {
+        time,
+        direction,
+        protocol,
+        data: data.to_vec(),
+    })
+}
+
+/// A frame as a Linux usbmon bulk transfer: a submission for frames sent and
+/// a completion for frames received, each with the data attached.
+fn usbmon(id: u64, frame: &Frame) -> Vec<u8> {
+    let time = frame.time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let (kind, endpoint) = match frame.direction {
+        Direction::Sent => (b'S', 0x01),
+        Direction::Received => (b'C', 0x81),
+    };
+    let len = frame.data.len() as u32;
+    let mut buf = Vec::with_capacity(64 + frame.data.len());
+    buf.extend_from_slice(&id.to_le_bytes());
+    buf.push(kind);
+    buf.push(3); // Bulk
+    buf.push(endpoint);
+    buf.push(0); // Device number
+    buf.extend_from_slice(&0u16.to_le_bytes()); // Bus number
+    buf.push(b'-'); // No setup packet
+    buf.push(0); // Data present
+    buf.extend_from_slice(&(time.as_secs() as i64).to_le_bytes());
+    buf.extend_from_slice(&(time.subsec_micros() as i32).to_le_bytes());
+    buf.extend_from_slice(&0i32.to_le_bytes()); // Status
+    buf.extend_from_slice(&len.to_le_bytes());
+    buf.extend_from_slice(&len.to_le_bytes());
+    // Setup packet, interval, start frame, transfer flags and descriptors
+    buf.resize(64, 0);
+    buf.extend_from_slice(&frame.data);
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A capture with one NavNet packet sent `ticks` seconds after 1970.
+    fn capture(ticks: u64) -> Vec<u8> {
+        let mut buf = vec![];
+        let mut shb = vec![];
+        shb.extend_from_slice(&0x1A2B_3C4Du32.to_le_bytes());
+        shb.extend_from_slice(&[1, 0, 0, 0]);
+        shb.extend_from_slice(&(-1i64).to_le_bytes());
+        block(&mut buf, 0x0A0D_0D0A, &shb);
+        let mut idb = vec![];
+        idb.extend_from_slice(&u32::from(LINKTYPE_NAVNET).to_le_bytes());
+        idb.extend_from_slice(&0u32.to_le_bytes());
+        option(&mut idb, 9, &[0]); // if_tsresol: seconds
+        option(&mut idb, 0, &[]);
+        block(&mut buf, 1, &idb);
+        let mut epb = vec![];
+        epb.extend_from_slice(&0u32.to_le_bytes());
+        epb.extend_from_slice(&((ticks >> 32) as u32).to_le_bytes());
+        epb.extend_from_slice(&(ticks as u32).to_le_bytes());
+        epb.extend_from_slice(&1u32.to_le_bytes());
+        epb.extend_from_slice(&1u32.to_le_bytes());
+        pad(&mut epb, &[0x42]);
+        option(&mut epb, 2, &0b10u32.to_le_bytes());
+        option(&mut epb, 0, &[]);
+        block(&mut buf, 6, &epb);
+        buf
+    }
+
+    #[test]
+    fn timestamps() {
+        let capture = Capture::read_pcapng(&capture(1_600_000_000)[..]).unwrap();
+        let frame = &capture.frames()[0];
+        assert_eq!(frame.time, UNIX_EPOCH + Duration::from_secs(1_600_000_000));
+        assert_eq!(frame.direction, Direction::Sent);
+        assert_eq!(frame.data, [0x42]);
+    }
+
+    #[test]
+    fn timestamps_out_of_range() {
+        // Too big for a `Duration`, then for a `SystemTime`
+        for &ticks in &[u64::MAX, 1 << 63] {
+            assert!(matches!(
+                Capture::read_pcapng(&capture(ticks)[..]),
+                Err(Error::InvalidCapture)
+            ));
+        }
+    }
+
+    #[test]
+    fn truncated() {
+        let buf = capture(0);
+        for len in &[4, 12, buf.len() - 4] {
+            assert!(matches!(
+                Capture::read_pcapng(&buf[..*len]),
+                Err(Error::InvalidCapture)
+            ));
+        }
+    }
+}
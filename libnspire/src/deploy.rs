@@ -0,0 +1,324 @@
+//! Installing an OS on many calculators at once.
+//!
+//! ```no_run
+//! # use libnspire::{deploy::Deployment, os_image::OsImage};
+//! # fn main() -> libnspire::Result<()> {
+//! let context = rusb::Context::new()?;
+//! let image = OsImage::from_bytes(std::fs::read("TI-Nspire.tco").unwrap())?;
+//! let handles = libnspire::deploy::connect_all(&context)?
+//!     .into_iter()
+//!     .filter_map(|handle| handle.map_err(|e| eprintln!("{}", e)).ok())
+//!     .collect();
+//! for report in Deployment::new(&image).run(&context, handles, &|_, _| {}) {
+//!     println!("{}: {:?}", report.id.unwrap_or_default(), report.outcome);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rusb::UsbContext;
+
+use crate::info::Info;
+use crate::os_image::{OsCompat, OsImage, OsProgress};
+use crate::{Error, Handle, Result, PID, PID_CX2, VID};
+
+/// How long to wait between attempts to find a calculator after it reboots.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// What happened to a single calculator during a deployment.
+#[derive(Debug)]
+pub enum Outcome {
+    /// The calculator was already running the OS version in the image.
+    Skipped,
+    /// The OS was installed and the calculator came back up running it.
+    Installed,
+    /// Something went wrong. The calculator may need attention.
+    Failed(Error),
+}
+
+/// The result of deploying an OS to a single calculator.
+#[derive(Debug)]
+pub struct Report {
+    /// The ID ("serial number") of the calculator, if it could be read.
+    pub id: Option<String>,
+    /// The calculator's info before the deployment, if it could be read.
+    pub before: Option<Info>,
+    /// The calculator's info after it rebooted into the new OS.
+    pub after: Option<Info>,
+    pub outcome: Outcome,
+}
+
+/// Finds a calculator again after it reboots, given its info from before.
+type Reconnect<'a> = dyn Fn(&Info) -> Result<Info> + Sync + 'a;
+
+/// Installs an OS image on several calculators in parallel, one thread per
+/// calculator.
+pub struct Deployment<'a> {
+    image: &'a OsImage,
+    reconnect_timeout: Duration,
+    allow_downgrade: bool,
+    reinstall: bool,
+    reconnect: Option<Box<Reconnect<'a>>>,
+}
+
+impl<'a> Deployment<'a> {
+    pub fn new(image: &'a OsImage) -> Self {
+        Deployment {
+            image,
+            reconnect_timeout: Duration::from_secs(300),
+            allow_downgrade: false,
+            reinstall: false,
+            reconnect: None,
+        }
+    }
+
+    /// How long to wait for a calculator to come back after installing the
+    /// OS. Defaults to 5 minutes.
+    pub fn reconnect_timeout(mut self, timeout: Duration) -> Self {
+        self.reconnect_timeout = timeout;
+        self
+    }
+
+    /// Install the image even if it is older than the OS on the calculator.
+    /// Defaults to `false`.
+    pub fn allow_downgrade(mut self, allow: bool) -> Self {
+        self.allow_downgrade = allow;
+        self
+    }
+
+    /// Install the image even if the calculator already runs that version.
+    /// Defaults to `false`.
+    pub fn reinstall(mut self, reinstall: bool) -> Self {
+        self.reinstall = reinstall;
+        self
+    }
+
+    /// Find calculators again after they reboot with `reconnect`, which is
+    /// given a calculator's info from before the install and returns its
+    /// info once it is running the new OS.
+    ///
+    /// By default, the USB port each calculator was on is watched until a
+    /// device shows up on it again, which only works for handles opened
+    /// with [`Handle::new`]. This is for calculators that aren't, such as
+    /// the [simulator][crate::sim::Simulator]. The reconnect timeout isn't
+    /// used.
+    pub fn reconnect_with(mut self, reconnect: impl Fn(&Info) -> Result<Info> + Sync + 'a) -> Self {
+        self.reconnect = Some(Box::new(reconnect));
+        self
+    }
+
+    /// Run the deployment, blocking until every calculator is done.
+    ///
+    /// `progress` is called with the index of the calculator in `handles`
+    /// along with its progress. It is called from several threads at once.
+    /// Reports are returned in the same order as `handles`. If the thread
+    /// deploying to a calculator panics, its outcome is
+    /// [`Error::Panicked`].
+    pub fn run<T: UsbContext + Send + Sync>(
+        &self,
+        context: &T,
+        handles: Vec<Handle<T>>,
+        progress: &(dyn Fn(usize, OsProgress) + Sync),
+    ) -> Vec<Report> {
+        thread::scope(|s| {
+            let threads: Vec<_> = handles
+                .into_iter()
+                .enumerate()
+                .map(|(i, handle)| {
+                    s.spawn(move || self.deploy_one(context, handle, &mut |p| progress(i, p)))
+                })
+                .collect();
+            threads
+                .into_iter()
+                .map(|thread| {
+                    thread.join().unwrap_or_else(|payload| Report {
+                        id: None,
+                        before: None,
+                        after: None,
+                        outcome: Outcome::Failed(Error::Panicked(panic_message(&*payload))),
+                    })
+                })
+                .collect()
+        })
+    }
+
+    fn deploy_one<T: UsbContext>(
+        &self,
+        context: &T,
+        handle: Handle<T>,
+        progress: &mut dyn FnMut(OsProgress),
+    ) -> Report {
+        let mut report = Report {
+            id: None,
+            before: None,
+            after: None,
+            outcome: Outcome::Skipped,
+        };
+        let before = match handle.info() {
+            Ok(info) => info,
+            Err(e) => {
+                report.outcome = Outcome::Failed(e);
+                return report;
+            }
+        };
+        report.id = Some(before.id.clone());
+        let compat = self.image.check_compat(&before);
+        report.before = Some(before);
+        match compat {
+            Ok(OsCompat::Reinstall) if !self.reinstall => return report,
+            Ok(OsCompat::Downgrade) if !self.allow_downgrade => {
+                report.outcome = Outcome::Failed(Error::OsVersionMismatch {
+                    expected: self.image.version(),
+                    found: report.before.as_ref().unwrap().version,
+                });
+                return report;
+            }
+            Ok(_) => {}
+            Err(e) => {
+                report.outcome = Outcome::Failed(e);
+                return report;
+            }
+        }
+
+        let before = report.before.as_ref().unwrap();
+        let after = match &self.reconnect {
+            Some(reconnect) => handle.send_os(self.image.data(), progress).and_then(|()| {
+                drop(handle);
+                reconnect(before)
+            }),
+            None => self.install_usb(context, handle, before, progress),
+        };
+        report.outcome = match after {
+            Ok(info) if info.version == self.image.version() => {
+                report.after = Some(info);
+                Outcome::Installed
+            }
+            Ok(info) => {
+                let found = info.version;
+                report.after = Some(info);
+                Outcome::Failed(Error::OsVersionMismatch {
+                    expected: self.image.version(),
+                    found,
+                })
+            }
+            Err(e) => Outcome::Failed(e),
+        };
+        report
+    }
+
+    /// Install the OS on a calculator connected over USB, and wait for it to
+    /// come back on the same port.
+    fn install_usb<T: UsbContext>(
+        &self,
+        context: &T,
+        handle: Handle<T>,
+        before: &Info,
+        progress: &mut dyn FnMut(OsProgress),
+    ) -> Result<Info> {
+        let device = handle.usb_device().ok_or(Error::NotSupported)?.device();
+        let port = Port {
+            bus: device.bus_number(),
+            ports: device.port_numbers()?,
+            address: device.address(),
+        };
+        handle.send_os(self.image.data(), progress)?;
+        // Release the device so it can be opened again once it reboots
+        drop(handle);
+        reconnect(context, &port, &before.id, self.reconnect_timeout)
+    }
+}
+
+/// Where a calculator is plugged in.
+struct Port {
+    bus: u8,
+    ports: Vec<u8>,
+    /// The calculator's address on the bus. It gets a new one when it
+    /// reboots.
+    address: u8,
+}
+
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+/// Open every connected calculator, returning a result for each one so that
+/// one that can't be opened doesn't stop the others from being used.
+pub fn connect_all<T: UsbContext>(context: &T) -> Result<Vec<Result<Handle<T>>>> {
+    Ok(context
+        .devices()?
+        .iter()
+        .filter(|device| {
+            device
+                .device_descriptor()
+                .map(|desc| desc.vendor_id() == VID && matches!(desc.product_id(), PID | PID_CX2))
+                .unwrap_or(false)
+        })
+        .map(|device| Handle::new(device.open()?))
+        .collect())
+}
+
+/// Wait for the calculator plugged into `port` to reboot and come back, and
+/// read its info. Only the device on that port is touched, so calculators
+/// being updated by other threads aren't disturbed.
+///
+/// The calculator may still answer for a moment after the install finishes,
+/// so a device on the port is only opened once it has gone away, or turns up
+/// with a new address (which it gets when it's plugged back in).
+fn reconnect<T: UsbContext>(context: &T, port: &Port, id: &str, timeout: Duration) -> Result<Info> {
+    let start = Instant::now();
+    let mut last_err = Error::NoDevice;
+    let mut disconnected = false;
+    while start.elapsed() < timeout {
+        thread::sleep(POLL_INTERVAL);
+        let devices = match context.devices() {
+            Ok(devices) => devices,
+            Err(e) => {
+                last_err = e.into();
+                continue;
+            }
+        };
+        let device = devices.iter().find(|device| {
+            device.bus_number() == port.bus
+                && device.port_numbers().ok().as_deref() == Some(&port.ports[..])
+                && device
+                    .device_descriptor()
+                    .map(|desc| desc.vendor_id() == VID)
+                    .unwrap_or(false)
+        });
+        let device = match device {
+            Some(device) if disconnected || device.address() != port.address => device,
+            // Not rebooted yet
+            Some(_) => continue,
+            None => {
+                disconnected = true;
+                continue;
+            }
+        };
+        match device
+            .open()
+            .map_err(Error::from)
+            .and_then(Handle::new)
+            .and_then(|handle| handle.info())
+        {
+            Ok(info) if info.id == id => return Ok(info),
+            Ok(info) => {
+                return Err(Error::WrongDevice {
+                    expected: id.to_string(),
+                    found: info.id,
+                })
+            }
+            // The calculator is probably still booting
+            Err(e) => last_err = e,
+        }
+    }
+    Err(last_err)
+}
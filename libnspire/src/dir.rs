@@ -6,9 +6,12 @@ use std::mem::transmute;
 use std::ops::Deref;
 
 use libnspire_sys::{nspire_dir_info, nspire_dir_item, nspire_dir_type, nspire_dirlist_free};
+#[cfg(feature = "serde")]
+use serde::{ser::SerializeStruct, Serialize, Serializer};
 
 /// The type of entry: a file or directory.
 #[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub enum EntryType {
     File,
     Directory,
@@ -58,6 +61,18 @@ impl fmt::Debug for DirItem {
     }
 }
 
+#[cfg(feature = "serde")]
+impl Serialize for DirItem {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("DirItem", 4)?;
+        s.serialize_field("name", &self.name().to_string_lossy())?;
+        s.serialize_field("size", &self.size())?;
+        s.serialize_field("date", &self.date())?;
+        s.serialize_field("entry_type", &self.entry_type())?;
+        s.end()
+    }
+}
+
 impl From<nspire_dir_item> for DirItem {
     fn from(item: nspire_dir_item) -> Self {
         DirItem(item)
@@ -0,0 +1,328 @@
+//! A Wireshark dissector for the calculator protocols.
+//!
+//! [`lua`] generates it from the tables libnspire itself uses, so it always
+//! matches the library. Save it in Wireshark's personal Lua plugins
+//! directory, for example with the `dissector` example:
+//!
+//! ```sh
+//! cargo run --example dissector > ~/.local/lib/wireshark/plugins/nspire.lua
+//! ```
+//!
+//! It dissects [captures][crate::capture] saved by libnspire with either
+//! [`LinkType`][crate::capture::LinkType], and NavNet and NNSE in USB
+//! captures of real calculators.
+
+use std::fmt::Write;
+
+use crate::capture::{LINKTYPE_NAVNET, LINKTYPE_NNSE};
+use crate::navnet::{self, Display, Field};
+use crate::nnse;
+
+/// The first link type Wireshark calls `USER0`.
+const LINKTYPE_USER0: u16 = 147;
+
+/// Generate the dissector, as a Lua plugin.
+pub fn lua() -> String {
+    let mut lua = format!(
+        "-- Wireshark dissector for TI-Nspire calculators, generated by libnspire {}.\n\
+         -- Don't edit this file; regenerate it instead.\n\n",
+        env!("CARGO_PKG_VERSION"),
+    );
+    lua += HELPERS;
+
+    let big_size = "ProtoField.uint32(\"navnet.big_size\", \"Big data size\", base.DEC)";
+    proto(
+        &mut lua,
+        "navnet",
+        "TI-Nspire NavNet",
+        navnet::HEADER,
+        &[("big_size", big_size)],
+    );
+    let _ = writeln!(
+        lua,
+        "local navnet_magic = {}\nlocal navnet_big_data = {}\n\
+         local navnet_header_size = {}\n\
+         local navnet_services = {}\n",
+        hex(navnet::MAGIC.into()),
+        hex(navnet::BIG_DATA.into()),
+        navnet::HEADER_SIZE,
+        names(navnet::SERVICES),
+    );
+    lua += NAVNET;
+
+    proto(&mut lua, "nnse", "TI-Nspire NNSE", nnse::HEADER, &[]);
+    let _ = writeln!(
+        lua,
+        "local nnse_header_size = {}\nlocal nnse_ack_flag = {}\n\
+         local nnse_stream = {}\nlocal nnse_services = {}\n",
+        nnse::HEADER_SIZE,
+        hex(nnse::ACK_FLAG.into()),
+        hex(nnse::SVC_STREAM.into()),
+        names(nnse::SERVICES),
+    );
+    lua += NNSE;
+
+    let _ = write!(
+        lua,
+        "local encaps = wtap_encaps or wtap\n\
+         local wtap_encap = DissectorTable.get(\"wtap_encap\")\n\
+         wtap_encap:add(encaps.USER{}, navnet)\n\
+         wtap_encap:add(encaps.USER{}, nnse)\n\
+         navnet:register_heuristic(\"usb.bulk\", navnet_heuristic)\n\
+         nnse:register_heuristic(\"usb.bulk\", nnse_heuristic)\n",
+        LINKTYPE_NAVNET - LINKTYPE_USER0,
+        LINKTYPE_NNSE - LINKTYPE_USER0,
+    );
+    lua
+}
+
+/// Declare a protocol called `name` with a field for each of `header`, one
+/// for the data after it, and the `extra` fields, named `{name}_{extra}`.
+fn proto(
+    lua: &mut String,
+    name: &str,
+    description: &str,
+    header: &[Field],
+    extra: &[(&str, &str)],
+) {
+    let _ = writeln!(
+        lua,
+        "local {0} = Proto(\"{0}\", \"{1}\")",
+        name, description
+    );
+    let _ = writeln!(lua, "local {}_header = {{", name);
+    for field in header {
+        let (base, names) = match &field.display {
+            Display::Hex => ("base.HEX", String::from("nil")),
+            Display::Decimal => ("base.DEC", String::from("nil")),
+            Display::Names(values) => ("base.HEX", self::names(values)),
+            Display::Flagged { names, flag, label } => {
+                let flagged: Vec<_> = names
+                    .iter()
+                    .flat_map(|&(value, name)| {
+                        vec![
+                            (value, name.to_string()),
+                            (value | flag, format!("{} ({})", name, label)),
+                        ]
+                    })
+                    .collect();
+                let flagged: Vec<_> = flagged.iter().map(|(v, n)| (*v, n.as_str())).collect();
+                ("base.HEX", self::names(&flagged))
+            }
+        };
+        let _ = writeln!(
+            lua,
+            "\t{{ name = \"{name}\", size = {size}, field = ProtoField.uint{bits}(\"{proto}.{name}\", \"{label}\", {base}, {names}) }},",
+            name = field.name,
+            size = field.size,
+            bits = field.size * 8,
+            proto = name,
+            label = field.label,
+            base = base,
+            names = names,
+        );
+    }
+    let _ = writeln!(lua, "}}");
+    let mut others = vec![format!("{}_data", name)];
+    let _ = writeln!(
+        lua,
+        "local {0}_data = ProtoField.bytes(\"{0}.data\", \"Data\")",
+        name
+    );
+    for (field, definition) in extra {
+        let _ = writeln!(lua, "local {}_{} = {}", name, field, definition);
+        others.push(format!("{}_{}", name, field));
+    }
+    let _ = writeln!(
+        lua,
+        "{}.fields = fields({}_header, {{ {} }})\n",
+        name,
+        name,
+        others.join(", ")
+    );
+}
+
+/// A Lua table of names.
+fn names(values: &[(u16, &str)]) -> String {
+    let entries: Vec<_> = values
+        .iter()
+        .map(|(value, name)| format!("[{}] = \"{}\"", hex((*value).into()), name))
+        .collect();
+    format!("{{ {} }}", entries.join(", "))
+}
+
+fn hex(value: u32) -> String {
+    format!("0x{:X}", value)
+}
+
+/// Shared by both protocols. Only uses arithmetic, as the bit operations
+/// available depend on Wireshark's Lua version.
+const HELPERS: &str = r#"local function xor(a, b)
+	local result, bit = 0, 1
+	while a > 0 or b > 0 do
+		if a % 2 ~= b % 2 then
+			result = result + bit
+		end
+		a, b, bit = math.floor(a / 2), math.floor(b / 2), bit * 2
+	end
+	return result
+end
+
+-- All the ProtoFields of a header, followed by any others
+local function fields(header, others)
+	local all = {}
+	for _, f in ipairs(header) do
+		table.insert(all, f.field)
+	end
+	for _, f in ipairs(others) do
+		table.insert(all, f)
+	end
+	return all
+end
+
+-- Where a header field starts
+local function offset_of(header, name)
+	local offset = 0
+	for _, f in ipairs(header) do
+		if f.name == name then
+			return offset
+		end
+		offset = offset + f.size
+	end
+end
+
+-- Add a header to the tree, returning the value of each field by name
+local function add_header(buf, tree, header)
+	local values, offset = {}, 0
+	for _, f in ipairs(header) do
+		local range = buf(offset, f.size)
+		tree:add(f.field, range)
+		values[f.name] = range:uint()
+		offset = offset + f.size
+	end
+	return values
+end
+
+local function name(names, value)
+	return names[value] or string.format("0x%04X", value)
+end
+
+"#;
+
+const NAVNET: &str = r#"local function navnet_header_checksum(buf)
+	local sum = 0
+	for i = 0, navnet_header_size - 2 do
+		sum = (sum + buf(i, 1):uint()) % 256
+	end
+	return sum
+end
+
+local function navnet_data_checksum(buf, offset, len)
+	local sum = 0
+	for i = offset, offset + len - 1 do
+		local tmp1 = buf(i, 1):uint() * 256 + math.floor(sum / 256)
+		sum = sum % 256
+		local tmp2 = xor((sum % 16) * 16, sum) * 256
+		local tmp3 = math.floor(tmp2 / 32)
+		sum = xor(math.floor(tmp3 / 128), xor(tmp1, xor(tmp2, tmp3)))
+	end
+	return sum
+end
+
+function navnet.dissector(buf, pinfo, tree)
+	if buf:len() < navnet_header_size or buf(0, 2):uint() ~= navnet_magic then
+		return 0
+	end
+	pinfo.cols.protocol = "NavNet"
+	local subtree = tree:add(navnet, buf())
+	local h = add_header(buf, subtree, navnet_header)
+	if navnet_header_checksum(buf) ~= h.header_csum then
+		subtree:add_expert_info(PI_CHECKSUM, PI_ERROR, "Bad header checksum")
+	end
+
+	local offset, size = navnet_header_size, h.data_size
+	local checked = size
+	if size == navnet_big_data and buf:len() >= offset + 4 then
+		size = buf(offset, 4):uint()
+		subtree:add(navnet_big_size, buf(offset, 4))
+		offset = offset + 4
+		checked = size + 4
+	end
+	local available = math.min(checked, buf:len() - navnet_header_size)
+	if available < checked then
+		subtree:add_expert_info(PI_MALFORMED, PI_ERROR, "Truncated data")
+	elseif navnet_data_checksum(buf, navnet_header_size, checked) ~= h.data_csum then
+		subtree:add_expert_info(PI_CHECKSUM, PI_ERROR, "Bad data checksum")
+	end
+	if size > 0 and offset < buf:len() then
+		subtree:add(navnet_data, buf(offset, math.min(size, buf:len() - offset)))
+	end
+
+	pinfo.cols.info = string.format("%s → %s, seq %d",
+		name(navnet_services, h.src_sid), name(navnet_services, h.dst_sid), h.seq)
+	return buf:len()
+end
+
+local function navnet_heuristic(buf, pinfo, tree)
+	if buf:len() < navnet_header_size or buf(0, 2):uint() ~= navnet_magic
+		or navnet_header_checksum(buf) ~= buf(navnet_header_size - 1, 1):uint() then
+		return false
+	end
+	navnet.dissector(buf, pinfo, tree)
+	return true
+end
+
+"#;
+
+const NNSE: &str = r#"-- The 16-bit one's complement sum, which is 0xFFFF for intact messages
+local function nnse_checksum(buf, len)
+	local sum = 0
+	for i = 0, len - 1 do
+		local byte = buf(i, 1):uint()
+		sum = sum + (i % 2 == 0 and byte * 256 or byte)
+	end
+	while sum > 0xFFFF do
+		sum = math.floor(sum / 0x10000) + sum % 0x10000
+	end
+	return sum
+end
+
+function nnse.dissector(buf, pinfo, tree)
+	if buf:len() < nnse_header_size then
+		return 0
+	end
+	local subtree = tree:add(nnse, buf())
+	local h = add_header(buf, subtree, nnse_header)
+	local len = math.min(h.length, buf:len())
+	if len < h.length then
+		subtree:add_expert_info(PI_MALFORMED, PI_ERROR, "Truncated message")
+	elseif nnse_checksum(buf, len) ~= 0xFFFF then
+		subtree:add_expert_info(PI_CHECKSUM, PI_ERROR, "Bad checksum")
+	end
+
+	local service = h.service % nnse_ack_flag
+	local is_ack = h.service >= nnse_ack_flag
+	pinfo.cols.protocol = "NNSE"
+	pinfo.cols.info = string.format("%s%s, seq %d",
+		name(nnse_services, service), is_ack and " ack" or "", h.seqno)
+	if len > nnse_header_size then
+		local data = buf(nnse_header_size, len - nnse_header_size)
+		subtree:add(nnse_data, data)
+		if service == nnse_stream and not is_ack then
+			navnet.dissector(data:tvb(), pinfo, tree)
+		end
+	end
+	return buf:len()
+end
+
+local function nnse_heuristic(buf, pinfo, tree)
+	if buf:len() < nnse_header_size
+		or buf(offset_of(nnse_header, "length"), 2):uint() ~= buf:len()
+		or nnse_checksum(buf, buf:len()) ~= 0xFFFF then
+		return false
+	end
+	nnse.dissector(buf, pinfo, tree)
+	return true
+end
+
+"#;
@@ -43,15 +43,74 @@ pub enum Error {
     Usb(#[from] rusb::Error),
     /// Unknown bits-per-pixel value: `{0}`
     UnknownBpp(u8),
+    /// Not a valid OS image
+    InvalidOsImage,
+    /// OS image is for `{found}`, but the calculator expects `{expected}`
+    OsMismatch { expected: String, found: String },
+    /// OS installation failed with status `{0:#04x}`
+    OsInstallFailed(u8),
+    /// Calculator is running OS `{found}`, expected `{expected}`
+    OsVersionMismatch {
+        expected: crate::info::Version,
+        found: crate::info::Version,
+    },
+    /// Not a valid TNS document
+    InvalidTns,
+    /// TNS document content is encrypted
+    EncryptedTns,
+    /// Found calculator `{found}` instead of `{expected}`
+    WrongDevice { expected: String, found: String },
+    /// Not a valid pcapng capture
+    InvalidCapture,
+    /// The host diverged from the recorded session at frame `{0}`
+    ReplayDiverged(usize),
+    /// The host stopped before replaying `{0}` recorded frames
+    ReplayIncomplete(usize),
+    /// Can't type `{0:?}` on the keypad
+    Untypable(char),
+    /// File read back wrong from byte `{offset}` (`{found}` of `{expected}` bytes)
+    VerificationFailed {
+        /// Where the file first differs. If one is a prefix of the other,
+        /// this is the length of the shorter one.
+        offset: usize,
+        /// The size written.
+        expected: usize,
+        /// The size read back.
+        found: usize,
+    },
+    /// Panicked: `{0}`
+    Panicked(String),
     /// unknown error
     Unknown,
 }
 
+impl Error {
+    /// The libnspire error code closest to this error, for handing back to C.
+    pub(crate) fn code(&self) -> c_int {
+        use libnspire_sys::*;
+        let code = match self {
+            Error::Timeout | Error::Usb(rusb::Error::Timeout) => NSPIRE_ERR_TIMEOUT,
+            Error::OutOfMemory | Error::Usb(rusb::Error::NoMem) => NSPIRE_ERR_NOMEM,
+            Error::NoDevice | Error::Usb(rusb::Error::NoDevice) => NSPIRE_ERR_NODEVICE,
+            Error::InvalidPacket => NSPIRE_ERR_INVALPKT,
+            Error::Nack => NSPIRE_ERR_NACK,
+            Error::Busy | Error::Usb(rusb::Error::Busy) => NSPIRE_ERR_BUSY,
+            Error::Invalid => NSPIRE_ERR_INVALID,
+            Error::Exists => NSPIRE_ERR_EXISTS,
+            Error::DoesNotExist => NSPIRE_ERR_NONEXIST,
+            Error::OsInstallFailed(_) => NSPIRE_ERR_OSFAILED,
+            Error::NotSupported | Error::Usb(rusb::Error::NotSupported) => NSPIRE_ERR_NOTSUPPORTED,
+            _ => NSPIRE_ERR_LIBUSB,
+        };
+        -(code as c_int)
+    }
+}
+
 pub(crate) fn err(code: c_int) -> Result<()> {
     use libnspire_sys::*;
-    use libusb1_sys::constants::*;
+    // libnspire isn't consistent about the sign of the error codes it returns
     #[forbid(unreachable_patterns)]
-    match code as c_uint {
+    match code.unsigned_abs() as c_uint {
         NSPIRE_ERR_SUCCESS => Ok(()),
         NSPIRE_ERR_TIMEOUT => Err(Error::Timeout),
         NSPIRE_ERR_NOMEM => Err(Error::OutOfMemory),
@@ -63,20 +122,8 @@ pub(crate) fn err(code: c_int) -> Result<()> {
         NSPIRE_ERR_INVALID => Err(Error::Invalid),
         NSPIRE_ERR_EXISTS => Err(Error::Exists),
         NSPIRE_ERR_NONEXIST => Err(Error::DoesNotExist),
-        _ => match code {
-            LIBUSB_ERROR_IO => Err(Error::Io),
-            LIBUSB_ERROR_INVALID_PARAM => Err(Error::Invalid),
-            LIBUSB_ERROR_ACCESS => Err(Error::Access),
-            LIBUSB_ERROR_NO_DEVICE => Err(Error::NoDevice),
-            LIBUSB_ERROR_NOT_FOUND => Err(Error::NoDevice),
-            LIBUSB_ERROR_BUSY => Err(Error::Busy),
-            LIBUSB_ERROR_TIMEOUT => Err(Error::Timeout),
-            LIBUSB_ERROR_OVERFLOW => Err(Error::LibUsb),
-            LIBUSB_ERROR_PIPE => Err(Error::LibUsb),
-            LIBUSB_ERROR_INTERRUPTED => Err(Error::LibUsb),
-            LIBUSB_ERROR_NO_MEM => Err(Error::OutOfMemory),
-            LIBUSB_ERROR_NOT_SUPPORTED => Err(Error::NotSupported),
-            _ => Err(Error::Unknown),
-        },
+        NSPIRE_ERR_OSFAILED => Err(Error::OsInstallFailed(0)),
+        NSPIRE_ERR_NOTSUPPORTED => Err(Error::NotSupported),
+        _ => Err(Error::Unknown),
     }
 }
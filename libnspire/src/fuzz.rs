@@ -0,0 +1,182 @@
+//! Entry points for the `cargo fuzz` targets in `fuzz/`.
+//!
+//! Each one plays a calculator that answers with the fuzzer's bytes, and
+//! drives the parser under test through a [`Handle`]. Errors are expected;
+//! panics, hangs and out of bounds accesses are bugs.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use crate::navnet::{
+    Packet, DEVICE_ADDR, HOST_ADDR, SID_ACK, SID_ACK_ZERO, SID_ADDR_ASSIGN, SID_DISCONNECT,
+};
+use crate::transport::Transport;
+use crate::{Error, Handle, Result};
+
+type FuzzHandle = Handle<rusb::GlobalContext>;
+
+/// NavNet packets, as sent by a calculator after the handshake.
+pub fn navnet(data: &[u8]) {
+    if let Some(packet) = Packet::decode(data) {
+        assert_eq!(Packet::decode(&packet.encode()), Some(packet));
+    }
+    if let Ok(handle) = FuzzHandle::from_transport(Device::new(data, false)) {
+        let _ = handle.info();
+        let _ = handle.list_dir("/");
+    }
+}
+
+/// NNSE messages, as sent by a CX II from the moment it's plugged in.
+pub fn nnse(data: &[u8]) {
+    if let Ok(handle) = FuzzHandle::from_transport(Messages(chunks(data).collect())) {
+        let _ = handle.info();
+    }
+}
+
+/// Replies to a directory listing.
+pub fn dir_entry(data: &[u8]) {
+    if let Ok(handle) = FuzzHandle::from_transport(Device::new(data, true)) {
+        let _ = handle.list_dir("/");
+    }
+}
+
+/// Replies to a device info request.
+pub fn devinfo(data: &[u8]) {
+    if let Ok(handle) = FuzzHandle::from_transport(Device::new(data, true)) {
+        let _ = handle.info();
+    }
+}
+
+/// Replies to a screenshot request, the RLE data included.
+pub fn screenshot(data: &[u8]) {
+    if let Ok(handle) = FuzzHandle::from_transport(Device::new(data, true)) {
+        let _ = handle.screenshot();
+    }
+}
+
+/// Split fuzz input into pieces, each preceded by its length as a big-endian
+/// `u16`.
+fn chunks(mut data: &[u8]) -> impl Iterator<Item = Vec<u8>> + '_ {
+    std::iter::from_fn(move || {
+        let (len, rest) = match data {
+            [high, low, rest @ ..] => (u16::from_be_bytes([*high, *low]) as usize, rest),
+            _ => return None,
+        };
+        let (chunk, rest) = rest.split_at(len.min(rest.len()));
+        data = rest;
+        Some(chunk.to_vec())
+    })
+}
+
+/// A NavNet calculator that connects and acknowledges packets properly, but
+/// replies to requests with the next chunk of fuzz input.
+struct Device {
+    replies: VecDeque<Vec<u8>>,
+    /// Whether to wrap replies in valid packets, or send them as they are.
+    wrap: bool,
+    /// Acknowledgements waiting to be read by the host.
+    outgoing: VecDeque<Vec<u8>>,
+    /// The service and host connection of the last request.
+    sids: (u16, u16),
+    seq: u8,
+}
+
+impl Device {
+    fn new(data: &[u8], wrap: bool) -> Self {
+        let mut device = Device {
+            replies: chunks(data).collect(),
+            wrap,
+            outgoing: VecDeque::new(),
+            sids: (SID_ADDR_ASSIGN, SID_ADDR_ASSIGN),
+            seq: 0,
+        };
+        let request = device.packet(
+            SID_ADDR_ASSIGN,
+            SID_ADDR_ASSIGN,
+            vec![0x64, 0x01, 0xFF, 0x00],
+        );
+        device.outgoing.push_back(request.encode());
+        device
+    }
+
+    fn packet(&mut self, src_sid: u16, dst_sid: u16, data: Vec<u8>) -> Packet {
+        self.seq = self.seq.wrapping_add(1).max(1);
+        Packet {
+            src_addr: DEVICE_ADDR,
+            src_sid,
+            dst_addr: HOST_ADDR,
+            dst_sid,
+            ack: 0,
+            seq: self.seq,
+            data,
+        }
+    }
+}
+
+impl Transport for Device {
+    fn read(&mut self, buf: &mut [u8], _timeout: Duration) -> Result<usize> {
+        let packet = match self.outgoing.pop_front() {
+            Some(packet) => packet,
+            None => {
+                let reply = self.replies.pop_front().ok_or(Error::Timeout)?;
+                if self.wrap {
+                    let (src_sid, dst_sid) = self.sids;
+                    self.packet(src_sid, dst_sid, reply).encode()
+                } else {
+                    reply
+                }
+            }
+        };
+        let len = packet.len().min(buf.len());
+        buf[..len].copy_from_slice(&packet[..len]);
+        Ok(len)
+    }
+
+    fn write(&mut self, buf: &[u8], _timeout: Duration) -> Result<usize> {
+        let packet = match Packet::decode(buf) {
+            Some(packet) if !packet.is_ack() && packet.dst_sid != SID_ADDR_ASSIGN => packet,
+            _ => return Ok(buf.len()),
+        };
+        // Disconnections are acknowledged to the connection being closed
+        let host_sid = match (packet.src_sid, &packet.data[..]) {
+            (SID_DISCONNECT, [high, low, ..]) => u16::from_be_bytes([*high, *low]),
+            _ => packet.src_sid,
+        };
+        let ack = Packet {
+            src_addr: DEVICE_ADDR,
+            src_sid: if packet.seq == 0 {
+                SID_ACK_ZERO
+            } else {
+                SID_ACK
+            },
+            dst_addr: HOST_ADDR,
+            dst_sid: host_sid,
+            ack: 0x0A,
+            seq: packet.seq,
+            data: packet.dst_sid.to_be_bytes().to_vec(),
+        };
+        self.outgoing.push_back(ack.encode());
+        self.sids = (packet.dst_sid, packet.src_sid);
+        Ok(buf.len())
+    }
+}
+
+/// A CX II that sends the fuzz input as its messages, and ignores the host.
+struct Messages(VecDeque<Vec<u8>>);
+
+impl Transport for Messages {
+    fn read(&mut self, buf: &mut [u8], _timeout: Duration) -> Result<usize> {
+        let message = self.0.pop_front().ok_or(Error::Timeout)?;
+        let len = message.len().min(buf.len());
+        buf[..len].copy_from_slice(&message[..len]);
+        Ok(len)
+    }
+
+    fn write(&mut self, buf: &[u8], _timeout: Duration) -> Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn is_cx_ii(&self) -> bool {
+        true
+    }
+}
@@ -2,11 +2,16 @@
 
 use libnspire_sys::{
     nspire_battery, nspire_devinfo, nspire_devinfo__bindgen_ty_3, nspire_devinfo__bindgen_ty_5,
-    nspire_runlevel, nspire_type,
+    nspire_devinfo__bindgen_ty_7, nspire_runlevel, nspire_type,
 };
 #[cfg(feature = "serde")]
 use serde::Serialize;
 use std::fmt;
+use std::str::FromStr;
+
+use crate::navnet;
+use crate::os_image::OsTarget;
+use crate::{Error, PID, PID_CX2};
 
 #[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
 #[cfg_attr(feature = "serde", derive(Serialize))]
@@ -20,15 +25,14 @@ pub enum HardwareType {
 
 impl HardwareType {
     /// Whether this model is a CAS model. This is the physical model, not the
-    /// software model: if CAS software has been installed on a non-CAS device,
-    /// this will still return `false`. Use the [name][crate::info::Info::name]
-    /// field and search for "CAS" instead.
+    /// software: if CAS software has been installed on a non-CAS device, this
+    /// still returns `false`, as does [`Model::is_cas`]. The OS extension in
+    /// [`Info::os_extension`] shows what the calculator is running.
     pub fn is_cas(&self) -> bool {
         matches!(self, HardwareType::Cas | HardwareType::CasCx)
     }
-    /// Whether this model is a CX or CX II model. Use
-    /// [`Handle::is_cx_ii`][crate::Handle::is_cx_ii] to determine if the
-    /// device is a CX II or not.
+    /// Whether this model is a CX or CX II model. Use [`Model`] to tell them
+    /// apart.
     pub fn is_cx(&self) -> bool {
         matches!(self, HardwareType::CasCx | HardwareType::NonCasCx)
     }
@@ -49,12 +53,142 @@ impl From<nspire_type> for HardwareType {
     }
 }
 
+/// A calculator model, as sold.
+///
+/// Found with [`Handle::model`][crate::Handle::model], or [`Model::detect`]
+/// given a calculator's [`Info`]:
+///
+/// ```
+/// use libnspire::{info::Model, sim::Simulator, Handle};
+/// # fn main() -> libnspire::Result<()> {
+/// let handle = Handle::<rusb::GlobalContext>::from_transport(Simulator::new())?;
+/// let model = handle.model()?;
+/// assert_eq!(model, Model::CxCas);
+/// assert!(model.has_color_screen() && !model.is_cx_ii());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum Model {
+    /// Original ("Classic") TI-Nspire, Clickpad or Touchpad.
+    Nspire,
+    /// Original ("Classic") TI-Nspire CAS, Clickpad or Touchpad.
+    NspireCas,
+    /// TI-Nspire CX.
+    Cx,
+    /// TI-Nspire CX CAS.
+    CxCas,
+    /// TI-Nspire CX II.
+    CxII,
+    /// TI-Nspire CX II CAS.
+    CxIICas,
+    /// TI-Nspire CX II-T.
+    CxIIT,
+}
+
+impl Model {
+    /// Work out the model from a calculator's hardware type and OS
+    /// extension, as found in its [`Info`], and whether it's a CX II, which
+    /// only shows in its USB product ID.
+    ///
+    /// The model is the hardware, so CAS software on a non-CAS calculator is
+    /// still reported as the non-CAS model. The OS extension is only used to
+    /// tell a CX II-T from a CX II, which it can't be told from otherwise,
+    /// and when the hardware type is unknown. Returns `None` if neither is
+    /// known.
+    ///
+    /// The hardware revision in [`Info::hw_version`] isn't taken, as no
+    /// mapping from hardware revisions to models is known, so it can't help
+    /// tell them apart.
+    pub fn detect(hw_type: HardwareType, os_extension: &str, is_cx_ii: bool) -> Option<Self> {
+        let os = OsTarget::from_extension(os_extension).filter(|os| os.is_cx_ii() == is_cx_ii);
+        let model = match (hw_type, is_cx_ii) {
+            (HardwareType::Cas, false) => Model::NspireCas,
+            (HardwareType::NonCas, false) => Model::Nspire,
+            (HardwareType::CasCx, false) => Model::CxCas,
+            (HardwareType::NonCasCx, false) => Model::Cx,
+            (HardwareType::CasCx, true) => Model::CxIICas,
+            (HardwareType::NonCasCx, true) => Model::CxII,
+            (HardwareType::Unknown(_), _) => os?.into(),
+            _ => return None,
+        };
+        Some(match (model, os) {
+            (Model::CxII, Some(OsTarget::CxIIT)) => Model::CxIIT,
+            _ => model,
+        })
+    }
+    /// The kind of OS image this model accepts.
+    pub fn os_target(&self) -> OsTarget {
+        match self {
+            Model::Nspire => OsTarget::Nspire,
+            Model::NspireCas => OsTarget::NspireCas,
+            Model::Cx => OsTarget::Cx,
+            Model::CxCas => OsTarget::CxCas,
+            Model::CxII => OsTarget::CxII,
+            Model::CxIICas => OsTarget::CxIICas,
+            Model::CxIIT => OsTarget::CxIIT,
+        }
+    }
+    /// Whether this is a CAS model.
+    pub fn is_cas(&self) -> bool {
+        self.os_target().is_cas()
+    }
+    /// Whether this model has a color screen, like all CX and CX II models.
+    pub fn has_color_screen(&self) -> bool {
+        self.os_target().is_cx()
+    }
+    /// Whether this is a CX II model, which speaks a different protocol over
+    /// USB.
+    pub fn is_cx_ii(&self) -> bool {
+        self.os_target().is_cx_ii()
+    }
+    /// The USB product ID this model uses.
+    pub fn product_id(&self) -> u16 {
+        if self.is_cx_ii() {
+            PID_CX2
+        } else {
+            PID
+        }
+    }
+    /// The most data a single packet to or from this model can carry.
+    pub fn max_packet_size(&self) -> usize {
+        if self.is_cx_ii() {
+            navnet::MAX_DATA_CX_II
+        } else {
+            navnet::MAX_DATA
+        }
+    }
+}
+
+impl From<OsTarget> for Model {
+    fn from(target: OsTarget) -> Self {
+        match target {
+            OsTarget::Nspire => Model::Nspire,
+            OsTarget::NspireCas => Model::NspireCas,
+            OsTarget::Cx => Model::Cx,
+            OsTarget::CxCas => Model::CxCas,
+            OsTarget::CxII => Model::CxII,
+            OsTarget::CxIICas => Model::CxIICas,
+            OsTarget::CxIIT => Model::CxIIT,
+        }
+    }
+}
+
+impl fmt::Display for Model {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.os_target().fmt(f)
+    }
+}
+
 #[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
 #[cfg_attr(feature = "serde", derive(Serialize))]
 pub enum Battery {
     Powered,
     Low,
     Ok,
+    /// The calculator doesn't know its battery level.
+    Unavailable,
     Unknown(u8),
 }
 
@@ -67,6 +201,7 @@ impl From<nspire_battery> for Battery {
             nspire_battery_NSPIRE_BATT_POWERED => Battery::Powered,
             nspire_battery_NSPIRE_BATT_OK => Battery::Ok,
             nspire_battery_NSPIRE_BATT_LOW => Battery::Low,
+            nspire_battery_NSPIRE_BATT_UNKNOWN => Battery::Unavailable,
             v => Battery::Unknown(v as u8),
         }
     }
@@ -96,7 +231,7 @@ impl From<nspire_runlevel> for RunLevel {
     }
 }
 
-#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
 #[cfg_attr(feature = "serde", derive(Serialize))]
 pub struct Version {
     pub major: u8,
@@ -132,6 +267,27 @@ impl fmt::Display for Version {
     }
 }
 
+impl FromStr for Version {
+    type Err = Error;
+
+    /// Parse a version in the form `major.minor.patch.build`, e.g.
+    /// `5.3.0.564`.
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let mut parts = s.trim().split('.');
+        let mut next = || parts.next().ok_or(Error::Invalid);
+        let version = Version {
+            major: next()?.parse().map_err(|_| Error::Invalid)?,
+            minor: next()?.parse().map_err(|_| Error::Invalid)?,
+            patch: next()?.parse().map_err(|_| Error::Invalid)?,
+            build: next()?.parse().map_err(|_| Error::Invalid)?,
+        };
+        if parts.next().is_some() {
+            return Err(Error::Invalid);
+        }
+        Ok(version)
+    }
+}
+
 #[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
 #[cfg_attr(feature = "serde", derive(Serialize))]
 pub struct Lcd {
@@ -141,22 +297,31 @@ pub struct Lcd {
     /// for color calculators.
     pub bpp: u8,
     pub sample_mode: u8,
+    /// Where the screen starts on the LCD, horizontally.
+    pub x: u16,
+    /// Where the screen starts on the LCD, vertically.
+    pub y: u16,
 }
 
-impl From<nspire_devinfo__bindgen_ty_5> for Lcd {
+impl From<(nspire_devinfo__bindgen_ty_5, nspire_devinfo__bindgen_ty_7)> for Lcd {
     fn from(
-        nspire_devinfo__bindgen_ty_5 {
-            width,
-            height,
-            bbp,
-            sample_mode,
-        }: nspire_devinfo__bindgen_ty_5,
+        (
+            nspire_devinfo__bindgen_ty_5 {
+                width,
+                height,
+                bbp,
+                sample_mode,
+            },
+            nspire_devinfo__bindgen_ty_7 { x, y },
+        ): (nspire_devinfo__bindgen_ty_5, nspire_devinfo__bindgen_ty_7),
     ) -> Self {
         Lcd {
             width,
             height,
             bpp: bbp,
             sample_mode,
+            x,
+            y,
         }
     }
 }
@@ -173,6 +338,8 @@ pub struct Info {
     pub boot1_version: Version,
     pub boot2_version: Version,
     pub hw_type: HardwareType,
+    /// The hardware revision, which tells apart boards of the same model.
+    pub hw_version: u32,
     pub clock_speed: u8,
     pub lcd: Lcd,
     /// The accepted file extension for OS upgrades.
@@ -183,35 +350,119 @@ pub struct Info {
     pub name: String,
     /// The ID ("serial number") of the calculator.
     pub id: String,
+    /// The short form of [`id`][Info::id] the calculator also reports.
+    pub short_id: String,
     /// Whether the calculator is in maintenance mode or the standard operating
     /// system.
     pub run_level: RunLevel,
     pub battery: Battery,
+    /// The battery level as reported, which [`battery`][Info::battery] is
+    /// decoded from.
+    pub battery_level: u8,
     pub is_charging: bool,
+    /// The device info reply as received, for anything not decoded above.
+    pub raw: Vec<u8>,
 }
 
 impl From<nspire_devinfo> for Info {
     fn from(info: nspire_devinfo) -> Self {
-        unsafe {
-            Info {
-                free_storage: info.storage.free,
-                total_storage: info.storage.total,
-                free_ram: info.ram.free,
-                total_ram: info.ram.total,
-                version: info.versions[0].into(),
-                boot1_version: info.versions[1].into(),
-                boot2_version: info.versions[2].into(),
-                hw_type: info.hw_type.into(),
-                clock_speed: info.clock_speed,
-                lcd: info.lcd.into(),
-                battery: info.batt.status.into(),
-                file_extension: crate::c_str(&info.extensions.file),
-                os_extension: crate::c_str(&info.extensions.os),
-                name: crate::c_str(&info.device_name),
-                id: crate::c_str(&info.electronic_id),
-                run_level: info.runlevel.into(),
-                is_charging: info.batt.is_charging > 0,
-            }
+        Info {
+            free_storage: info.storage.free,
+            total_storage: info.storage.total,
+            free_ram: info.ram.free,
+            total_ram: info.ram.total,
+            version: info.versions[0].into(),
+            boot1_version: info.versions[1].into(),
+            boot2_version: info.versions[2].into(),
+            hw_type: info.hw_type.into(),
+            hw_version: info.hw_version,
+            clock_speed: info.clock_speed,
+            lcd: (info.lcd, info.lcd_offset).into(),
+            battery: info.batt.status.into(),
+            battery_level: info.batt.status as u8,
+            file_extension: crate::c_str(&info.extensions.file),
+            os_extension: crate::c_str(&info.extensions.os),
+            name: crate::c_str(&info.device_name),
+            id: crate::c_str(&info.electronic_id),
+            short_id: crate::c_str(&info.short_id),
+            run_level: info.runlevel.into(),
+            is_charging: info.batt.is_charging > 0,
+            raw: info.raw.data[..usize::from(info.raw.len).min(info.raw.data.len())].to_vec(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect() {
+        use HardwareType::*;
+        #[rustfmt::skip]
+        let table = [
+            // OS extension, hardware type, CX II, model
+            ("tno", NonCas, false, Some(Model::Nspire)),
+            ("tnc", Cas, false, Some(Model::NspireCas)),
+            ("tco", NonCasCx, false, Some(Model::Cx)),
+            ("tcc", CasCx, false, Some(Model::CxCas)),
+            ("tco2", NonCasCx, true, Some(Model::CxII)),
+            ("tcc2", CasCx, true, Some(Model::CxIICas)),
+            ("tct2", NonCasCx, true, Some(Model::CxIIT)),
+            ("TCT2", NonCasCx, true, Some(Model::CxIIT)),
+            // The hardware wins over the software
+            ("tnc", NonCas, false, Some(Model::Nspire)),
+            ("tno", Cas, false, Some(Model::NspireCas)),
+            ("tcc2", NonCasCx, true, Some(Model::CxII)),
+            ("tct2", CasCx, true, Some(Model::CxIICas)),
+            // Unknown extensions don't matter
+            ("", NonCas, false, Some(Model::Nspire)),
+            ("", Cas, false, Some(Model::NspireCas)),
+            ("", NonCasCx, false, Some(Model::Cx)),
+            ("", CasCx, false, Some(Model::CxCas)),
+            ("", NonCasCx, true, Some(Model::CxII)),
+            ("", CasCx, true, Some(Model::CxIICas)),
+            // ...nor do ones for the wrong protocol
+            ("tco", NonCasCx, true, Some(Model::CxII)),
+            ("tcc2", CasCx, false, Some(Model::CxCas)),
+            ("tct2", NonCasCx, false, Some(Model::Cx)),
+            // Unknown hardware goes by the software
+            ("TCC2", Unknown(0), true, Some(Model::CxIICas)),
+            ("tct2", Unknown(0), true, Some(Model::CxIIT)),
+            ("tno", Unknown(0), false, Some(Model::Nspire)),
+            ("tcc", Unknown(0), true, None),
+            ("", Unknown(0x1F), false, None),
+            // Non-CX hardware can't be a CX II
+            ("", Cas, true, None),
+            ("tcc2", NonCas, true, None),
+        ];
+        for &(ext, hw_type, is_cx_ii, model) in &table {
+            let detected = Model::detect(hw_type, ext, is_cx_ii);
+            assert_eq!(detected, model, "{:?} {:?} {}", ext, hw_type, is_cx_ii);
+        }
+    }
+
+    #[test]
+    fn properties() {
+        #[rustfmt::skip]
+        let table = [
+            // Model, CAS, color, CX II
+            (Model::Nspire, false, false, false),
+            (Model::NspireCas, true, false, false),
+            (Model::Cx, false, true, false),
+            (Model::CxCas, true, true, false),
+            (Model::CxII, false, true, true),
+            (Model::CxIICas, true, true, true),
+            (Model::CxIIT, false, true, true),
+        ];
+        for &(model, cas, color, cx_ii) in &table {
+            assert_eq!(model.is_cas(), cas, "{}", model);
+            assert_eq!(model.has_color_screen(), color, "{}", model);
+            assert_eq!(model.is_cx_ii(), cx_ii, "{}", model);
+            assert_eq!(Model::from(model.os_target()), model);
+            let (pid, packet) = if cx_ii { (PID_CX2, 1440) } else { (PID, 254) };
+            assert_eq!(model.product_id(), pid);
+            assert_eq!(model.max_packet_size(), packet);
         }
     }
 }
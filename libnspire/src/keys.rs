@@ -0,0 +1,315 @@
+//! Pressing keys on the calculator from the host.
+//!
+//! [`Handle::send_keys`][crate::Handle::send_keys] presses [`Key`]s one after
+//! another, as if they were pressed on the keypad, and
+//! [`Handle::type_text`][crate::Handle::type_text] types text. Together with
+//! [`Handle::screenshot`][crate::Handle::screenshot], this can drive the
+//! calculator's interface:
+//!
+//! ```
+//! use libnspire::{keys::Key, sim::Simulator, Handle};
+//! # fn main() -> libnspire::Result<()> {
+//! let sim = Simulator::new();
+//! let handle = Handle::<rusb::GlobalContext>::from_transport(sim.clone())?;
+//! handle.send_keys(&[Key::Home, Key::Num1])?;
+//! handle.type_text("Hi")?;
+//! assert_eq!(
+//!     sim.keys(),
+//!     [Key::Home, Key::Num1, Key::Shift, Key::H, Key::I]
+//! );
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Neither the key codes nor how they're packed into packets has been checked
+//! against a capture of TI's software pressing keys, so keys may not arrive
+//! as expected on a real calculator. The simulator accepts what the library
+//! sends.
+
+use crate::{Error, Result};
+
+/// A key on the keypad of a Touchpad, CX or CX II.
+#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
+pub enum Key {
+    A,
+    B,
+    C,
+    D,
+    E,
+    F,
+    G,
+    H,
+    I,
+    J,
+    K,
+    L,
+    M,
+    N,
+    O,
+    P,
+    Q,
+    R,
+    S,
+    T,
+    U,
+    V,
+    W,
+    X,
+    Y,
+    Z,
+    Num0,
+    Num1,
+    Num2,
+    Num3,
+    Num4,
+    Num5,
+    Num6,
+    Num7,
+    Num8,
+    Num9,
+    Space,
+    Period,
+    Comma,
+    Plus,
+    Minus,
+    Multiply,
+    Divide,
+    Power,
+    Equals,
+    LeftParen,
+    RightParen,
+    Question,
+    Quote,
+    Apostrophe,
+    /// The `(-)` key.
+    Negative,
+    /// The `EE` key.
+    Ee,
+    Pi,
+    Theta,
+    Square,
+    /// The `e^x` key.
+    Exp,
+    /// The `10^x` key.
+    TenX,
+    Sin,
+    Cos,
+    Tan,
+    Esc,
+    /// The home key, also the on key.
+    Home,
+    Doc,
+    Menu,
+    Tab,
+    Ctrl,
+    Shift,
+    Var,
+    /// The catalog key, with the book icon.
+    Catalog,
+    /// The backspace key.
+    Del,
+    Enter,
+    /// The return key, at the bottom right.
+    Return,
+    /// Clicking the touchpad.
+    Click,
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+/// Each key, the character it types (if any) and its scan code, which is its
+/// row on the keypad matrix in the high nibble and its column in the low one.
+const KEYS: &[(Key, Option<char>, u8)] = &[
+    (Key::Return, None, 0x00),
+    (Key::Enter, None, 0x01),
+    (Key::Space, Some(' '), 0x02),
+    (Key::Negative, None, 0x03),
+    (Key::Z, Some('z'), 0x04),
+    (Key::Period, Some('.'), 0x05),
+    (Key::Y, Some('y'), 0x06),
+    (Key::Num0, Some('0'), 0x07),
+    (Key::X, Some('x'), 0x08),
+    (Key::Theta, None, 0x0A),
+    (Key::Comma, Some(','), 0x10),
+    (Key::Plus, Some('+'), 0x11),
+    (Key::W, Some('w'), 0x12),
+    (Key::Num3, Some('3'), 0x13),
+    (Key::V, Some('v'), 0x14),
+    (Key::Num2, Some('2'), 0x15),
+    (Key::U, Some('u'), 0x16),
+    (Key::Num1, Some('1'), 0x17),
+    (Key::T, Some('t'), 0x18),
+    (Key::Ee, None, 0x19),
+    (Key::Pi, None, 0x1A),
+    (Key::Question, Some('?'), 0x20),
+    (Key::Minus, Some('-'), 0x22),
+    (Key::S, Some('s'), 0x23),
+    (Key::Num6, Some('6'), 0x24),
+    (Key::R, Some('r'), 0x25),
+    (Key::Num5, Some('5'), 0x26),
+    (Key::Q, Some('q'), 0x27),
+    (Key::Num4, Some('4'), 0x28),
+    (Key::P, Some('p'), 0x29),
+    (Key::TenX, None, 0x2A),
+    (Key::Apostrophe, Some('\''), 0x31),
+    (Key::Multiply, Some('*'), 0x32),
+    (Key::O, Some('o'), 0x33),
+    (Key::Num9, Some('9'), 0x34),
+    (Key::N, Some('n'), 0x35),
+    (Key::Num8, Some('8'), 0x36),
+    (Key::M, Some('m'), 0x37),
+    (Key::Num7, Some('7'), 0x38),
+    (Key::L, Some('l'), 0x39),
+    (Key::Square, None, 0x3A),
+    (Key::Quote, Some('"'), 0x41),
+    (Key::Divide, Some('/'), 0x42),
+    (Key::K, Some('k'), 0x43),
+    (Key::Tan, None, 0x44),
+    (Key::J, Some('j'), 0x45),
+    (Key::Cos, None, 0x46),
+    (Key::I, Some('i'), 0x47),
+    (Key::Sin, None, 0x48),
+    (Key::H, Some('h'), 0x49),
+    (Key::Exp, None, 0x4A),
+    (Key::Click, None, 0x52),
+    (Key::G, Some('g'), 0x53),
+    (Key::RightParen, Some(')'), 0x54),
+    (Key::F, Some('f'), 0x55),
+    (Key::LeftParen, Some('('), 0x56),
+    (Key::E, Some('e'), 0x57),
+    (Key::Var, None, 0x58),
+    (Key::D, Some('d'), 0x59),
+    (Key::Shift, None, 0x5A),
+    (Key::Equals, Some('='), 0x60),
+    (Key::Del, None, 0x61),
+    (Key::C, Some('c'), 0x63),
+    (Key::Catalog, None, 0x64),
+    (Key::B, Some('b'), 0x65),
+    (Key::Power, Some('^'), 0x66),
+    (Key::A, Some('a'), 0x67),
+    (Key::Esc, None, 0x70),
+    (Key::Home, None, 0x71),
+    (Key::Doc, None, 0x72),
+    (Key::Menu, None, 0x73),
+    (Key::Tab, None, 0x74),
+    (Key::Ctrl, None, 0x75),
+    (Key::Up, None, 0x76),
+    (Key::Right, None, 0x77),
+    (Key::Down, None, 0x78),
+    (Key::Left, None, 0x79),
+];
+
+impl Key {
+    /// The character this key types, if any.
+    pub fn char(self) -> Option<char> {
+        self.entry().1
+    }
+
+    /// The code sent to the calculator for this key: the character it types
+    /// in the high byte, and its scan code in the low byte.
+    pub fn code(self) -> u16 {
+        let (_, c, scan) = self.entry();
+        u16::from(c.map_or(0, |c| c as u8)) << 8 | u16::from(scan)
+    }
+
+    /// The key sent as `code`, if any.
+    pub fn from_code(code: u16) -> Option<Key> {
+        KEYS.iter()
+            .map(|&(key, _, _)| key)
+            .find(|key| key.code() == code)
+    }
+
+    fn entry(self) -> (Key, Option<char>, u8) {
+        *KEYS
+            .iter()
+            .find(|(key, _, _)| *key == self)
+            .expect("every key is in KEYS")
+    }
+}
+
+/// The keys that type `text`, with [`Key::Shift`] before capitals,
+/// [`Key::Enter`] for newlines and [`Key::Tab`] for tabs.
+pub fn keys_for(text: &str) -> Result<Vec<Key>> {
+    let mut keys = vec![];
+    for c in text.chars() {
+        let lower = c.to_ascii_lowercase();
+        let key = KEYS
+            .iter()
+            .find(|(_, typed, _)| *typed == Some(lower))
+            .map(|&(key, _, _)| key);
+        match (c, key) {
+            ('\n', _) => keys.push(Key::Enter),
+            ('\t', _) => keys.push(Key::Tab),
+            (_, Some(key)) if c.is_ascii_uppercase() => keys.extend(&[Key::Shift, key]),
+            (_, Some(key)) => keys.push(key),
+            (_, None) => return Err(Error::Untypable(c)),
+        }
+    }
+    Ok(keys)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes() {
+        #[rustfmt::skip]
+        let table = [
+            // Letters type their lowercase character
+            (Key::A, 0x6167),
+            (Key::H, 0x6849),
+            (Key::Z, 0x7A04),
+            // Digits
+            (Key::Num0, 0x3007),
+            (Key::Num1, 0x3117),
+            (Key::Num9, 0x3934),
+            // Keys that type punctuation
+            (Key::Space, 0x2002),
+            (Key::Period, 0x2E05),
+            (Key::Plus, 0x2B11),
+            (Key::Power, 0x5E66),
+            (Key::Quote, 0x2241),
+            (Key::Apostrophe, 0x2731),
+            // Keys that don't type anything only have a scan code
+            (Key::Return, 0x0000),
+            (Key::Enter, 0x0001),
+            (Key::Negative, 0x0003),
+            (Key::Shift, 0x005A),
+            (Key::Click, 0x0052),
+            (Key::Home, 0x0071),
+            (Key::Left, 0x0079),
+        ];
+        for &(key, code) in &table {
+            assert_eq!(key.code(), code, "{:?}", key);
+            assert_eq!(Key::from_code(code), Some(key));
+        }
+    }
+
+    #[test]
+    fn every_key() {
+        for &(key, c, scan) in KEYS {
+            assert_eq!(key.char(), c);
+            assert_eq!(key.code() & 0xFF, u16::from(scan), "{:?}", key);
+            assert_eq!(Key::from_code(key.code()), Some(key));
+            let clashes = KEYS.iter().filter(|entry| entry.2 == scan).count();
+            assert_eq!(clashes, 1, "scan code {:#04x} is used twice", scan);
+        }
+        // The character has to match the scan code
+        assert_eq!(Key::from_code(0x6100), None);
+        assert_eq!(Key::from_code(0x6267), None);
+    }
+
+    #[test]
+    fn typing() {
+        let keys = keys_for("Ab1 (x)\n\t").unwrap();
+        #[rustfmt::skip]
+        assert_eq!(keys, [
+            Key::Shift, Key::A, Key::B, Key::Num1, Key::Space, Key::LeftParen, Key::X,
+            Key::RightParen, Key::Enter, Key::Tab,
+        ]);
+        assert!(matches!(keys_for("a~"), Err(Error::Untypable('~'))));
+    }
+}
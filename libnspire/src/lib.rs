@@ -1,29 +1,59 @@
 //! Start with [`Handle::new`]
 
-use std::ffi::{CStr, CString};
+use std::ffi::CString;
+use std::fmt;
 use std::mem;
+use std::ops::Deref;
 use std::os::raw::c_char;
 use std::ptr::{null_mut, NonNull};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::thread::{self, ThreadId};
+use std::time::{Duration, Instant, SystemTime};
 
 use rusb::{DeviceHandle, UsbContext};
+use tracing::Span;
 
-use crate::callback::CallbackData;
+use crate::callback::{CallbackData, ClockData, OsCallbackData, TapData, TransportData};
 use array_iterator::ArrayIterator;
+use capture::Capture;
 use dir::{DirItem, DirList};
 pub use error::*;
-use info::Info;
+use flate2::Crc;
+use info::{Info, Model};
+use keys::Key;
 use libnspire_sys::{
     free, nspire_attr, nspire_device_info, nspire_devinfo, nspire_dir_create, nspire_dir_delete,
     nspire_dirlist, nspire_file_copy, nspire_file_delete, nspire_file_move, nspire_file_read,
-    nspire_file_write, nspire_free, nspire_handle, nspire_image, nspire_init, nspire_os_send,
-    nspire_screenshot,
+    nspire_file_session_begin, nspire_file_session_end, nspire_file_write, nspire_free,
+    nspire_handle, nspire_image, nspire_init_opts, nspire_init_transport_opts, nspire_login,
+    nspire_os_install, nspire_packet_size, nspire_ping, nspire_screenshot, nspire_send_keys,
+    nspire_set_packet_size, nspire_set_tap,
 };
+use os_image::{OsCompat, OsImage, OsProgress};
 use std::convert::TryFrom;
+use transfer::Transfer;
+use transport::Transport;
 
+pub mod bridge;
 mod callback;
+pub mod capture;
+pub mod deploy;
 pub mod dir;
+pub mod dissector;
 mod error;
+#[cfg(feature = "fuzzing")]
+#[doc(hidden)]
+pub mod fuzz;
 pub mod info;
+pub mod keys;
+mod navnet;
+mod nnse;
+pub mod os_image;
+pub mod replay;
+pub mod sim;
+pub mod tns;
+pub mod transfer;
+pub mod transport;
 
 /// The USB vendor ID used by all Nspire calculators.
 pub const VID: u16 = 0x0451;
@@ -32,10 +62,116 @@ pub const PID: u16 = 0xe012;
 /// The USB vendor ID used by all CX II calculators.
 pub const PID_CX2: u16 = 0xe022;
 
+/// A NavNet service ID, as logged.
+struct Service(u16);
+
+impl fmt::Display for Service {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:#06x}", self.0)
+    }
+}
+
+/// A span for one call to a NavNet service, to be run with [`traced`].
+macro_rules! service_span {
+    ($name:literal, $service:expr $(, $($field:tt)+)?) => {
+        tracing::debug_span!(
+            $name,
+            service = %Service($service),
+            $($($field)+,)?
+            bytes = tracing::field::Empty,
+            duration = tracing::field::Empty,
+        )
+    };
+}
+
+/// Run `f` in `span`, recording how long it took and any error.
+fn traced<R>(span: Span, f: impl FnOnce() -> Result<R>) -> Result<R> {
+    let _entered = span.enter();
+    let start = Instant::now();
+    let result = f();
+    span.record("duration", tracing::field::debug(start.elapsed()));
+    if let Err(e) = &result {
+        tracing::debug!(error = %e, "failed");
+    }
+    result
+}
+
 /// A handle to a calculator.
+///
+/// A handle can be shared between threads. Calls to the calculator take
+/// turns, each one finishing before the next starts, so calling the handle
+/// from inside one of its own progress callbacks fails with [`Error::Busy`],
+/// as does calling it from inside a [`file_session`][Handle::file_session]
+/// closure instead of using the [`FileSession`] given.
 pub struct Handle<T: UsbContext> {
+    session: Mutex<Session>,
+    /// The thread holding `session`, to catch it calling the handle again.
+    owner: Mutex<Option<ThreadId>>,
+    packet_size: usize,
+    /// The USB device, if opened with [`Handle::new`].
+    device: Option<DeviceHandle<T>>,
+    /// Must outlive `session`, which points to it.
+    tap: Box<TapData>,
+    /// Must outlive `session`, which points to it.
+    _clock: Option<Box<ClockData>>,
+    is_cx_ii: bool,
+}
+
+/// libnspire's handle, which keeps the state of the connection, such as
+/// sequence numbers and the open service.
+struct Session {
     handle: NonNull<nspire_handle>,
-    device: DeviceHandle<T>,
+    /// Must outlive `handle`, which points into it.
+    _transport: Option<Box<TransportData>>,
+}
+
+// Only ever used by one thread at a time, by whoever locked it
+unsafe impl Send for Session {}
+
+impl Session {
+    fn new(handle: *mut nspire_handle, transport: Option<Box<TransportData>>) -> Result<Self> {
+        Ok(Session {
+            handle: NonNull::new(handle).ok_or(Error::NoDevice)?,
+            _transport: transport,
+        })
+    }
+
+    fn as_ptr(&self) -> *mut nspire_handle {
+        self.handle.as_ptr()
+    }
+
+    fn packet_size(&self) -> usize {
+        unsafe { nspire_packet_size(self.as_ptr()) as usize }
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        let _ = traced(service_span!("disconnect", navnet::SID_DISCONNECT), || {
+            unsafe { nspire_free(self.as_ptr()) };
+            Ok(())
+        });
+    }
+}
+
+/// A locked [`Session`], which clears its owner when unlocked.
+struct SessionGuard<'a> {
+    session: MutexGuard<'a, Session>,
+    owner: &'a Mutex<Option<ThreadId>>,
+}
+
+impl Deref for SessionGuard<'_> {
+    type Target = Session;
+
+    fn deref(&self) -> &Session {
+        &self.session
+    }
+}
+
+impl Drop for SessionGuard<'_> {
+    fn drop(&mut self) {
+        *self.owner.lock().unwrap_or_else(PoisonError::into_inner) = None;
+    }
 }
 
 fn is_cx_ii<T: UsbContext>(device: &DeviceHandle<T>) -> Result<bool> {
@@ -43,115 +179,213 @@ fn is_cx_ii<T: UsbContext>(device: &DeviceHandle<T>) -> Result<bool> {
 }
 
 impl<T: UsbContext> Handle<T> {
-    /// Create a new handle to a USB device.
+    /// Create a new handle to a USB device. See [`HandleBuilder`] for more
+    /// options.
     pub fn new(device: DeviceHandle<T>) -> Result<Self> {
-        let mut handle: *mut nspire_handle = null_mut();
-        err(unsafe { nspire_init(&mut handle, device.as_raw() as _, is_cx_ii(&device)?) })?;
-        Ok(Handle {
-            handle: NonNull::new(handle).ok_or(Error::NoDevice)?,
-            device,
+        HandleBuilder::new().open(device)
+    }
+
+    /// Create a new handle to a calculator reachable through a
+    /// [`Transport`], such as the [simulator][crate::sim::Simulator].
+    ///
+    /// `T` isn't used by such handles, so any [`UsbContext`] will do.
+    pub fn from_transport(transport: impl Transport + 'static) -> Result<Self> {
+        HandleBuilder::new().open_transport(transport)
+    }
+
+    /// Lock the session for a call to the calculator, failing with
+    /// [`Error::Busy`] if this thread already holds it, which would deadlock.
+    fn session(&self) -> Result<SessionGuard<'_>> {
+        let thread = thread::current().id();
+        let owner = || self.owner.lock().unwrap_or_else(PoisonError::into_inner);
+        if *owner() == Some(thread) {
+            return Err(Error::Busy);
+        }
+        // libnspire's state is only touched by C, which can't panic
+        let session = self.session.lock().unwrap_or_else(PoisonError::into_inner);
+        *owner() = Some(thread);
+        Ok(SessionGuard {
+            session,
+            owner: &self.owner,
         })
     }
 
+    /// Hook up the tap, which records frames once a capture is started.
+    fn with_tap(self) -> Result<Self> {
+        unsafe {
+            nspire_set_tap(
+                self.session()?.as_ptr(),
+                Some(TapData::tap),
+                self.tap.as_mut_void(),
+            )
+        };
+        Ok(self)
+    }
+
+    /// Start recording every frame sent to or received from the calculator,
+    /// discarding any capture already in progress. See [`capture`].
+    pub fn start_capture(&self) {
+        *self.tap.0.lock().unwrap() = Some(vec![]);
+    }
+
+    /// Stop recording, returning the frames recorded since
+    /// [`start_capture`][Handle::start_capture]. The capture is empty if none
+    /// was started.
+    pub fn stop_capture(&self) -> Capture {
+        Capture::new(self.tap.0.lock().unwrap().take().unwrap_or_default())
+    }
+
+    /// Whether frames are being recorded.
+    pub fn is_capturing(&self) -> bool {
+        self.tap.0.lock().unwrap().is_some()
+    }
+
+    /// The USB device this handle talks to, or `None` if it was created with
+    /// [`from_transport`][Handle::from_transport].
+    pub fn usb_device(&self) -> Option<&DeviceHandle<T>> {
+        self.device.as_ref()
+    }
+
     /// Whether this device is a CX II, CAS or non-CAS.
     pub fn is_cx_ii(&self) -> Result<bool> {
-        is_cx_ii(&self.device)
+        Ok(self.is_cx_ii)
+    }
+
+    /// The most data sent to the calculator in one packet: 1440 bytes for a
+    /// CX II, and 254 for others unless [`HandleBuilder::big_packets`] is
+    /// set and the calculator accepts them.
+    pub fn packet_size(&self) -> usize {
+        self.packet_size
+    }
+
+    fn set_packet_size(&mut self, size: usize) -> Result<()> {
+        let session = self
+            .session
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner);
+        err(unsafe { nspire_set_packet_size(session.as_ptr(), size as u32) })?;
+        self.packet_size = size;
+        Ok(())
+    }
+
+    /// Identify the calculator's model. See [`Model::detect`].
+    pub fn model(&self) -> Result<Model> {
+        let info = self.info()?;
+        Model::detect(info.hw_type, &info.os_extension, self.is_cx_ii).ok_or(Error::Invalid)
+    }
+
+    /// Send `payload` to the calculator and wait for it to come back,
+    /// returning the round-trip time. This is much cheaper than
+    /// [`Handle::info`] for checking that the calculator is still there.
+    ///
+    /// The payload can be up to [`packet_size`][Handle::packet_size] bytes
+    /// long.
+    ///
+    /// ```
+    /// use libnspire::{sim::Simulator, Handle};
+    /// # fn main() -> libnspire::Result<()> {
+    /// let handle = Handle::<rusb::GlobalContext>::from_transport(Simulator::new())?;
+    /// let rtt = handle.ping(b"are you there?")?;
+    /// assert!(rtt < std::time::Duration::from_secs(1));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn ping(&self, payload: &[u8]) -> Result<Duration> {
+        traced(service_span!("ping", navnet::SID_ECHO), || {
+            Span::current().record("bytes", payload.len());
+            let start = Instant::now();
+            err(unsafe {
+                nspire_ping(
+                    self.session()?.as_ptr(),
+                    payload.as_ptr() as _,
+                    payload.len(),
+                )
+            })?;
+            Ok(start.elapsed())
+        })
     }
 
     pub fn info(&self) -> Result<Info> {
-        unsafe {
+        traced(service_span!("info", navnet::SID_DEVINFO), || unsafe {
             let mut info: nspire_devinfo = mem::zeroed();
-            err(nspire_device_info(self.handle.as_ptr(), &mut info))?;
+            err(nspire_device_info(self.session()?.as_ptr(), &mut info))?;
             Ok(info.into())
-        }
+        })
     }
 
     /// Take a screenshot.
     pub fn screenshot(&self) -> Result<Image> {
-        unsafe {
-            let mut image: *mut nspire_image = null_mut();
-            err(nspire_screenshot(self.handle.as_ptr(), &mut image))?;
-            let width = (*image).width;
-            let height = (*image).height;
-            let bbp = (*image).bbp;
-            let len = (width as u32 * height as u32 * bbp as u32) / 8;
-            let data = (*image).data.as_slice(len as usize).into();
-            free(image as _);
-            Ok(Image {
-                width,
-                height,
-                bpp: bbp,
-                data,
-            })
-        }
+        traced(
+            service_span!("screenshot", navnet::SID_SCREENSHOT),
+            || unsafe {
+                let mut image: *mut nspire_image = null_mut();
+                err(nspire_screenshot(self.session()?.as_ptr(), &mut image))?;
+                let width = (*image).width;
+                let height = (*image).height;
+                let bbp = (*image).bbp;
+                let len = (width as usize * height as usize * bbp as usize) / 8;
+                let data: Vec<u8> = (*image).data.as_slice(len).into();
+                free(image as _);
+                Span::current().record("bytes", data.len());
+                Ok(Image {
+                    width,
+                    height,
+                    bpp: bbp,
+                    data,
+                })
+            },
+        )
+    }
+
+    /// Press `keys` one after another. See [`keys`].
+    pub fn send_keys(&self, keys: &[Key]) -> Result<()> {
+        traced(
+            service_span!("send_keys", navnet::SID_KEYS, count = keys.len()),
+            || {
+                let codes: Vec<u16> = keys.iter().map(|key| key.code()).collect();
+                err(unsafe {
+                    nspire_send_keys(self.session()?.as_ptr(), codes.as_ptr(), codes.len())
+                })
+            },
+        )
+    }
+
+    /// Type `text` on the keypad. See [`keys::keys_for`].
+    pub fn type_text(&self, text: &str) -> Result<()> {
+        self.send_keys(&keys::keys_for(text)?)
     }
 
     /// Move/rename a file.
     pub fn move_file(&self, src: &str, dest: &str) -> Result<()> {
-        let src = CString::new(src)?;
-        let dest = CString::new(dest)?;
-        unsafe {
-            err(nspire_file_move(
-                self.handle.as_ptr(),
-                src.as_ptr(),
-                dest.as_ptr(),
-            ))
-        }
+        self.file_session(|s| s.move_file(src, dest))
     }
 
     /// Get the attributes of a file or directory.
     pub fn file_attr(&self, src: &str) -> Result<DirItem> {
-        let src = CString::new(src)?;
-        unsafe {
-            let mut item = mem::zeroed();
-            err(nspire_attr(self.handle.as_ptr(), src.as_ptr(), &mut item))?;
-            Ok(item.into())
-        }
+        self.file_session(|s| s.file_attr(src))
     }
 
     /// Copy a file.
     pub fn copy_file(&self, src: &str, dest: &str) -> Result<()> {
-        let src = CString::new(src)?;
-        let dest = CString::new(dest)?;
-        unsafe {
-            err(nspire_file_copy(
-                self.handle.as_ptr(),
-                src.as_ptr(),
-                dest.as_ptr(),
-            ))
-        }
+        self.file_session(|s| s.copy_file(src, dest))
     }
 
     /// Delete a file.
     pub fn delete_file(&self, path: &str) -> Result<()> {
-        let path = CString::new(path)?;
-        unsafe { err(nspire_file_delete(self.handle.as_ptr(), path.as_ptr())) }
+        self.file_session(|s| s.delete_file(path))
     }
 
     /// Read a file. Returns the number of bytes read. You must pass a buffer
     /// large enough to read the entire file (or smaller if that's all you care
-    /// about).
+    /// about). The whole file is transferred either way, with what doesn't
+    /// fit dropped.
     pub fn read_file(
         &self,
         path: &str,
         buf: &mut [u8],
         progress: &mut dyn FnMut(usize),
     ) -> Result<usize> {
-        let path = CString::new(path)?;
-        let mut bytes = 0;
-        let mut cb = CallbackData(progress);
-        unsafe {
-            err(nspire_file_read(
-                self.handle.as_ptr(),
-                path.as_ptr(),
-                buf.as_mut_ptr() as _,
-                buf.len() as _,
-                &mut bytes,
-                Some(CallbackData::callback),
-                cb.as_mut_void(),
-            ))?;
-        }
-        Ok(bytes as usize)
+        self.file_session(|s| s.read_file(path, buf, progress))
     }
 
     /// Write a file.
@@ -161,63 +395,339 @@ impl<T: UsbContext> Handle<T> {
         buf: &[u8],
         progress: &mut dyn FnMut(usize),
     ) -> Result<()> {
-        let path = CString::new(path)?;
-        let mut cb = CallbackData(progress);
-        unsafe {
-            err(nspire_file_write(
-                self.handle.as_ptr(),
-                path.as_ptr(),
-                buf.as_ptr() as _,
-                buf.len() as _,
-                Some(CallbackData::callback),
-                cb.as_mut_void(),
-            ))
-        }
+        self.file_session(|s| s.write_file(path, buf, progress))
+    }
+
+    /// Write a file, then read it back to check that it arrived intact. See
+    /// [`FileSession::write_file_verified`].
+    pub fn write_file_verified(&self, path: &str, data: &[u8]) -> Result<()> {
+        self.file_session(|s| s.write_file_verified(path, data))
+    }
+
+    /// Write a file like [`write_file`][Handle::write_file], returning how
+    /// long it took. See [`transfer`].
+    pub fn upload(
+        &self,
+        path: &str,
+        buf: &[u8],
+        progress: &mut dyn FnMut(usize),
+    ) -> Result<Transfer> {
+        let packet_size = self.packet_size();
+        let start = Instant::now();
+        self.write_file(path, buf, progress)?;
+        Ok(Transfer {
+            bytes: buf.len(),
+            elapsed: start.elapsed(),
+            packet_size,
+        })
+    }
+
+    /// Read a file like [`read_file`][Handle::read_file], returning how long
+    /// it took and, in [`Transfer::bytes`], how much was read. See
+    /// [`transfer`].
+    pub fn download(
+        &self,
+        path: &str,
+        buf: &mut [u8],
+        progress: &mut dyn FnMut(usize),
+    ) -> Result<Transfer> {
+        let packet_size = self.packet_size();
+        let start = Instant::now();
+        let bytes = self.read_file(path, buf, progress)?;
+        Ok(Transfer {
+            bytes,
+            elapsed: start.elapsed(),
+            packet_size,
+        })
     }
 
     /// Send an OS update.
-    pub fn send_os(&self, buf: &[u8], progress: &mut dyn FnMut(usize)) -> Result<()> {
-        let mut cb = CallbackData(progress);
-        unsafe {
-            err(nspire_os_send(
-                self.handle.as_ptr(),
-                buf.as_ptr() as _,
-                buf.len() as _,
-                Some(CallbackData::callback),
-                cb.as_mut_void(),
-            ))
-        }
+    ///
+    /// This first uploads the image, then waits for the calculator to finish
+    /// installing it. Both phases are reported to `progress`. Use
+    /// [`check_os_compat`][Handle::check_os_compat] beforehand to avoid
+    /// sending an image the calculator will reject.
+    pub fn send_os(&self, buf: &[u8], progress: &mut dyn FnMut(OsProgress)) -> Result<()> {
+        traced(service_span!("send_os", navnet::SID_OS), || {
+            Span::current().record("bytes", buf.len());
+            let mut cb = OsCallbackData {
+                total: buf.len(),
+                progress,
+            };
+            let mut status = 0;
+            let res = unsafe {
+                err(nspire_os_install(
+                    self.session()?.as_ptr(),
+                    buf.as_ptr() as _,
+                    buf.len() as _,
+                    Some(OsCallbackData::callback),
+                    cb.as_mut_void(),
+                    &mut status,
+                ))
+            };
+            match res {
+                Err(Error::OsInstallFailed(_)) => Err(Error::OsInstallFailed(status)),
+                res => res,
+            }
+        })
+    }
+
+    /// Check whether an OS image can be installed on this calculator before
+    /// sending it with [`send_os`][Handle::send_os]. See
+    /// [`OsImage::check_compat`].
+    pub fn check_os_compat(&self, image: &OsImage) -> Result<OsCompat> {
+        image.check_compat(&self.info()?)
     }
 
     /// Create a directory.
     pub fn create_dir(&self, path: &str) -> Result<()> {
-        let path = CString::new(path)?;
-        unsafe { err(nspire_dir_create(self.handle.as_ptr(), path.as_ptr())) }
+        self.file_session(|s| s.create_dir(path))
     }
 
     /// Delete a directory.
     pub fn delete_dir(&self, path: &str) -> Result<()> {
-        let path = CString::new(path)?;
-        unsafe { err(nspire_dir_delete(self.handle.as_ptr(), path.as_ptr())) }
+        self.file_session(|s| s.delete_dir(path))
     }
 
     /// Get the contents of a directory.
     pub fn list_dir(&self, path: &str) -> Result<DirList> {
-        let path = CString::new(path)?;
-        unsafe {
-            let mut list = null_mut();
-            err(nspire_dirlist(
-                self.handle.as_ptr(),
-                path.as_ptr(),
-                &mut list,
-            ))?;
-            Ok(DirList::from_raw(list))
-        }
+        self.file_session(|s| s.list_dir(path))
+    }
+
+    /// Run `f` with the file service connected throughout, rather than
+    /// connecting and disconnecting for each operation. This saves a round
+    /// trip per operation, which adds up when deleting many files or walking
+    /// a tree.
+    ///
+    /// Other calls to the handle wait until `f` returns, so `f` must not use
+    /// the handle itself: such calls fail with [`Error::Busy`].
+    ///
+    /// ```
+    /// use libnspire::{sim::Simulator, Handle};
+    /// # fn main() -> libnspire::Result<()> {
+    /// let sim = Simulator::new();
+    /// for i in 0..10 {
+    ///     sim.add_file(&format!("/documents/{}.tns", i), b"");
+    /// }
+    /// let handle = Handle::<rusb::GlobalContext>::from_transport(sim.clone())?;
+    /// handle.file_session(|s| {
+    ///     for item in s.list_dir("/documents")?.iter() {
+    ///         let name = item.name().to_string_lossy();
+    ///         s.delete_file(&format!("/documents/{}", name))?;
+    ///     }
+    ///     Ok(())
+    /// })?;
+    /// assert!(handle.list_dir("/documents")?.is_empty());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn file_session<R>(&self, f: impl FnOnce(&FileSession) -> Result<R>) -> Result<R> {
+        let session = FileSession {
+            session: self.session()?,
+        };
+        err(unsafe { nspire_file_session_begin(session.as_ptr()) })?;
+        let result = f(&session);
+        // Like each operation's own disconnection used to, this can't fail
+        // the operations, which are done by now
+        unsafe { nspire_file_session_end(session.as_ptr()) };
+        result
     }
 }
 
-unsafe impl<T: UsbContext> Send for Handle<T> {}
-unsafe impl<T: UsbContext> Sync for Handle<T> {}
+/// How many times [`FileSession::write_file_verified`] writes a file before
+/// giving up.
+pub const VERIFY_ATTEMPTS: usize = 3;
+
+/// The file service, kept connected by [`Handle::file_session`].
+pub struct FileSession<'a> {
+    session: SessionGuard<'a>,
+}
+
+impl FileSession<'_> {
+    fn as_ptr(&self) -> *mut nspire_handle {
+        self.session.as_ptr()
+    }
+
+    /// Move/rename a file.
+    pub fn move_file(&self, src: &str, dest: &str) -> Result<()> {
+        traced(
+            service_span!("move_file", navnet::SID_FILE, src, dest),
+            || {
+                let src = CString::new(src)?;
+                let dest = CString::new(dest)?;
+                unsafe { err(nspire_file_move(self.as_ptr(), src.as_ptr(), dest.as_ptr())) }
+            },
+        )
+    }
+
+    /// Get the attributes of a file or directory.
+    pub fn file_attr(&self, src: &str) -> Result<DirItem> {
+        traced(
+            service_span!("file_attr", navnet::SID_FILE, path = src),
+            || {
+                let src = CString::new(src)?;
+                unsafe {
+                    let mut item = mem::zeroed();
+                    err(nspire_attr(self.as_ptr(), src.as_ptr(), &mut item))?;
+                    Ok(item.into())
+                }
+            },
+        )
+    }
+
+    /// Copy a file.
+    pub fn copy_file(&self, src: &str, dest: &str) -> Result<()> {
+        traced(
+            service_span!("copy_file", navnet::SID_FILE, src, dest),
+            || {
+                let src = CString::new(src)?;
+                let dest = CString::new(dest)?;
+                unsafe { err(nspire_file_copy(self.as_ptr(), src.as_ptr(), dest.as_ptr())) }
+            },
+        )
+    }
+
+    /// Delete a file.
+    pub fn delete_file(&self, path: &str) -> Result<()> {
+        traced(service_span!("delete_file", navnet::SID_FILE, path), || {
+            let path = CString::new(path)?;
+            unsafe { err(nspire_file_delete(self.as_ptr(), path.as_ptr())) }
+        })
+    }
+
+    /// Read a file. Returns the number of bytes read. You must pass a buffer
+    /// large enough to read the entire file (or smaller if that's all you care
+    /// about). The whole file is transferred either way, with what doesn't
+    /// fit dropped.
+    pub fn read_file(
+        &self,
+        path: &str,
+        buf: &mut [u8],
+        progress: &mut dyn FnMut(usize),
+    ) -> Result<usize> {
+        traced(service_span!("read_file", navnet::SID_FILE, path), || {
+            let path = CString::new(path)?;
+            let mut bytes = 0;
+            let mut cb = CallbackData(progress);
+            unsafe {
+                err(nspire_file_read(
+                    self.as_ptr(),
+                    path.as_ptr(),
+                    buf.as_mut_ptr() as _,
+                    buf.len() as _,
+                    &mut bytes,
+                    Some(CallbackData::callback),
+                    cb.as_mut_void(),
+                ))?;
+            }
+            Span::current().record("bytes", bytes);
+            Ok(bytes)
+        })
+    }
+
+    /// Write a file.
+    pub fn write_file(
+        &self,
+        path: &str,
+        buf: &[u8],
+        progress: &mut dyn FnMut(usize),
+    ) -> Result<()> {
+        traced(service_span!("write_file", navnet::SID_FILE, path), || {
+            Span::current().record("bytes", buf.len());
+            let path = CString::new(path)?;
+            let mut cb = CallbackData(progress);
+            unsafe {
+                err(nspire_file_write(
+                    self.as_ptr(),
+                    path.as_ptr(),
+                    buf.as_ptr() as _,
+                    buf.len() as _,
+                    Some(CallbackData::callback),
+                    cb.as_mut_void(),
+                ))
+            }
+        })
+    }
+
+    /// Write a file, then read it back to check that it arrived intact,
+    /// comparing its size and CRC-32 with `data`. A file that doesn't match
+    /// is written again, up to [`VERIFY_ATTEMPTS`] times in all, before
+    /// giving up with [`Error::VerificationFailed`]. Only mismatches are
+    /// retried: if writing or reading the file fails, that error is returned
+    /// straight away.
+    ///
+    /// ```
+    /// use libnspire::{sim::Simulator, Handle};
+    /// # fn main() -> libnspire::Result<()> {
+    /// let sim = Simulator::new();
+    /// let handle = Handle::<rusb::GlobalContext>::from_transport(sim.clone())?;
+    /// handle.write_file_verified("/documents/a.tns", b"checked")?;
+    /// assert_eq!(sim.file("/documents/a.tns").as_deref(), Some(&b"checked"[..]));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn write_file_verified(&self, path: &str, data: &[u8]) -> Result<()> {
+        let mut crc = Crc::new();
+        crc.update(data);
+        let expected_crc = crc.sum();
+        let mut attempt = 1;
+        loop {
+            self.write_file(path, data, &mut |_| {})?;
+            let size = self.file_attr(path)?.size() as usize;
+            let mut buf = vec![0; size];
+            let len = self.read_file(path, &mut buf, &mut |_| {})?;
+            buf.truncate(len);
+            let mut crc = Crc::new();
+            crc.update(&buf);
+            if size == data.len() && buf.len() == size && crc.sum() == expected_crc {
+                return Ok(());
+            }
+            let offset = data
+                .iter()
+                .zip(&buf)
+                .position(|(a, b)| a != b)
+                .unwrap_or_else(|| data.len().min(buf.len()));
+            let error = Error::VerificationFailed {
+                offset,
+                expected: data.len(),
+                found: size,
+            };
+            if attempt == VERIFY_ATTEMPTS {
+                return Err(error);
+            }
+            tracing::warn!(path, attempt, %error, "retrying");
+            attempt += 1;
+        }
+    }
+
+    /// Create a directory.
+    pub fn create_dir(&self, path: &str) -> Result<()> {
+        traced(service_span!("create_dir", navnet::SID_FILE, path), || {
+            let path = CString::new(path)?;
+            unsafe { err(nspire_dir_create(self.as_ptr(), path.as_ptr())) }
+        })
+    }
+
+    /// Delete a directory.
+    pub fn delete_dir(&self, path: &str) -> Result<()> {
+        traced(service_span!("delete_dir", navnet::SID_FILE, path), || {
+            let path = CString::new(path)?;
+            unsafe { err(nspire_dir_delete(self.as_ptr(), path.as_ptr())) }
+        })
+    }
+
+    /// Get the contents of a directory.
+    pub fn list_dir(&self, path: &str) -> Result<DirList> {
+        traced(service_span!("list_dir", navnet::SID_FILE, path), || {
+            let path = CString::new(path)?;
+            unsafe {
+                let mut list = null_mut();
+                err(nspire_dirlist(self.as_ptr(), path.as_ptr(), &mut list))?;
+                Ok(DirList::from_raw(list))
+            }
+        })
+    }
+}
 
 impl<T: UsbContext> TryFrom<DeviceHandle<T>> for Handle<T> {
     type Error = Error;
@@ -227,9 +737,194 @@ impl<T: UsbContext> TryFrom<DeviceHandle<T>> for Handle<T> {
     }
 }
 
-impl<T: UsbContext> Drop for Handle<T> {
-    fn drop(&mut self) {
-        unsafe { nspire_free(self.handle.as_ptr()) }
+/// Opens a [`Handle`] with settings beyond what [`Handle::new`] and
+/// [`Handle::from_transport`] use.
+///
+/// ```
+/// use libnspire::{sim::Simulator, ClockSource, HandleBuilder};
+/// # fn main() -> libnspire::Result<()> {
+/// let sim = Simulator::new();
+/// let _handle = HandleBuilder::new()
+///     .client_name("Lab PC 3")
+///     .clock(ClockSource::DontSet)
+///     .open_transport::<rusb::GlobalContext>(sim.clone())?;
+/// assert_eq!(sim.client_name().as_deref(), Some("Lab PC 3"));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct HandleBuilder {
+    client_name: Option<String>,
+    clock: ClockSource,
+    big_packets: bool,
+}
+
+impl HandleBuilder {
+    pub fn new() -> Self {
+        HandleBuilder::default()
+    }
+
+    /// The name the calculator shows for this computer, up to 52 bytes
+    /// long. By default, no name is sent. Opening a CX II with a name set
+    /// fails with [`Error::NotSupported`], as how a CX II is told the name
+    /// isn't known.
+    pub fn client_name(mut self, name: impl Into<String>) -> Self {
+        self.client_name = Some(name.into());
+        self
+    }
+
+    /// What to set a CX II's clock to when connecting. By default, it's set
+    /// to the host's time. Other calculators don't ask.
+    pub fn clock(mut self, clock: ClockSource) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Send calculators other than the CX II up to 1440 bytes per packet, as
+    /// a CX II is sent, instead of 254. Uploads and OS installs then take
+    /// fewer packets, but not every calculator accepts such packets, so a
+    /// full-size [`ping`][Handle::ping] is sent when connecting to check, and
+    /// 254 is used if that fails. Off by default.
+    ///
+    /// ```
+    /// use libnspire::{sim::Simulator, HandleBuilder};
+    /// # fn main() -> libnspire::Result<()> {
+    /// let sim = Simulator::new();
+    /// let builder = HandleBuilder::new().big_packets(true);
+    /// let handle = builder.open_transport::<rusb::GlobalContext>(sim.clone())?;
+    /// assert_eq!(handle.packet_size(), 254);
+    ///
+    /// sim.set_big_packets(true);
+    /// let handle = builder.open_transport::<rusb::GlobalContext>(sim.clone())?;
+    /// assert_eq!(handle.packet_size(), 1440);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn big_packets(mut self, enabled: bool) -> Self {
+        self.big_packets = enabled;
+        self
+    }
+
+    /// Open a handle to a USB device, like [`Handle::new`].
+    pub fn open<T: UsbContext>(&self, device: DeviceHandle<T>) -> Result<Handle<T>> {
+        let handle = traced(service_span!("connect", navnet::SID_ADDR_ASSIGN), || {
+            callback::init_log();
+            let mut handle: *mut nspire_handle = null_mut();
+            let is_cx_ii = is_cx_ii(&device)?;
+            let mut clock = self.clock_data();
+            let opts = ClockData::opts(clock.as_deref_mut());
+            // libnspire sets up its libusb context the first time, unguarded
+            static OPENING: Mutex<()> = Mutex::new(());
+            let _opening = OPENING.lock().unwrap_or_else(PoisonError::into_inner);
+            err(unsafe { nspire_init_opts(&mut handle, device.as_raw() as _, is_cx_ii, &opts) })?;
+            let session = Session::new(handle, None)?;
+            Handle {
+                packet_size: session.packet_size(),
+                session: Mutex::new(session),
+                owner: Mutex::default(),
+                device: Some(device),
+                tap: Box::default(),
+                _clock: clock,
+                is_cx_ii,
+            }
+            .with_tap()
+        })?;
+        self.finish(handle)
+    }
+
+    /// Open a handle to a calculator reachable through a [`Transport`], like
+    /// [`Handle::from_transport`].
+    pub fn open_transport<T: UsbContext>(
+        &self,
+        transport: impl Transport + 'static,
+    ) -> Result<Handle<T>> {
+        let handle = traced(service_span!("connect", navnet::SID_ADDR_ASSIGN), || {
+            callback::init_log();
+            let is_cx_ii = transport.is_cx_ii();
+            let mut transport = Box::new(TransportData(Box::new(transport)));
+            let raw = transport.as_raw();
+            let mut handle: *mut nspire_handle = null_mut();
+            let mut clock = self.clock_data();
+            let opts = ClockData::opts(clock.as_deref_mut());
+            err(unsafe { nspire_init_transport_opts(&mut handle, &raw, is_cx_ii, &opts) })?;
+            let session = Session::new(handle, Some(transport))?;
+            Handle {
+                packet_size: session.packet_size(),
+                session: Mutex::new(session),
+                owner: Mutex::default(),
+                device: None,
+                tap: Box::default(),
+                _clock: clock,
+                is_cx_ii,
+            }
+            .with_tap()
+        })?;
+        self.finish(handle)
+    }
+
+    /// The host's time is libnspire's default, so it needs no callback.
+    fn clock_data(&self) -> Option<Box<ClockData>> {
+        match self.clock {
+            ClockSource::Host => None,
+            clock => Some(Box::new(ClockData(clock))),
+        }
+    }
+
+    /// Apply the settings that need a connected calculator.
+    fn finish<T: UsbContext>(&self, mut handle: Handle<T>) -> Result<Handle<T>> {
+        if let Some(name) = &self.client_name {
+            traced(service_span!("login", navnet::SID_LOGIN, name), || {
+                let name = CString::new(name.as_str())?;
+                err(unsafe { nspire_login(handle.session()?.as_ptr(), name.as_ptr()) })
+            })?;
+        }
+        if self.big_packets && !handle.is_cx_ii {
+            handle.set_packet_size(navnet::MAX_DATA_CX_II)?;
+            if let Err(e) = handle.ping(&[0; navnet::MAX_DATA_CX_II]) {
+                tracing::debug!(error = %e, "big packets refused");
+                handle.set_packet_size(navnet::MAX_DATA)?;
+            }
+        }
+        Ok(handle)
+    }
+}
+
+/// What a CX II's clock is set to when connecting. See
+/// [`HandleBuilder::clock`].
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum ClockSource {
+    /// The host's time.
+    #[default]
+    Host,
+    /// Always this time, to freeze the calculator's clock or make tests
+    /// deterministic.
+    Fixed(SystemTime),
+    /// The host's time, shifted by this many seconds, such as a time zone's
+    /// offset from UTC.
+    UtcOffset(i32),
+    /// Leave the calculator's clock as it is, by not answering its request
+    /// for the time. Whether every CX II carries on connecting without an
+    /// answer hasn't been checked against a real calculator; if one doesn't,
+    /// requests after connecting time out.
+    DontSet,
+}
+
+impl ClockSource {
+    /// The time to set the calculator's clock to, if any.
+    pub fn now(&self) -> Option<SystemTime> {
+        match *self {
+            ClockSource::Host => Some(SystemTime::now()),
+            ClockSource::Fixed(time) => Some(time),
+            ClockSource::UtcOffset(secs) => {
+                let offset = Duration::from_secs(secs.unsigned_abs().into());
+                if secs < 0 {
+                    SystemTime::now().checked_sub(offset)
+                } else {
+                    SystemTime::now().checked_add(offset)
+                }
+            }
+            ClockSource::DontSet => None,
+        }
     }
 }
 
@@ -254,36 +949,92 @@ const fn convert_channel(value: u8, from_max: u8) -> u8 {
 impl TryFrom<Image> for image::DynamicImage {
     type Error = Error;
 
-    /// Currently broken.
+    /// Convert a screenshot to an image. 16-bit screenshots are RGB565, red
+    /// in the high bits. Fails with [`Error::InvalidPacket`] if the data is
+    /// too short for the screenshot's size.
     fn try_from(image: Image) -> Result<Self> {
         use image::ImageBuffer;
+        let (width, height) = (image.width as u32, image.height as u32);
         match image.bpp {
-            8 => Ok(image::DynamicImage::ImageLuma8(
-                ImageBuffer::from_vec(image.width as u32, image.height as u32, image.data).unwrap(),
-            )),
+            8 => ImageBuffer::from_vec(width, height, image.data)
+                .map(image::DynamicImage::ImageLuma8)
+                .ok_or(Error::InvalidPacket),
             16 => {
                 let data: Vec<u8> = image
                     .data
-                    .chunks(2)
+                    .chunks_exact(2)
                     .flat_map(|d| {
-                        let color = u16::from_ne_bytes([d[0], d[1]]);
+                        let color = u16::from_le_bytes([d[0], d[1]]);
                         ArrayIterator::new([
-                            convert_channel(color as u8 & MAX_R, MAX_R),
+                            convert_channel((color >> 11) as u8 & MAX_R, MAX_R),
                             convert_channel((color >> 5) as u8 & MAX_G, MAX_G),
-                            convert_channel((color >> 11) as u8 & MAX_B, MAX_B),
+                            convert_channel(color as u8 & MAX_B, MAX_B),
                         ])
                     })
                     .collect();
-                dbg!(data.len());
-                Ok(image::DynamicImage::ImageRgb8(
-                    ImageBuffer::from_vec(image.width as u32, image.height as u32, data).unwrap(),
-                ))
+                ImageBuffer::from_vec(width, height, data)
+                    .map(image::DynamicImage::ImageRgb8)
+                    .ok_or(Error::InvalidPacket)
             }
             other => Err(Error::UnknownBpp(other)),
         }
     }
 }
 
-unsafe fn c_str(s: &[c_char]) -> String {
-    CStr::from_ptr(s.as_ptr()).to_string_lossy().to_string()
+fn c_str(s: &[c_char]) -> String {
+    let bytes: Vec<u8> = s
+        .iter()
+        .take_while(|&&c| c != 0)
+        .map(|&c| c as u8)
+        .collect();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+#[cfg(all(test, feature = "image"))]
+mod tests {
+    use super::*;
+
+    fn screenshot(width: u16, height: u16, bpp: u8, data: &[u8]) -> Image {
+        Image {
+            width,
+            height,
+            bpp,
+            data: data.to_vec(),
+        }
+    }
+
+    #[test]
+    fn rgb565() {
+        // Red, then blue, little-endian
+        let image = screenshot(2, 1, 16, &[0x00, 0xF8, 0x1F, 0x00]);
+        let image = image::DynamicImage::try_from(image).unwrap();
+        assert_eq!(image.to_rgb8().into_raw(), [255, 0, 0, 0, 0, 255]);
+
+        // Full green, then white
+        let image = screenshot(2, 1, 16, &[0xE0, 0x07, 0xFF, 0xFF]);
+        let image = image::DynamicImage::try_from(image).unwrap();
+        assert_eq!(image.to_rgb8().into_raw(), [0, 255, 0, 255, 255, 255]);
+    }
+
+    #[test]
+    fn grayscale() {
+        let image = image::DynamicImage::try_from(screenshot(2, 1, 8, &[0, 200])).unwrap();
+        assert_eq!(image.to_luma8().into_raw(), [0, 200]);
+    }
+
+    #[test]
+    fn short_data() {
+        let short: &[(u16, u16, u8, &[u8])] =
+            &[(2, 1, 16, &[0x00, 0xF8, 0x1F]), (2, 2, 8, &[0; 3])];
+        for &(width, height, bpp, data) in short {
+            assert!(matches!(
+                image::DynamicImage::try_from(screenshot(width, height, bpp, data)),
+                Err(Error::InvalidPacket)
+            ));
+        }
+        assert!(matches!(
+            image::DynamicImage::try_from(screenshot(1, 1, 4, &[0])),
+            Err(Error::UnknownBpp(4))
+        ));
+    }
 }
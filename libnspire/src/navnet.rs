@@ -0,0 +1,267 @@
+//! The packet format used by non-CX II calculators, mirroring `packet.c`.
+//! Magic numbers come from libnspire's `protocol.h`.
+
+use std::convert::TryInto;
+
+use libnspire_sys::{
+    NSPIRE_ADDR_DEVICE, NSPIRE_ADDR_HOST, NSPIRE_NAVNET_MAGIC, NSPIRE_SID_ACK, NSPIRE_SID_ACK_ZERO,
+    NSPIRE_SID_ADDR_ASSIGN, NSPIRE_SID_DEVINFO, NSPIRE_SID_DISCONNECT, NSPIRE_SID_ECHO,
+    NSPIRE_SID_FILE, NSPIRE_SID_KEYS, NSPIRE_SID_LOGIN, NSPIRE_SID_NACK, NSPIRE_SID_OS,
+    NSPIRE_SID_SCREENSHOT,
+};
+
+pub const MAGIC: u16 = NSPIRE_NAVNET_MAGIC as u16;
+pub const HEADER_SIZE: usize = header_size(HEADER);
+/// A `data_size` of this value means the length follows as a 32-bit integer.
+pub const BIG_DATA: u8 = 0xFF;
+/// The most data a single packet carries.
+pub const MAX_DATA: usize = 254;
+/// The most data a single packet carries on a CX II.
+pub const MAX_DATA_CX_II: usize = 1440;
+
+pub const HOST_ADDR: u16 = NSPIRE_ADDR_HOST as u16;
+pub const DEVICE_ADDR: u16 = NSPIRE_ADDR_DEVICE as u16;
+
+/// The service ID used by acknowledgements of packets with a sequence number.
+pub const SID_ACK: u16 = NSPIRE_SID_ACK as u16;
+/// The service ID used by acknowledgements of packets with sequence number 0.
+pub const SID_ACK_ZERO: u16 = NSPIRE_SID_ACK_ZERO as u16;
+pub const SID_NACK: u16 = NSPIRE_SID_NACK as u16;
+pub const SID_ECHO: u16 = NSPIRE_SID_ECHO as u16;
+pub const SID_ADDR_ASSIGN: u16 = NSPIRE_SID_ADDR_ASSIGN as u16;
+pub const SID_DEVINFO: u16 = NSPIRE_SID_DEVINFO as u16;
+pub const SID_SCREENSHOT: u16 = NSPIRE_SID_SCREENSHOT as u16;
+pub const SID_KEYS: u16 = NSPIRE_SID_KEYS as u16;
+pub const SID_LOGIN: u16 = NSPIRE_SID_LOGIN as u16;
+pub const SID_FILE: u16 = NSPIRE_SID_FILE as u16;
+pub const SID_OS: u16 = NSPIRE_SID_OS as u16;
+pub const SID_DISCONNECT: u16 = NSPIRE_SID_DISCONNECT as u16;
+
+/// The names of the service IDs.
+pub const SERVICES: &[(u16, &str)] = &[
+    (SID_NACK, "Nack"),
+    (SID_ACK_ZERO, "Ack (sequence 0)"),
+    (SID_ACK, "Ack"),
+    (SID_ECHO, "Echo"),
+    (SID_ADDR_ASSIGN, "Address assignment"),
+    (SID_DEVINFO, "Device info"),
+    (SID_SCREENSHOT, "Screenshot"),
+    (SID_KEYS, "Keypress"),
+    (SID_LOGIN, "Login"),
+    (SID_FILE, "File"),
+    (SID_OS, "OS install"),
+    (SID_DISCONNECT, "Disconnect"),
+];
+
+/// The names of the addresses.
+pub const ADDRESSES: &[(u16, &str)] = &[(HOST_ADDR, "Computer"), (DEVICE_ADDR, "Calculator")];
+
+/// A header field, described for other tools such as the
+/// [dissector][crate::dissector].
+pub struct Field {
+    /// An identifier, in `snake_case`.
+    pub name: &'static str,
+    pub label: &'static str,
+    /// The size in bytes. All fields are big-endian.
+    pub size: usize,
+    pub display: Display,
+}
+
+pub enum Display {
+    Hex,
+    Decimal,
+    /// Hexadecimal, with names for the listed values.
+    Names(&'static [(u16, &'static str)]),
+    /// Like `Names`, but the values may have `flag` set, meaning `label`.
+    Flagged {
+        names: &'static [(u16, &'static str)],
+        flag: u16,
+        label: &'static str,
+    },
+}
+
+/// The header, in order.
+pub const HEADER: &[Field] = &[
+    Field::new("magic", "Magic", 2, Display::Hex),
+    Field::new("src_addr", "Source address", 2, Display::Names(ADDRESSES)),
+    Field::new("src_sid", "Source service", 2, Display::Names(SERVICES)),
+    Field::new(
+        "dst_addr",
+        "Destination address",
+        2,
+        Display::Names(ADDRESSES),
+    ),
+    Field::new(
+        "dst_sid",
+        "Destination service",
+        2,
+        Display::Names(SERVICES),
+    ),
+    Field::new("data_csum", "Data checksum", 2, Display::Hex),
+    Field::new("data_size", "Data size", 1, Display::Decimal),
+    Field::new("ack", "Ack", 1, Display::Hex),
+    Field::new("seq", "Sequence number", 1, Display::Decimal),
+    Field::new("header_csum", "Header checksum", 1, Display::Hex),
+];
+
+impl Field {
+    pub const fn new(
+        name: &'static str,
+        label: &'static str,
+        size: usize,
+        display: Display,
+    ) -> Self {
+        Field {
+            name,
+            label,
+            size,
+            display,
+        }
+    }
+}
+
+pub const fn header_size(header: &[Field]) -> usize {
+    let mut size = 0;
+    let mut i = 0;
+    while i < header.len() {
+        size += header[i].size;
+        i += 1;
+    }
+    size
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Packet {
+    pub src_addr: u16,
+    pub src_sid: u16,
+    pub dst_addr: u16,
+    pub dst_sid: u16,
+    pub ack: u8,
+    pub seq: u8,
+    pub data: Vec<u8>,
+}
+
+impl Packet {
+    /// Whether this packet acknowledges (or refuses) another one.
+    pub fn is_ack(&self) -> bool {
+        matches!(self.src_sid, SID_ACK | SID_ACK_ZERO | SID_NACK)
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut data = vec![];
+        let data_size = if self.data.len() < BIG_DATA as usize {
+            self.data.len() as u8
+        } else {
+            data.extend_from_slice(&(self.data.len() as u32).to_be_bytes());
+            BIG_DATA
+        };
+        data.extend_from_slice(&self.data);
+
+        let mut buf = Vec::with_capacity(HEADER_SIZE + data.len());
+        for field in &[
+            MAGIC,
+            self.src_addr,
+            self.src_sid,
+            self.dst_addr,
+            self.dst_sid,
+            data_checksum(&data),
+        ] {
+            buf.extend_from_slice(&field.to_be_bytes());
+        }
+        buf.extend_from_slice(&[data_size, self.ack, self.seq]);
+        buf.push(header_checksum(&buf));
+        buf.extend_from_slice(&data);
+        buf
+    }
+
+    /// Parse a packet, returning `None` if it is truncated or its checksums
+    /// don't match.
+    pub fn decode(buf: &[u8]) -> Option<Packet> {
+        if buf.len() < HEADER_SIZE {
+            return None;
+        }
+        let field = |i: usize| u16::from_be_bytes([buf[i], buf[i + 1]]);
+        if field(0) != MAGIC || buf[15] != header_checksum(&buf[..15]) {
+            return None;
+        }
+        let rest = &buf[HEADER_SIZE..];
+        let (full_len, data) = match buf[12] {
+            BIG_DATA => {
+                let len = u32::from_be_bytes(rest.get(..4)?.try_into().ok()?) as usize;
+                (len.checked_add(4)?, rest.get(4..len.checked_add(4)?)?)
+            }
+            len => (len as usize, rest.get(..len as usize)?),
+        };
+        if field(10) != data_checksum(&rest[..full_len]) {
+            return None;
+        }
+        Some(Packet {
+            src_addr: field(2),
+            src_sid: field(4),
+            dst_addr: field(6),
+            dst_sid: field(8),
+            ack: buf[13],
+            seq: buf[14],
+            data: data.to_vec(),
+        })
+    }
+}
+
+fn header_checksum(header: &[u8]) -> u8 {
+    header.iter().fold(0u8, |sum, &b| sum.wrapping_add(b))
+}
+
+fn data_checksum(data: &[u8]) -> u16 {
+    let mut checksum: u16 = 0;
+    for &byte in data {
+        let tmp1 = u16::from(byte) << 8 | checksum >> 8;
+        checksum &= 0xFF;
+        let tmp2 = (((checksum & 0xF) << 4) ^ checksum) << 8;
+        let tmp3 = tmp2 >> 5;
+        checksum = tmp3 >> 7;
+        checksum ^= tmp1 ^ tmp2 ^ tmp3;
+    }
+    checksum
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_matches_encoding() {
+        let packet = Packet {
+            src_addr: HOST_ADDR,
+            src_sid: 0x8001,
+            dst_addr: DEVICE_ADDR,
+            dst_sid: SID_FILE,
+            ack: 0x0A,
+            seq: 7,
+            data: vec![1, 2, 3],
+        };
+        let buf = packet.encode();
+        let mut fields = vec![];
+        let mut offset = 0;
+        for field in HEADER {
+            let bytes = &buf[offset..offset + field.size];
+            let value = bytes.iter().fold(0u32, |n, &b| n << 8 | u32::from(b));
+            fields.push((field.name, value));
+            offset += field.size;
+        }
+        assert_eq!(offset, HEADER_SIZE);
+        let expected = [
+            ("magic", u32::from(MAGIC)),
+            ("src_addr", 0x6400),
+            ("src_sid", 0x8001),
+            ("dst_addr", 0x6401),
+            ("dst_sid", 0x4060),
+            ("data_csum", u32::from(data_checksum(&[1, 2, 3]))),
+            ("data_size", 3),
+            ("ack", 0x0A),
+            ("seq", 7),
+            ("header_csum", u32::from(buf[15])),
+        ];
+        assert_eq!(fields, expected);
+        assert_eq!(Packet::decode(&buf), Some(packet));
+    }
+}
@@ -0,0 +1,60 @@
+//! The packet format used by CX II calculators, which carries NavNet packets,
+//! mirroring `cx2.cpp`.
+
+use libnspire_sys::{
+    NSPIRE_NNSE_ACK_FLAG, NSPIRE_NNSE_ADDR_ALL, NSPIRE_NNSE_ADDR_CALC, NSPIRE_NNSE_ADDR_ME,
+    NSPIRE_NNSE_SVC_ADDR_REQ, NSPIRE_NNSE_SVC_ECHO, NSPIRE_NNSE_SVC_LOOPBACK,
+    NSPIRE_NNSE_SVC_STATS, NSPIRE_NNSE_SVC_STREAM, NSPIRE_NNSE_SVC_TIME, NSPIRE_NNSE_SVC_TRANSMIT,
+    NSPIRE_NNSE_SVC_UNKNOWN,
+};
+
+use crate::navnet::{header_size, Display, Field};
+
+pub const HEADER_SIZE: usize = header_size(HEADER);
+
+/// Set in the service of acknowledgements.
+pub const ACK_FLAG: u8 = NSPIRE_NNSE_ACK_FLAG as u8;
+/// The service that carries NavNet packets.
+pub const SVC_STREAM: u16 = NSPIRE_NNSE_SVC_STREAM as u16;
+
+/// The names of the services, without [`ACK_FLAG`].
+pub const SERVICES: &[(u16, &str)] = &[
+    (NSPIRE_NNSE_SVC_ADDR_REQ as u16, "Address request"),
+    (NSPIRE_NNSE_SVC_TIME as u16, "Time"),
+    (NSPIRE_NNSE_SVC_ECHO as u16, "Echo"),
+    (SVC_STREAM, "Stream"),
+    (NSPIRE_NNSE_SVC_TRANSMIT as u16, "Transmit"),
+    (NSPIRE_NNSE_SVC_LOOPBACK as u16, "Loopback"),
+    (NSPIRE_NNSE_SVC_STATS as u16, "Stats"),
+    (NSPIRE_NNSE_SVC_UNKNOWN as u16, "Unknown"),
+];
+
+/// The names of the addresses. The computer is assigned one by the
+/// calculator.
+pub const ADDRESSES: &[(u16, &str)] = &[
+    (NSPIRE_NNSE_ADDR_CALC as u16, "Calculator"),
+    (NSPIRE_NNSE_ADDR_ME as u16, "Me"),
+    (NSPIRE_NNSE_ADDR_ALL as u16, "All"),
+];
+
+/// The header, in order.
+pub const HEADER: &[Field] = &[
+    Field::new("misc", "Misc", 1, Display::Hex),
+    Field::new(
+        "service",
+        "Service",
+        1,
+        Display::Flagged {
+            names: SERVICES,
+            flag: ACK_FLAG as u16,
+            label: "Ack",
+        },
+    ),
+    Field::new("src", "Source address", 1, Display::Names(ADDRESSES)),
+    Field::new("dest", "Destination address", 1, Display::Names(ADDRESSES)),
+    Field::new("unknown", "Unknown", 1, Display::Hex),
+    Field::new("req_ack", "Requests ack", 1, Display::Hex),
+    Field::new("length", "Length", 2, Display::Decimal),
+    Field::new("seqno", "Sequence number", 2, Display::Decimal),
+    Field::new("csum", "Checksum", 2, Display::Hex),
+];
@@ -0,0 +1,360 @@
+//! Parsing of OS upgrade images (`.tno`, `.tnc`, `.tco`, `.tcc`, `.tco2`,
+//! `.tcc2` and `.tct2` files).
+//!
+//! Every image starts with a plain-text header such as
+//! `TI-Nspire.tcc2 5.3.0.564`, naming the kind of calculator it is built for
+//! and the version of the OS inside. The rest of the file is sent to the
+//! calculator as-is.
+
+use std::fmt;
+
+use crate::info::{HardwareType, Info, Version};
+use crate::{Error, Result};
+
+/// The magic bytes at the start of every OS image.
+const MAGIC: &[u8] = b"TI-Nspire.";
+/// The header is never anywhere near this long; stop looking for its end
+/// after this many bytes.
+const MAX_HEADER_LEN: usize = 64;
+
+/// The family of calculators an OS image can be installed on.
+#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
+pub enum OsTarget {
+    /// Original TI-Nspire (Clickpad or Touchpad), `.tno`.
+    Nspire,
+    /// Original TI-Nspire CAS, `.tnc`.
+    NspireCas,
+    /// TI-Nspire CX, `.tco`.
+    Cx,
+    /// TI-Nspire CX CAS, `.tcc`.
+    CxCas,
+    /// TI-Nspire CX II, `.tco2`.
+    CxII,
+    /// TI-Nspire CX II CAS, `.tcc2`.
+    CxIICas,
+    /// TI-Nspire CX II-T, `.tct2`.
+    CxIIT,
+}
+
+impl OsTarget {
+    /// Look up the target for an OS file extension, without the leading dot.
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        Some(match ext.to_ascii_lowercase().as_str() {
+            "tno" => OsTarget::Nspire,
+            "tnc" => OsTarget::NspireCas,
+            "tco" => OsTarget::Cx,
+            "tcc" => OsTarget::CxCas,
+            "tco2" => OsTarget::CxII,
+            "tcc2" => OsTarget::CxIICas,
+            "tct2" => OsTarget::CxIIT,
+            _ => return None,
+        })
+    }
+    /// The file extension used by images for this target, without the
+    /// leading dot.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OsTarget::Nspire => "tno",
+            OsTarget::NspireCas => "tnc",
+            OsTarget::Cx => "tco",
+            OsTarget::CxCas => "tcc",
+            OsTarget::CxII => "tco2",
+            OsTarget::CxIICas => "tcc2",
+            OsTarget::CxIIT => "tct2",
+        }
+    }
+    /// Whether this image contains CAS software.
+    pub fn is_cas(&self) -> bool {
+        matches!(
+            self,
+            OsTarget::NspireCas | OsTarget::CxCas | OsTarget::CxIICas
+        )
+    }
+    /// Whether this image is for a color (CX or CX II) model.
+    pub fn is_cx(&self) -> bool {
+        !matches!(self, OsTarget::Nspire | OsTarget::NspireCas)
+    }
+    /// Whether this image is for a CX II model.
+    pub fn is_cx_ii(&self) -> bool {
+        matches!(self, OsTarget::CxII | OsTarget::CxIICas | OsTarget::CxIIT)
+    }
+}
+
+impl fmt::Display for OsTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            OsTarget::Nspire => "TI-Nspire",
+            OsTarget::NspireCas => "TI-Nspire CAS",
+            OsTarget::Cx => "TI-Nspire CX",
+            OsTarget::CxCas => "TI-Nspire CX CAS",
+            OsTarget::CxII => "TI-Nspire CX II",
+            OsTarget::CxIICas => "TI-Nspire CX II CAS",
+            OsTarget::CxIIT => "TI-Nspire CX II-T",
+        })
+    }
+}
+
+/// How an OS image relates to the OS currently installed on a calculator.
+#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
+pub enum OsCompat {
+    /// The image is newer than the installed OS.
+    Upgrade,
+    /// The image is the same version as the installed OS.
+    Reinstall,
+    /// The image is older than the installed OS. The calculator may refuse
+    /// to install it.
+    Downgrade,
+}
+
+/// Progress of an OS installation, as reported by
+/// [`Handle::send_os`][crate::Handle::send_os].
+#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
+pub enum OsProgress {
+    /// The image is being sent to the calculator.
+    Uploading {
+        /// The number of bytes left to send.
+        remaining: usize,
+        /// The size of the image.
+        total: usize,
+    },
+    /// The image has been sent and the calculator is installing it. The value
+    /// is a percentage, and may go slightly over 100.
+    Installing(u8),
+}
+
+/// A parsed OS upgrade image.
+#[derive(Clone)]
+pub struct OsImage {
+    target: OsTarget,
+    version: Version,
+    data: Vec<u8>,
+}
+
+impl OsImage {
+    /// Parse the header of an OS image.
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self> {
+        if !data.starts_with(MAGIC) {
+            return Err(Error::InvalidOsImage);
+        }
+        let header = &data[MAGIC.len()..data.len().min(MAX_HEADER_LEN)];
+        let header = match header.iter().position(|&b| b == b'\n' || b == 0) {
+            Some(end) => &header[..end],
+            None => header,
+        };
+        let header = String::from_utf8_lossy(header);
+        let mut fields = header.split_ascii_whitespace();
+        let target = fields
+            .next()
+            .and_then(OsTarget::from_extension)
+            .ok_or(Error::InvalidOsImage)?;
+        let version = fields
+            .next()
+            .and_then(|version| version.parse().ok())
+            .ok_or(Error::InvalidOsImage)?;
+        Ok(OsImage {
+            target,
+            version,
+            data,
+        })
+    }
+    /// The kind of calculator this image is built for.
+    pub fn target(&self) -> OsTarget {
+        self.target
+    }
+    /// The version of the OS contained in this image.
+    pub fn version(&self) -> Version {
+        self.version
+    }
+    /// The size of the image in bytes, i.e. how much will be sent to the
+    /// calculator.
+    pub fn size(&self) -> usize {
+        self.data.len()
+    }
+    /// The raw contents of the image, suitable for
+    /// [`Handle::send_os`][crate::Handle::send_os].
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+    /// Check whether this image can be installed on a calculator, given its
+    /// [`Info`].
+    ///
+    /// Returns an error if the calculator would reject the image outright,
+    /// otherwise how the image's version compares to the installed one.
+    pub fn check_compat(&self, info: &Info) -> Result<OsCompat> {
+        if !info
+            .os_extension
+            .eq_ignore_ascii_case(self.target.extension())
+        {
+            return Err(Error::OsMismatch {
+                expected: info.os_extension.clone(),
+                found: self.target.extension().to_string(),
+            });
+        }
+        if !matches!(info.hw_type, HardwareType::Unknown(_))
+            && (info.hw_type.is_cas() != self.target.is_cas()
+                || info.hw_type.is_cx() != self.target.is_cx())
+        {
+            return Err(Error::OsMismatch {
+                expected: format!("{:?}", info.hw_type),
+                found: self.target.to_string(),
+            });
+        }
+        Ok(match self.version.cmp(&info.version) {
+            std::cmp::Ordering::Greater => OsCompat::Upgrade,
+            std::cmp::Ordering::Equal => OsCompat::Reinstall,
+            std::cmp::Ordering::Less => OsCompat::Downgrade,
+        })
+    }
+}
+
+impl fmt::Debug for OsImage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OsImage")
+            .field("target", &self.target)
+            .field("version", &self.version)
+            .field("size", &self.size())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TARGETS: &[(&str, OsTarget)] = &[
+        ("tno", OsTarget::Nspire),
+        ("tnc", OsTarget::NspireCas),
+        ("tco", OsTarget::Cx),
+        ("tcc", OsTarget::CxCas),
+        ("tco2", OsTarget::CxII),
+        ("tcc2", OsTarget::CxIICas),
+        ("tct2", OsTarget::CxIIT),
+    ];
+
+    fn image(header: &str) -> Result<OsImage> {
+        OsImage::from_bytes([header.as_bytes(), &[0; 16]].concat())
+    }
+
+    fn info(os_extension: &str, hw_type: HardwareType, version: &str) -> Info {
+        let mut info: Info = unsafe { std::mem::zeroed::<libnspire_sys::nspire_devinfo>() }.into();
+        info.os_extension = os_extension.to_string();
+        info.hw_type = hw_type;
+        info.version = version.parse().unwrap();
+        info
+    }
+
+    #[test]
+    fn extensions() {
+        for &(ext, target) in TARGETS {
+            assert_eq!(OsTarget::from_extension(ext), Some(target));
+            assert_eq!(OsTarget::from_extension(&ext.to_uppercase()), Some(target));
+            assert_eq!(target.extension(), ext);
+        }
+        assert_eq!(OsTarget::from_extension("tns"), None);
+    }
+
+    #[test]
+    fn parses_each_target() {
+        for &(ext, target) in TARGETS {
+            let image = image(&format!("TI-Nspire.{} 5.3.0.564\n", ext)).unwrap();
+            assert_eq!(image.target(), target);
+            assert_eq!(image.version(), "5.3.0.564".parse().unwrap());
+            assert_eq!(image.size(), image.data().len());
+        }
+    }
+
+    #[test]
+    fn header_ends_at_nul() {
+        let image = image("TI-Nspire.tcc 4.5.0.1180\0garbage").unwrap();
+        assert_eq!(image.target(), OsTarget::CxCas);
+        assert_eq!(image.version().build, 1180);
+    }
+
+    #[test]
+    fn rejects_truncated_headers() {
+        for data in &[
+            &b""[..],
+            b"TI-Nsp",
+            b"TI-Nspire.",
+            b"TI-Nspire.tcc",
+            b"TI-Nspire.tcc 4.5",
+            b"TI-Nspire.tcc 4.5.0.",
+            b"TI-Nspire.xyz 4.5.0.1180",
+            b"Not-Nspire.tcc 4.5.0.1180",
+        ] {
+            assert!(
+                matches!(
+                    OsImage::from_bytes(data.to_vec()),
+                    Err(Error::InvalidOsImage)
+                ),
+                "{:?} parsed",
+                String::from_utf8_lossy(data)
+            );
+        }
+    }
+
+    #[test]
+    fn version_parsing() {
+        let version: Version = " 5.3.0.564 ".parse().unwrap();
+        assert_eq!(
+            version,
+            Version {
+                major: 5,
+                minor: 3,
+                patch: 0,
+                build: 564
+            }
+        );
+        assert_eq!(version.to_string(), "5.3.0.564");
+        for bad in &["", "5.3.0", "5.3.0.564.1", "5.3.x.564", "256.0.0.0"] {
+            assert!(bad.parse::<Version>().is_err(), "{:?} parsed", bad);
+        }
+    }
+
+    #[test]
+    fn version_ordering() {
+        let versions: Vec<Version> = [
+            "3.9.0.463",
+            "4.5.0.1180",
+            "4.5.1.12",
+            "5.2.0.771",
+            "5.3.0.564",
+        ]
+        .iter()
+        .map(|v| v.parse().unwrap())
+        .collect();
+        for pair in versions.windows(2) {
+            assert!(pair[0] < pair[1], "{} >= {}", pair[0], pair[1]);
+        }
+    }
+
+    #[test]
+    fn compat() {
+        let image = image("TI-Nspire.tcc 4.5.0.1180\n").unwrap();
+        let check = |version| image.check_compat(&info("tcc", HardwareType::CasCx, version));
+        assert_eq!(check("4.4.0.532").unwrap(), OsCompat::Upgrade);
+        assert_eq!(check("4.5.0.1180").unwrap(), OsCompat::Reinstall);
+        assert_eq!(check("5.0.0.1").unwrap(), OsCompat::Downgrade);
+    }
+
+    #[test]
+    fn compat_mismatch() {
+        let image = image("TI-Nspire.tcc 4.5.0.1180\n").unwrap();
+        let wrong_extension = info("tco", HardwareType::NonCasCx, "4.4.0.532");
+        assert!(matches!(
+            image.check_compat(&wrong_extension),
+            Err(Error::OsMismatch { .. })
+        ));
+        // Reported extension matches, but the hardware doesn't
+        let wrong_hardware = info("tcc", HardwareType::NonCas, "4.4.0.532");
+        assert!(matches!(
+            image.check_compat(&wrong_hardware),
+            Err(Error::OsMismatch { .. })
+        ));
+        let unknown_hardware = info("tcc", HardwareType::Unknown(0x42), "4.4.0.532");
+        assert_eq!(
+            image.check_compat(&unknown_hardware).unwrap(),
+            OsCompat::Upgrade
+        );
+    }
+}
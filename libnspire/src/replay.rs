@@ -0,0 +1,208 @@
+//! Recording sessions with a calculator and playing them back.
+//!
+//! A [`Recording`] records everything a [`Transport`] exchanges, from the
+//! initial handshake on. A [`ReplayTransport`] then plays the calculator's
+//! side of a recording back to a [`Handle`][crate::Handle], and fails if the
+//! host says anything different from what was recorded. This locks down the
+//! exact wire behavior of the library against a recorded trace:
+//!
+//! ```
+//! use libnspire::capture::{Capture, LinkType};
+//! use libnspire::replay::{Recording, ReplayTransport};
+//! use libnspire::{sim::Simulator, Error, Handle};
+//! # fn main() -> libnspire::Result<()> {
+//! type UsbHandle = Handle<rusb::GlobalContext>;
+//!
+//! // Record a session, and save it
+//! let sim = Simulator::new();
+//! sim.add_file("/hello.tns", b"hello");
+//! let recording = Recording::default();
+//! let handle = UsbHandle::from_transport(recording.record(sim))?;
+//! handle.list_dir("/")?;
+//! drop(handle);
+//! let mut pcapng = vec![];
+//! recording.capture().write_pcapng(&mut pcapng, LinkType::User)?;
+//!
+//! // Doing the same thing again replays successfully
+//! let replay = ReplayTransport::new(&Capture::read_pcapng(&pcapng[..])?);
+//! let handle = UsbHandle::from_transport(replay.clone())?;
+//! let names: Vec<_> = handle.list_dir("/")?.iter().map(|f| f.name().to_owned()).collect();
+//! assert!(names.iter().any(|name| name.to_str() == Ok("hello.tns")));
+//! drop(handle);
+//! replay.finish()?;
+//!
+//! // Doing something else doesn't
+//! let replay = ReplayTransport::new(&recording.capture());
+//! let handle = UsbHandle::from_transport(replay.clone())?;
+//! assert!(handle.list_dir("/documents").is_err());
+//! drop(handle);
+//! assert!(matches!(replay.finish(), Err(Error::ReplayDiverged(_))));
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Traces of real calculators can be recorded by wrapping a
+//! [`UsbTransport`][crate::transport::UsbTransport], or read from Wireshark
+//! captures with [`Capture::read_pcapng`]. Replies that depend on the time,
+//! such as to a CX II's time requests, won't match. The traces in this
+//! crate's own tests are all recorded from the [simulator][crate::sim], so
+//! they don't show that the library matches real calculators.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use crate::capture::{Capture, Direction, Frame, Protocol};
+use crate::transport::Transport;
+use crate::{Error, Result};
+
+/// The frames exchanged by [`Recorder`]s made with
+/// [`record`][Recording::record]. Clones share the same frames.
+#[derive(Clone, Default)]
+pub struct Recording(Arc<Mutex<Vec<Frame>>>);
+
+impl Recording {
+    /// Record everything exchanged with `transport`.
+    pub fn record<T: Transport>(&self, transport: T) -> Recorder<T> {
+        Recorder {
+            transport,
+            recording: self.clone(),
+        }
+    }
+
+    /// The frames recorded so far.
+    pub fn capture(&self) -> Capture {
+        Capture::new(self.0.lock().unwrap().clone())
+    }
+}
+
+/// A [`Transport`] that records everything exchanged with another one.
+pub struct Recorder<T> {
+    transport: T,
+    recording: Recording,
+}
+
+impl<T: Transport> Recorder<T> {
+    fn push(&self, direction: Direction, data: &[u8]) {
+        self.recording.0.lock().unwrap().push(Frame {
+            time: SystemTime::now(),
+            direction,
+            protocol: if self.transport.is_cx_ii() {
+                Protocol::Nnse
+            } else {
+                Protocol::NavNet
+            },
+            data: data.to_vec(),
+        });
+    }
+}
+
+impl<T: Transport> Transport for Recorder<T> {
+    fn read(&mut self, buf: &mut [u8], timeout: Duration) -> Result<usize> {
+        let len = self.transport.read(buf, timeout)?;
+        self.push(Direction::Received, &buf[..len]);
+        Ok(len)
+    }
+    fn write(&mut self, buf: &[u8], timeout: Duration) -> Result<usize> {
+        let len = self.transport.write(buf, timeout)?;
+        self.push(Direction::Sent, &buf[..len]);
+        Ok(len)
+    }
+    fn is_cx_ii(&self) -> bool {
+        self.transport.is_cx_ii()
+    }
+}
+
+/// A calculator that replays a recorded session. Clones share the same
+/// session, so one can be given to a [`Handle`][crate::Handle] and the other
+/// checked with [`finish`][ReplayTransport::finish].
+#[derive(Clone)]
+pub struct ReplayTransport {
+    state: Arc<Mutex<State>>,
+    is_cx_ii: bool,
+}
+
+struct State {
+    frames: Vec<Frame>,
+    /// The frame to replay next.
+    next: usize,
+    /// How much of the next frame has been read, if it was received.
+    read: usize,
+    /// The frame the host first diverged at.
+    diverged: Option<usize>,
+}
+
+impl ReplayTransport {
+    /// Replay the frames in `capture` that went over USB.
+    pub fn new(capture: &Capture) -> Self {
+        let frames: Vec<_> = capture.wire_frames().cloned().collect();
+        let is_cx_ii = frames.iter().any(|f| f.protocol == Protocol::Nnse);
+        ReplayTransport {
+            state: Arc::new(Mutex::new(State {
+                frames,
+                next: 0,
+                read: 0,
+                diverged: None,
+            })),
+            is_cx_ii,
+        }
+    }
+
+    /// Check that the host did exactly what was recorded: it never diverged,
+    /// and replayed every frame.
+    pub fn finish(&self) -> Result<()> {
+        let state = self.state.lock().unwrap();
+        match state.diverged {
+            Some(frame) => Err(Error::ReplayDiverged(frame)),
+            None if state.next < state.frames.len() => {
+                Err(Error::ReplayIncomplete(state.frames.len() - state.next))
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl Transport for ReplayTransport {
+    fn read(&mut self, buf: &mut [u8], _timeout: Duration) -> Result<usize> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(frame) = state.diverged {
+            return Err(Error::ReplayDiverged(frame));
+        }
+        let (next, read) = (state.next, state.read);
+        let frame = match state.frames.get(next) {
+            Some(frame) if frame.direction == Direction::Received => frame,
+            // The calculator didn't say anything here
+            _ => return Err(Error::Timeout),
+        };
+        let len = buf.len().min(frame.data.len() - read);
+        buf[..len].copy_from_slice(&frame.data[read..read + len]);
+        if read + len == frame.data.len() {
+            state.next += 1;
+            state.read = 0;
+        } else {
+            state.read += len;
+        }
+        Ok(len)
+    }
+
+    fn write(&mut self, buf: &[u8], _timeout: Duration) -> Result<usize> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(frame) = state.diverged {
+            return Err(Error::ReplayDiverged(frame));
+        }
+        let next = state.next;
+        match state.frames.get(next) {
+            Some(frame) if frame.direction == Direction::Sent && frame.data == buf => {
+                state.next += 1;
+                Ok(buf.len())
+            }
+            _ => {
+                state.diverged = Some(next);
+                Err(Error::ReplayDiverged(next))
+            }
+        }
+    }
+
+    fn is_cx_ii(&self) -> bool {
+        self.is_cx_ii
+    }
+}
@@ -0,0 +1,761 @@
+//! A simulated calculator, for testing without hardware.
+//!
+//! [`Simulator`] answers the echo, device info, screenshot, keypress, login,
+//! file and OS services the way a (non-CX II) calculator does, keeping its
+//! files in memory. Pass it to [`Handle::from_transport`][crate::Handle::from_transport]:
+//!
+//! ```
+//! use libnspire::{sim::Simulator, Handle};
+//! # fn main() -> libnspire::Result<()> {
+//! let sim = Simulator::new();
+//! sim.add_file("/documents/hello.tns", b"hello");
+//!
+//! let handle = Handle::<rusb::GlobalContext>::from_transport(sim.clone())?;
+//! let list = handle.list_dir("/documents")?;
+//! assert_eq!(list[0].name().to_str(), Ok("hello.tns"));
+//!
+//! handle.write_file("/documents/new.tns", b"world", &mut |_| {})?;
+//! assert_eq!(sim.file("/documents/new.tns").as_deref(), Some(&b"world"[..]));
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::info::Version;
+use crate::keys::Key;
+use crate::navnet::*;
+use crate::os_image::OsImage;
+use crate::transport::Transport;
+use crate::{Error, Image, Result};
+
+/// Sent in place of a success code when an operation fails.
+const FAILED: [u8; 2] = [0xFF, 0x0A];
+const OK: [u8; 2] = [0xFF, 0x00];
+
+/// A simulated calculator.
+///
+/// Each clone is a separate connection to the same calculator: the clones
+/// share files and settings, but a new [`Handle`][crate::Handle] must be
+/// given a fresh clone.
+pub struct Simulator {
+    device: Arc<Mutex<Device>>,
+    link: Link,
+}
+
+struct Device {
+    name: String,
+    id: String,
+    version: Version,
+    files: BTreeMap<String, Node>,
+    screen: Image,
+    installed_os: Option<Vec<u8>>,
+    keys: Vec<Key>,
+    client_name: Option<String>,
+    big_packets: bool,
+    latency: Duration,
+    corrupt_writes: usize,
+}
+
+enum Node {
+    Dir { date: u32 },
+    File { date: u32, data: Vec<u8> },
+}
+
+/// The state of one connection.
+#[derive(Default)]
+struct Link {
+    /// Packets waiting to be read by the host.
+    outgoing: VecDeque<Vec<u8>>,
+    seq: u8,
+    op: Op,
+}
+
+/// A multi-packet operation in progress.
+#[derive(Default)]
+enum Op {
+    #[default]
+    None,
+    DirList(VecDeque<Vec<u8>>),
+    Write {
+        path: String,
+        size: usize,
+        data: Vec<u8>,
+    },
+    Read(Vec<u8>),
+    Os {
+        size: usize,
+        data: Vec<u8>,
+    },
+}
+
+impl Simulator {
+    /// A TI-Nspire CX CAS named "Simulator" with an empty `/documents`
+    /// directory.
+    pub fn new() -> Self {
+        let mut files = BTreeMap::new();
+        files.insert("/documents".to_string(), Node::Dir { date: now() });
+        let device = Device {
+            name: "Simulator".to_string(),
+            id: "1000000000000000000000001".to_string(),
+            version: Version {
+                major: 4,
+                minor: 5,
+                patch: 0,
+                build: 1180,
+            },
+            files,
+            screen: Image {
+                width: 320,
+                height: 240,
+                bpp: 16,
+                data: vec![0xFF; 320 * 240 * 2],
+            },
+            installed_os: None,
+            keys: vec![],
+            client_name: None,
+            big_packets: false,
+            latency: Duration::ZERO,
+            corrupt_writes: 0,
+        };
+        Simulator::connect(Arc::new(Mutex::new(device)))
+    }
+
+    fn connect(device: Arc<Mutex<Device>>) -> Self {
+        let mut link = Link::default();
+        // The calculator asks for an address as soon as it's plugged in
+        link.send(
+            SID_ADDR_ASSIGN,
+            SID_ADDR_ASSIGN,
+            vec![0x64, 0x01, 0xFF, 0x00],
+        );
+        Simulator { device, link }
+    }
+
+    fn device(&self) -> std::sync::MutexGuard<'_, Device> {
+        self.device.lock().unwrap()
+    }
+
+    /// Set the name reported by [`Handle::info`][crate::Handle::info].
+    pub fn set_name(&self, name: &str) {
+        self.device().name = name.to_string();
+    }
+
+    /// Set the ID ("serial number") reported by
+    /// [`Handle::info`][crate::Handle::info].
+    pub fn set_id(&self, id: &str) {
+        self.device().id = id.to_string();
+    }
+
+    /// Set the OS version. Installing an OS image changes it to the image's
+    /// version.
+    pub fn set_version(&self, version: Version) {
+        self.device().version = version;
+    }
+
+    /// Set what screenshots show.
+    pub fn set_screen(&self, screen: Image) {
+        self.device().screen = screen;
+    }
+
+    /// Set whether the calculator accepts packets of more than 254 bytes.
+    /// Off by default. When set, the simulator also sends files in such
+    /// packets, which a real calculator may not do. See
+    /// [`HandleBuilder::big_packets`][crate::HandleBuilder::big_packets].
+    pub fn set_big_packets(&self, enabled: bool) {
+        self.device().big_packets = enabled;
+    }
+
+    /// Set how long each packet takes to reach the calculator, to see how
+    /// round trips add up as they would over USB. None by default.
+    pub fn set_latency(&self, latency: Duration) {
+        self.device().latency = latency;
+    }
+
+    /// Flip a bit in the middle of each of the next `count` non-empty files
+    /// written, as a bad cable might.
+    pub fn set_corrupt_writes(&self, count: usize) {
+        self.device().corrupt_writes = count;
+    }
+
+    /// Create a directory, along with any missing parents.
+    pub fn add_dir(&self, path: &str) {
+        self.device().add_dir(&normalize(path));
+    }
+
+    /// Create or replace a file, creating any missing parent directories.
+    pub fn add_file(&self, path: &str, data: &[u8]) {
+        let path = normalize(path);
+        let mut device = self.device();
+        device.add_dir(parent(&path));
+        let node = Node::File {
+            date: now(),
+            data: data.to_vec(),
+        };
+        device.files.insert(path, node);
+    }
+
+    /// The contents of a file, or `None` if there is no such file.
+    pub fn file(&self, path: &str) -> Option<Vec<u8>> {
+        match self.device().files.get(&normalize(path)) {
+            Some(Node::File { data, .. }) => Some(data.clone()),
+            _ => None,
+        }
+    }
+
+    /// The last OS image sent to the calculator.
+    pub fn installed_os(&self) -> Option<Vec<u8>> {
+        self.device().installed_os.clone()
+    }
+
+    /// The name the host last logged in with.
+    pub fn client_name(&self) -> Option<String> {
+        self.device().client_name.clone()
+    }
+
+    /// Every key pressed so far, in order.
+    pub fn keys(&self) -> Vec<Key> {
+        self.device().keys.clone()
+    }
+}
+
+impl Default for Simulator {
+    fn default() -> Self {
+        Simulator::new()
+    }
+}
+
+impl Clone for Simulator {
+    fn clone(&self) -> Self {
+        Simulator::connect(self.device.clone())
+    }
+}
+
+impl Transport for Simulator {
+    fn read(&mut self, buf: &mut [u8], _timeout: Duration) -> Result<usize> {
+        let packet = self.link.outgoing.pop_front().ok_or(Error::Timeout)?;
+        let len = packet.len().min(buf.len());
+        buf[..len].copy_from_slice(&packet[..len]);
+        Ok(len)
+    }
+
+    fn write(&mut self, buf: &[u8], _timeout: Duration) -> Result<usize> {
+        let latency = self.device().latency;
+        if !latency.is_zero() {
+            std::thread::sleep(latency);
+        }
+        // A real calculator drops corrupted packets, too
+        if let Some(packet) = Packet::decode(buf) {
+            let mut device = self.device.lock().unwrap();
+            self.link.receive(&mut device, packet);
+        }
+        Ok(buf.len())
+    }
+}
+
+impl Link {
+    /// Queue a packet for the host.
+    fn send(&mut self, src_sid: u16, dst_sid: u16, data: Vec<u8>) {
+        self.seq = self.seq.wrapping_add(1).max(1);
+        let packet = Packet {
+            src_addr: DEVICE_ADDR,
+            src_sid,
+            dst_addr: HOST_ADDR,
+            dst_sid,
+            ack: 0,
+            seq: self.seq,
+            data,
+        };
+        self.outgoing.push_back(packet.encode());
+    }
+
+    fn acknowledge(&mut self, packet: &Packet, src_sid: u16, dst_sid: u16) {
+        let ack = Packet {
+            src_addr: DEVICE_ADDR,
+            src_sid,
+            dst_addr: HOST_ADDR,
+            dst_sid,
+            ack: 0x0A,
+            seq: packet.seq,
+            data: packet.dst_sid.to_be_bytes().to_vec(),
+        };
+        self.outgoing.push_back(ack.encode());
+    }
+
+    fn receive(&mut self, device: &mut Device, packet: Packet) {
+        if packet.is_ack() || packet.dst_sid == SID_ADDR_ASSIGN {
+            return;
+        }
+        if packet.data.len() > device.max_data() {
+            self.acknowledge(&packet, SID_NACK, packet.src_sid);
+            return;
+        }
+        let ack_sid = if packet.seq == 0 {
+            SID_ACK_ZERO
+        } else {
+            SID_ACK
+        };
+        if packet.src_sid == SID_DISCONNECT {
+            // The host is waiting for an ack addressed to the connection it's
+            // closing
+            let host_sid = match packet.data[..] {
+                [high, low, ..] => u16::from_be_bytes([high, low]),
+                _ => return,
+            };
+            self.acknowledge(&packet, ack_sid, host_sid);
+            self.op = Op::None;
+            return;
+        }
+
+        let replies = match packet.dst_sid {
+            SID_ECHO => Some(vec![packet.data.clone()]),
+            SID_DEVINFO => device.devinfo(&packet.data),
+            SID_SCREENSHOT => device.screenshot(&packet.data),
+            SID_KEYS => device.keys(&packet.data),
+            SID_LOGIN => device.login(&packet.data),
+            SID_FILE => self.file(device, &packet.data),
+            SID_OS => self.os(device, &packet.data),
+            _ => None,
+        };
+        match replies {
+            Some(replies) => {
+                self.acknowledge(&packet, ack_sid, packet.src_sid);
+                for reply in replies {
+                    self.send(packet.dst_sid, packet.src_sid, reply);
+                }
+            }
+            None => self.acknowledge(&packet, SID_NACK, packet.src_sid),
+        }
+    }
+
+    fn file(&mut self, device: &mut Device, data: &[u8]) -> Option<Vec<Vec<u8>>> {
+        match (&mut self.op, data) {
+            (Op::Write { path, size, data }, [0x05, chunk @ ..]) => {
+                data.extend_from_slice(chunk);
+                if data.len() < *size {
+                    return Some(vec![]);
+                }
+                let path = std::mem::take(path);
+                let mut data = std::mem::take(data);
+                self.op = Op::None;
+                if device.corrupt_writes > 0 {
+                    device.corrupt_writes -= 1;
+                    let middle = data.len() / 2;
+                    data[middle] ^= 1;
+                }
+                device.files.insert(path, Node::File { date: now(), data });
+                return Some(vec![OK.to_vec()]);
+            }
+            (Op::Read(data), [0x04]) => {
+                let data = std::mem::take(data);
+                return Some(
+                    data.chunks(device.max_data() - 1)
+                        .map(|chunk| [&[0x05], chunk].concat())
+                        .collect(),
+                );
+            }
+            (Op::Read(_), [0xFF, 0x00]) => {
+                self.op = Op::None;
+                return Some(vec![]);
+            }
+            // Nothing else can be done until the host ends the transfer
+            (Op::Read(_), _) => return None,
+            (Op::DirList(entries), [0x0E]) => {
+                return Some(vec![entries.pop_front().unwrap_or_else(|| OK.to_vec())]);
+            }
+            (Op::DirList(_), [0x0F]) => {
+                self.op = Op::None;
+                return Some(vec![OK.to_vec()]);
+            }
+            _ => {}
+        }
+
+        let reply = match data {
+            [0x0D, path @ ..] => {
+                let path = normalize(&string(path)?);
+                match device.list(&path) {
+                    Ok(entries) => {
+                        self.op = Op::DirList(entries.into());
+                        vec![0xFF, 0x00]
+                    }
+                    Err(Error::Invalid) => vec![0xFF, 0x0F],
+                    Err(_) => FAILED.to_vec(),
+                }
+            }
+            [0x20, 0x01, path @ ..] => match device.node(&normalize(&string(path)?)) {
+                Some((size, date, is_dir)) => {
+                    let mut reply = vec![0x20];
+                    reply.extend_from_slice(&size.to_be_bytes());
+                    reply.extend_from_slice(&date.to_be_bytes());
+                    reply.extend_from_slice(&[is_dir as u8, 0]);
+                    reply
+                }
+                None => FAILED.to_vec(),
+            },
+            [0x0A, 0x03, path @ ..] => status(device.create_dir(&normalize(&string(path)?))),
+            [0x0B, 0x03, path @ ..] => status(device.delete_dir(&normalize(&string(path)?))),
+            [0x09, 0x01, path @ ..] => status(device.delete_file(&normalize(&string(path)?))),
+            [0x21, 0x01, rest @ ..] => {
+                let (src, dest) = two_strings(rest)?;
+                status(device.rename(&src, &dest))
+            }
+            [0x0C, 0x01, rest @ ..] => {
+                let (src, dest) = two_strings(rest)?;
+                status(device.copy(&src, &dest))
+            }
+            [0x03, 0x01, rest @ ..] => {
+                let path = string(rest)?;
+                let size = rest.get(padded_len(&path)..padded_len(&path) + 4)?;
+                let size = u32::from_be_bytes([size[0], size[1], size[2], size[3]]) as usize;
+                let path = normalize(&path);
+                if !device.can_write(&path) {
+                    return Some(vec![FAILED.to_vec()]);
+                }
+                if size == 0 {
+                    let node = Node::File {
+                        date: now(),
+                        data: vec![],
+                    };
+                    device.files.insert(path, node);
+                    return Some(vec![vec![0x04], OK.to_vec()]);
+                }
+                self.op = Op::Write {
+                    path,
+                    size,
+                    data: vec![],
+                };
+                vec![0x04]
+            }
+            [0x07, 0x01, path @ ..] => match device.files.get(&normalize(&string(path)?)) {
+                Some(Node::File { data, .. }) => {
+                    let mut reply = vec![0x03, 0x01];
+                    reply.extend_from_slice(&[0; 9]);
+                    reply.extend_from_slice(&(data.len() as u32).to_be_bytes());
+                    self.op = Op::Read(data.clone());
+                    reply
+                }
+                // Still needs to be long enough to parse
+                _ => [&FAILED[..], &[0; 13]].concat(),
+            },
+            _ => return None,
+        };
+        Some(vec![reply])
+    }
+
+    fn os(&mut self, device: &mut Device, data: &[u8]) -> Option<Vec<Vec<u8>>> {
+        match (&mut self.op, data) {
+            (Op::None, [0x03, a, b, c, d]) => {
+                let size = u32::from_be_bytes([*a, *b, *c, *d]) as usize;
+                self.op = Op::Os { size, data: vec![] };
+                Some(vec![vec![0x04]])
+            }
+            (Op::Os { size, data }, [0x05, chunk @ ..]) => {
+                let mut replies = vec![];
+                if data.is_empty() {
+                    replies.push(OK.to_vec());
+                }
+                data.extend_from_slice(chunk);
+                if data.len() < *size {
+                    return Some(replies);
+                }
+                let data = std::mem::take(data);
+                self.op = Op::None;
+                match OsImage::from_bytes(data.clone()) {
+                    Ok(image) => {
+                        device.version = image.version();
+                        device.installed_os = Some(data);
+                        replies.extend((0..=100).step_by(25).map(|percent| vec![0x00, percent]));
+                    }
+                    Err(_) => replies.push(vec![0xFF, 0x01]),
+                }
+                Some(replies)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Device {
+    /// The most data a packet to or from the calculator can carry.
+    fn max_data(&self) -> usize {
+        if self.big_packets {
+            MAX_DATA_CX_II
+        } else {
+            MAX_DATA
+        }
+    }
+
+    fn devinfo(&self, data: &[u8]) -> Option<Vec<Vec<u8>>> {
+        let reply = match data {
+            [0x01] => {
+                let mut reply = vec![0x01];
+                for n in &[100 << 20, 115 << 20, 30u64 << 20, 64 << 20] {
+                    reply.extend_from_slice(&n.to_be_bytes());
+                }
+                // Battery OK, not charging, 132 MHz
+                reply.extend_from_slice(&[0x7F, 0, 0, 132]);
+                for version in &[self.version, self.version, self.version] {
+                    reply.extend_from_slice(&[version.major, version.minor * 10 + version.patch]);
+                    reply.extend_from_slice(&version.build.to_be_bytes());
+                }
+                // Hardware version
+                reply.extend_from_slice(&[0; 4]);
+                // Run level: OS
+                reply.extend_from_slice(&2u16.to_be_bytes());
+                let lcd = [0, 0, self.screen.width, self.screen.height];
+                for n in &lcd {
+                    reply.extend_from_slice(&n.to_be_bytes());
+                }
+                // Sample mode, hardware type: CX CAS
+                reply.extend_from_slice(&[self.screen.bpp, 0, 0x0F]);
+                let mut id = self.id.as_bytes().to_vec();
+                id.resize(27, 0);
+                reply.extend_from_slice(&id[..17]);
+                reply.extend_from_slice(&id);
+                reply
+            }
+            [0x02] => [&[0x02], self.name.as_bytes(), &[0]].concat(),
+            [0x03] => b"\x03tns\0tcc\0".to_vec(),
+            _ => return None,
+        };
+        Some(vec![reply])
+    }
+
+    fn screenshot(&self, data: &[u8]) -> Option<Vec<Vec<u8>>> {
+        if data != [0x00] {
+            return None;
+        }
+        let rle = rle_encode(&self.screen.data);
+        let mut header = vec![0x00];
+        header.extend_from_slice(&(rle.len() as u32).to_be_bytes());
+        for n in &[0, 0, self.screen.width, self.screen.height] {
+            header.extend_from_slice(&n.to_be_bytes());
+        }
+        header.extend_from_slice(&[self.screen.bpp, 0]);
+        let mut replies = vec![header];
+        replies.extend(
+            rle.chunks(MAX_DATA - 1)
+                .map(|chunk| [&[0x00], chunk].concat()),
+        );
+        Some(replies)
+    }
+
+    fn login(&mut self, data: &[u8]) -> Option<Vec<Vec<u8>>> {
+        match data {
+            [0x01, name @ .., 0] => {
+                self.client_name = Some(String::from_utf8_lossy(name).into_owned());
+                Some(vec![OK.to_vec()])
+            }
+            _ => None,
+        }
+    }
+
+    /// Keypresses are acknowledged, but never answered.
+    fn keys(&mut self, data: &[u8]) -> Option<Vec<Vec<u8>>> {
+        match data {
+            [0x01, 0, 0, 0x80] => {}
+            [0x01, _, _, _, 0x80, .., high, low] if data.len() == 26 => {
+                let key = Key::from_code(u16::from_be_bytes([*high, *low]))?;
+                self.keys.push(key);
+            }
+            _ => return None,
+        }
+        Some(vec![])
+    }
+
+    fn is_dir(&self, path: &str) -> bool {
+        path == "/" || matches!(self.files.get(path), Some(Node::Dir { .. }))
+    }
+
+    /// The size, date and type of a file or directory.
+    fn node(&self, path: &str) -> Option<(u32, u32, bool)> {
+        match self.files.get(path) {
+            Some(Node::File { date, data }) => Some((data.len() as u32, *date, false)),
+            Some(Node::Dir { date }) => Some((0, *date, true)),
+            None if path == "/" => Some((0, 0, true)),
+            None => None,
+        }
+    }
+
+    fn children<'a>(&'a self, dir: &'a str) -> impl Iterator<Item = (&'a String, &'a Node)> {
+        self.files
+            .iter()
+            .filter(move |(path, _)| *path != "/" && parent(path) == dir)
+    }
+
+    fn list(&self, dir: &str) -> Result<Vec<Vec<u8>>> {
+        if !self.is_dir(dir) {
+            return Err(if self.files.contains_key(dir) {
+                Error::Invalid
+            } else {
+                Error::DoesNotExist
+            });
+        }
+        Ok(self
+            .children(dir)
+            .map(|(path, node)| {
+                let (size, date, is_dir) = match node {
+                    Node::File { date, data } => (data.len() as u32, *date, false),
+                    Node::Dir { date } => (0, *date, true),
+                };
+                let mut entry = vec![0x10, 0x00, 0x00];
+                entry.extend_from_slice(&padded(base_name(path)));
+                entry.extend_from_slice(&size.to_be_bytes());
+                entry.extend_from_slice(&date.to_be_bytes());
+                entry.extend_from_slice(&[is_dir as u8, 0]);
+                entry
+            })
+            .collect())
+    }
+
+    fn add_dir(&mut self, path: &str) {
+        if path == "/" || self.is_dir(path) {
+            return;
+        }
+        self.add_dir(parent(path));
+        self.files
+            .insert(path.to_string(), Node::Dir { date: now() });
+    }
+
+    fn can_write(&self, path: &str) -> bool {
+        self.is_dir(parent(path)) && !self.is_dir(path)
+    }
+
+    fn create_dir(&mut self, path: &str) -> Result<()> {
+        if !self.is_dir(parent(path)) || path == "/" || self.files.contains_key(path) {
+            return Err(Error::Exists);
+        }
+        self.files
+            .insert(path.to_string(), Node::Dir { date: now() });
+        Ok(())
+    }
+
+    fn delete_dir(&mut self, path: &str) -> Result<()> {
+        if path == "/" || !self.is_dir(path) || self.children(path).next().is_some() {
+            return Err(Error::Invalid);
+        }
+        self.files.remove(path);
+        Ok(())
+    }
+
+    fn delete_file(&mut self, path: &str) -> Result<()> {
+        match self.files.get(path) {
+            Some(Node::File { .. }) => {
+                self.files.remove(path);
+                Ok(())
+            }
+            _ => Err(Error::DoesNotExist),
+        }
+    }
+
+    fn rename(&mut self, src: &str, dest: &str) -> Result<()> {
+        let inside =
+            |path: &str, dir: &str| path.starts_with(dir) && path[dir.len()..].starts_with('/');
+        if !self.files.contains_key(src)
+            || self.files.contains_key(dest)
+            || !self.is_dir(parent(dest))
+            || inside(dest, src)
+        {
+            return Err(Error::Invalid);
+        }
+        let moved: Vec<String> = self
+            .files
+            .keys()
+            .filter(|path| *path == src || inside(path, src))
+            .cloned()
+            .collect();
+        for path in moved {
+            let node = self.files.remove(&path).unwrap();
+            self.files
+                .insert(format!("{}{}", dest, &path[src.len()..]), node);
+        }
+        Ok(())
+    }
+
+    fn copy(&mut self, src: &str, dest: &str) -> Result<()> {
+        let data = match self.files.get(src) {
+            Some(Node::File { data, .. }) => data.clone(),
+            _ => return Err(Error::Invalid),
+        };
+        if self.files.contains_key(dest) || !self.is_dir(parent(dest)) {
+            return Err(Error::Invalid);
+        }
+        self.files
+            .insert(dest.to_string(), Node::File { date: now(), data });
+        Ok(())
+    }
+}
+
+fn status(result: Result<()>) -> Vec<u8> {
+    match result {
+        Ok(()) => OK.to_vec(),
+        Err(_) => FAILED.to_vec(),
+    }
+}
+
+/// Read a NUL-terminated string at the start of `data`.
+fn string(data: &[u8]) -> Option<String> {
+    let end = data.iter().position(|&b| b == 0)?;
+    Some(String::from_utf8_lossy(&data[..end]).into_owned())
+}
+
+/// Read a source and destination path, each padded as `padded` does.
+fn two_strings(data: &[u8]) -> Option<(String, String)> {
+    let src = string(data)?;
+    let dest = string(data.get(padded_len(&src)..)?)?;
+    Some((normalize(&src), normalize(&dest)))
+}
+
+/// Paths in packets are NUL-terminated and padded to at least 9 bytes.
+fn padded(s: &str) -> Vec<u8> {
+    let mut buf = s.as_bytes().to_vec();
+    buf.resize(padded_len(s), 0);
+    buf
+}
+
+fn padded_len(s: &str) -> usize {
+    s.len().max(8) + 1
+}
+
+/// Remove trailing slashes and make the path absolute.
+fn normalize(path: &str) -> String {
+    let path = path.trim_end_matches('/');
+    if path.starts_with('/') {
+        path.to_string()
+    } else {
+        format!("/{}", path)
+    }
+}
+
+fn parent(path: &str) -> &str {
+    match path.rfind('/') {
+        Some(0) | None => "/",
+        Some(i) => &path[..i],
+    }
+}
+
+fn base_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+fn now() -> u32 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs() as u32)
+}
+
+/// Compress a screenshot the way the calculator does: a signed length byte,
+/// followed by either one byte to repeat (length + 1 times) or, if the length
+/// is negative, -length + 1 literal bytes. This only uses repeats.
+fn rle_encode(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![];
+    let mut rest = data;
+    while let Some(&byte) = rest.first() {
+        let run = rest.iter().take(128).take_while(|&&b| b == byte).count();
+        out.extend_from_slice(&[(run - 1) as u8, byte]);
+        rest = &rest[run..];
+    }
+    out
+}
@@ -0,0 +1,842 @@
+//! Reading TI-Nspire documents (`.tns` files).
+//!
+//! A document is a zip archive prefixed with a short `*TIMLP` header. It
+//! contains a `Document.xml` along with one `ProblemN.xml` per problem, each
+//! of which holds that problem's pages ("cards"). Documents saved by TI's
+//! software usually have their XML encrypted, in which case only the list of
+//! entries is available. Documents made by third-party tools such as Luna,
+//! or by [`Builder`], store them as plain XML.
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::{Read, Write};
+
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::{Compression, Crc};
+
+use crate::{Error, Result};
+
+/// The magic bytes at the start of every document.
+const MAGIC: &[u8] = b"*TIMLP";
+/// The header version written by [`Builder`], understood by OS 3.0.2 and
+/// later.
+const BUILDER_VERSION: &str = "0500";
+
+const LOCAL_HEADER_SIG: u32 = 0x0403_4b50;
+const CENTRAL_HEADER_SIG: u32 = 0x0201_4b50;
+const END_OF_CENTRAL_DIR_SIG: u32 = 0x0605_4b50;
+/// Version 2.0, needed for deflate
+const ZIP_VERSION: u16 = 20;
+const LOCAL_HEADER_LEN: usize = 30;
+const CENTRAL_HEADER_LEN: usize = 46;
+const END_OF_CENTRAL_DIR_LEN: usize = 22;
+
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATE: u16 = 8;
+/// The most deflate can compress data by, which bounds how big an entry's
+/// contents can really be, whatever its header says.
+const MAX_DEFLATE_RATIO: usize = 1032;
+
+/// A single file inside a document.
+#[derive(Clone)]
+pub struct Entry {
+    name: String,
+    method: u16,
+    crc: u32,
+    size: u32,
+    data: Vec<u8>,
+}
+
+impl Entry {
+    /// The path of this entry within the document, e.g. `Problem1.xml`.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    /// The uncompressed size of this entry.
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+    /// Whether this entry is stored with TI's proprietary encryption, and so
+    /// can't be read.
+    pub fn is_encrypted(&self) -> bool {
+        !matches!(self.method, METHOD_STORED | METHOD_DEFLATE)
+    }
+    /// The uncompressed contents of this entry.
+    pub fn contents(&self) -> Result<Vec<u8>> {
+        let contents = match self.method {
+            METHOD_STORED => self.data.clone(),
+            METHOD_DEFLATE => {
+                // The size comes from the header, so don't trust it further
+                // than the compressed data could go
+                let capacity =
+                    (self.size as usize).min(self.data.len().saturating_mul(MAX_DEFLATE_RATIO));
+                let mut out = Vec::with_capacity(capacity);
+                DeflateDecoder::new(&self.data[..])
+                    .take(self.size as u64)
+                    .read_to_end(&mut out)
+                    .map_err(|_| Error::InvalidTns)?;
+                out
+            }
+            _ => return Err(Error::EncryptedTns),
+        };
+        let mut crc = Crc::new();
+        crc.update(&contents);
+        if contents.len() != self.size as usize || crc.sum() != self.crc {
+            return Err(Error::InvalidTns);
+        }
+        Ok(contents)
+    }
+}
+
+impl fmt::Debug for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Entry")
+            .field("name", &self.name)
+            .field("size", &self.size)
+            .field("encrypted", &self.is_encrypted())
+            .finish()
+    }
+}
+
+/// A page of a problem.
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+pub struct Page {
+    /// The applications shown on this page, e.g. `TI.ScriptApp` for a Lua
+    /// script or `TI.GraphsApp`. A page split into several panes has one
+    /// entry per pane.
+    pub apps: Vec<String>,
+}
+
+/// A problem within a document.
+#[derive(Clone, Debug)]
+pub struct Problem {
+    /// The name of the entry this problem is stored in.
+    pub entry: String,
+    /// Whether the problem is encrypted. If so, `pages` and `scripts` are
+    /// empty.
+    pub encrypted: bool,
+    pub pages: Vec<Page>,
+    /// The source of every Lua script in this problem.
+    pub scripts: Vec<String>,
+}
+
+/// A parsed TI-Nspire document.
+#[derive(Clone, Debug)]
+pub struct Document {
+    version: String,
+    entries: Vec<Entry>,
+}
+
+impl Document {
+    /// Parse a document.
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        if !data.starts_with(MAGIC) {
+            return Err(Error::InvalidTns);
+        }
+        let zip_start = find(&data[MAGIC.len()..], &LOCAL_HEADER_SIG.to_le_bytes())
+            .map(|i| i + MAGIC.len())
+            .ok_or(Error::InvalidTns)?;
+        let version = String::from_utf8_lossy(&data[MAGIC.len()..zip_start])
+            .trim_end_matches('\0')
+            .to_string();
+        let entries = match read_central_dir(data)? {
+            Some(entries) => entries,
+            None => read_local_headers(&data[zip_start..])?,
+        };
+        Ok(Document { version, entries })
+    }
+    /// The format version from the header, e.g. `0500`.
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+    /// Every file in the document.
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+    /// Look up a file in the document by name.
+    pub fn entry(&self, name: &str) -> Option<&Entry> {
+        self.entries.iter().find(|entry| entry.name == name)
+    }
+    /// Whether any of the document's XML is encrypted.
+    pub fn is_encrypted(&self) -> bool {
+        self.entries
+            .iter()
+            .any(|entry| entry.name.ends_with(".xml") && entry.is_encrypted())
+    }
+    /// The problems in this document, in order.
+    pub fn problems(&self) -> Result<Vec<Problem>> {
+        let mut entries: Vec<(u32, &Entry)> = self
+            .entries
+            .iter()
+            .filter_map(|entry| {
+                let n = entry
+                    .name
+                    .strip_prefix("Problem")?
+                    .strip_suffix(".xml")?
+                    .parse()
+                    .ok()?;
+                Some((n, entry))
+            })
+            .collect();
+        entries.sort_by_key(|&(n, _)| n);
+        entries
+            .into_iter()
+            .map(|(_, entry)| parse_problem(entry))
+            .collect()
+    }
+    /// The source of every Lua script in the document.
+    pub fn lua_scripts(&self) -> Result<Vec<String>> {
+        Ok(self
+            .problems()?
+            .into_iter()
+            .flat_map(|problem| problem.scripts)
+            .collect())
+    }
+    /// Every image embedded in the document, as `(name, contents)`. Images
+    /// are recognized by their contents rather than their names.
+    pub fn images(&self) -> Result<Vec<(&str, Vec<u8>)>> {
+        let mut images = vec![];
+        for entry in self.entries.iter().filter(|entry| !entry.is_encrypted()) {
+            let contents = entry.contents()?;
+            if is_image(&contents) {
+                images.push((entry.name(), contents));
+            }
+        }
+        Ok(images)
+    }
+}
+
+/// Creates a document containing a single Lua script, which can be uploaded
+/// with [`Handle::write_file`][crate::Handle::write_file].
+///
+/// The layout follows documents made by third-party tools rather than TI's
+/// software. Only reading the result back with [`Document`] is tested: it
+/// hasn't been checked that calculators or TI's software open it.
+///
+/// ```
+/// # use libnspire::tns::{Builder, Document};
+/// let tns = Builder::new("function on.paint(gc) gc:drawString(\"Hi\", 10, 10) end")
+///     .build()
+///     .unwrap();
+/// let doc = Document::from_bytes(&tns).unwrap();
+/// assert_eq!(doc.lua_scripts().unwrap().len(), 1);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Builder {
+    script: String,
+    resources: Vec<(String, Vec<u8>)>,
+}
+
+impl Builder {
+    /// Start a document with the given Lua source.
+    pub fn new(script: impl Into<String>) -> Self {
+        Builder {
+            script: script.into(),
+            resources: vec![],
+        }
+    }
+    /// Give the script some data, such as an image in TI's format for
+    /// `image.new`.
+    ///
+    /// The calculator only loads resources that TI's editor embeds in a
+    /// format of its own, so resources are put in the script instead: it
+    /// starts with a global table, `resources`, mapping each name to its
+    /// data as a string. This is on the script's first line, so line numbers
+    /// in error messages still match.
+    pub fn resource(mut self, name: impl Into<String>, data: impl Into<Vec<u8>>) -> Self {
+        self.resources.push((name.into(), data.into()));
+        self
+    }
+    /// Create the document.
+    pub fn build(&self) -> Result<Vec<u8>> {
+        let problem = format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\" ?>\
+             <prob xmlns=\"urn:TI.Problem\" ver=\"1.0\" pbname=\"\"><sym></sym>\
+             <card clay=\"0\" h1=\"10000\" h2=\"10000\" w1=\"10000\" w2=\"10000\">\
+             <isDummyCard>0</isDummyCard><flag>0</flag>\
+             <wdgt xmlns:sc=\"urn:TI.ScriptApp\" type=\"TI.ScriptApp\" ver=\"1.0\">\
+             <sc:mFlags>1024</sc:mFlags><sc:value>-10</sc:value>\
+             <sc:script version=\"33882629\" id=\"0\">{}</sc:script></wdgt></card></prob>",
+            escape(&self.source())
+        );
+        let mut zip = ZipWriter::default();
+        zip.add("Document.xml", DOCUMENT_XML.as_bytes())?;
+        zip.add("Problem1.xml", problem.as_bytes())?;
+        let mut out = Vec::from(MAGIC);
+        out.extend_from_slice(BUILDER_VERSION.as_bytes());
+        out.extend(zip.finish()?);
+        Ok(out)
+    }
+    /// The script, with the resources table in front of it if there are
+    /// any.
+    fn source(&self) -> String {
+        if self.resources.is_empty() {
+            return self.script.clone();
+        }
+        let mut source = "resources = {".to_string();
+        for (name, data) in &self.resources {
+            source.push('[');
+            source.push_str(&lua_string(name.as_bytes()));
+            source.push_str("] = ");
+            source.push_str(&lua_string(data));
+            source.push_str(", ");
+        }
+        source.push_str("} ");
+        source.push_str(&self.script);
+        source
+    }
+}
+
+/// The document settings written by [`Builder`].
+const DOCUMENT_XML: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\" ?>\
+    <doc xmlns=\"urn:TI.Document\" ver=\"1.0\"><fver>1</fver><fmt>0</fmt>\
+    <settings><stg><ang>0</ang><fmt>1</fmt><dd>10</dd><expf>0</expf><vecf>0</vecf>\
+    <rf>0</rf><base>0</base><unit>0</unit><rmode>0</rmode><cmp>0</cmp></stg></settings>\
+    </doc>";
+
+fn parse_problem(entry: &Entry) -> Result<Problem> {
+    let mut problem = Problem {
+        entry: entry.name.clone(),
+        encrypted: true,
+        pages: vec![],
+        scripts: vec![],
+    };
+    if entry.is_encrypted() {
+        return Ok(problem);
+    }
+    let xml = entry.contents()?;
+    let xml = match std::str::from_utf8(&xml) {
+        // Some tools obfuscate the XML without using a different zip method
+        Ok(xml) if xml.trim_start().starts_with('<') => xml,
+        _ => return Ok(problem),
+    };
+    problem.encrypted = false;
+    for card in elements(xml, "card") {
+        problem.pages.push(Page {
+            apps: start_tags(card, "wdgt")
+                .filter_map(|(tag, _)| attribute(tag, "type"))
+                .map(String::from)
+                .collect(),
+        });
+    }
+    problem.scripts = elements(xml, "sc:script").map(unescape).collect();
+    Ok(problem)
+}
+
+fn is_image(data: &[u8]) -> bool {
+    data.starts_with(b"\x89PNG\r\n\x1a\n")
+        || data.starts_with(b"\xff\xd8\xff")
+        || data.starts_with(b"GIF8")
+        || data.starts_with(b"BM")
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Result<u16> {
+    data.get(offset..offset + 2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .ok_or(Error::InvalidTns)
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Result<u32> {
+    data.get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or(Error::InvalidTns)
+}
+
+fn slice(data: &[u8], offset: usize, len: usize) -> Result<&[u8]> {
+    offset
+        .checked_add(len)
+        .and_then(|end| data.get(offset..end))
+        .ok_or(Error::InvalidTns)
+}
+
+/// Read the entries listed in the central directory. Returns `None` if there
+/// is no central directory.
+fn read_central_dir(data: &[u8]) -> Result<Option<Vec<Entry>>> {
+    let eocd = match data
+        .len()
+        .checked_sub(END_OF_CENTRAL_DIR_LEN)
+        .and_then(|last| {
+            (0..=last)
+                .rev()
+                .find(|&i| u32_at(data, i).ok() == Some(END_OF_CENTRAL_DIR_SIG))
+        }) {
+        Some(eocd) => eocd,
+        None => return Ok(None),
+    };
+    let count = u16_at(data, eocd + 10)?;
+    let dir_size = u32_at(data, eocd + 12)? as usize;
+    let dir_offset = u32_at(data, eocd + 16)? as usize;
+    // Offsets are relative to the start of the zip archive, not the file
+    let base = eocd
+        .checked_sub(dir_size)
+        .and_then(|start| start.checked_sub(dir_offset))
+        .ok_or(Error::InvalidTns)?;
+
+    let mut entries = vec![];
+    let mut pos = base + dir_offset;
+    for _ in 0..count {
+        if u32_at(data, pos)? != CENTRAL_HEADER_SIG {
+            return Err(Error::InvalidTns);
+        }
+        let method = u16_at(data, pos + 10)?;
+        let crc = u32_at(data, pos + 16)?;
+        let compressed = u32_at(data, pos + 20)? as usize;
+        let size = u32_at(data, pos + 24)?;
+        let name_len = u16_at(data, pos + 28)? as usize;
+        let extra_len = u16_at(data, pos + 30)? as usize;
+        let comment_len = u16_at(data, pos + 32)? as usize;
+        let local = base + u32_at(data, pos + 42)? as usize;
+        let name = slice(data, pos + CENTRAL_HEADER_LEN, name_len)?;
+
+        if u32_at(data, local)? != LOCAL_HEADER_SIG {
+            return Err(Error::InvalidTns);
+        }
+        let local_name_len = u16_at(data, local + 26)? as usize;
+        let local_extra_len = u16_at(data, local + 28)? as usize;
+        let start = local + LOCAL_HEADER_LEN + local_name_len + local_extra_len;
+        entries.push(Entry {
+            name: String::from_utf8_lossy(name).into_owned(),
+            method,
+            crc,
+            size,
+            data: slice(data, start, compressed)?.to_vec(),
+        });
+        pos += CENTRAL_HEADER_LEN + name_len + extra_len + comment_len;
+    }
+    Ok(Some(entries))
+}
+
+/// Read entries by walking the local file headers, for archives without a
+/// central directory.
+fn read_local_headers(data: &[u8]) -> Result<Vec<Entry>> {
+    let mut entries = vec![];
+    let mut pos = 0;
+    while u32_at(data, pos).ok() == Some(LOCAL_HEADER_SIG) {
+        let method = u16_at(data, pos + 8)?;
+        let crc = u32_at(data, pos + 14)?;
+        let compressed = u32_at(data, pos + 18)? as usize;
+        let size = u32_at(data, pos + 22)?;
+        let name_len = u16_at(data, pos + 26)? as usize;
+        let extra_len = u16_at(data, pos + 28)? as usize;
+        let name = slice(data, pos + LOCAL_HEADER_LEN, name_len)?;
+        let start = pos + LOCAL_HEADER_LEN + name_len + extra_len;
+        entries.push(Entry {
+            name: String::from_utf8_lossy(name).into_owned(),
+            method,
+            crc,
+            size,
+            data: slice(data, start, compressed)?.to_vec(),
+        });
+        pos = start + compressed;
+    }
+    if entries.is_empty() {
+        return Err(Error::InvalidTns);
+    }
+    Ok(entries)
+}
+
+/// Builds a zip archive in memory, compressing every entry.
+#[derive(Default)]
+struct ZipWriter {
+    data: Vec<u8>,
+    central_dir: Vec<u8>,
+    count: u16,
+}
+
+impl ZipWriter {
+    fn add(&mut self, name: &str, contents: &[u8]) -> Result<()> {
+        let mut crc = Crc::new();
+        crc.update(contents);
+        let mut encoder = DeflateEncoder::new(vec![], Compression::default());
+        encoder.write_all(contents).map_err(|_| Error::Io)?;
+        let compressed = encoder.finish().map_err(|_| Error::Io)?;
+        let size = zip_u32(contents.len())?;
+        let compressed_size = zip_u32(compressed.len())?;
+        let name_len = u16::try_from(name.len()).map_err(|_| Error::Invalid)?;
+        let offset = zip_u32(self.data.len())?;
+        // Fields shared by the local and central directory headers: version
+        // needed, flags, method, time, date, CRC, sizes, name length and
+        // extra field length
+        let mut common = vec![];
+        common.extend_from_slice(&ZIP_VERSION.to_le_bytes());
+        common.extend_from_slice(&0u16.to_le_bytes());
+        common.extend_from_slice(&METHOD_DEFLATE.to_le_bytes());
+        common.extend_from_slice(&0u16.to_le_bytes());
+        common.extend_from_slice(&0x21u16.to_le_bytes());
+        common.extend_from_slice(&crc.sum().to_le_bytes());
+        common.extend_from_slice(&compressed_size.to_le_bytes());
+        common.extend_from_slice(&size.to_le_bytes());
+        common.extend_from_slice(&name_len.to_le_bytes());
+        common.extend_from_slice(&0u16.to_le_bytes());
+
+        self.data.extend_from_slice(&LOCAL_HEADER_SIG.to_le_bytes());
+        self.data.extend_from_slice(&common);
+        self.data.extend_from_slice(name.as_bytes());
+        self.data.extend_from_slice(&compressed);
+
+        self.central_dir
+            .extend_from_slice(&CENTRAL_HEADER_SIG.to_le_bytes());
+        self.central_dir
+            .extend_from_slice(&ZIP_VERSION.to_le_bytes());
+        self.central_dir.extend_from_slice(&common);
+        // Comment length, disk number, internal and external attributes
+        self.central_dir.extend_from_slice(&[0; 10]);
+        self.central_dir.extend_from_slice(&offset.to_le_bytes());
+        self.central_dir.extend_from_slice(name.as_bytes());
+        self.count = self.count.checked_add(1).ok_or(Error::Invalid)?;
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<Vec<u8>> {
+        let offset = zip_u32(self.data.len())?;
+        let size = zip_u32(self.central_dir.len())?;
+        self.data.extend_from_slice(&self.central_dir);
+        self.data
+            .extend_from_slice(&END_OF_CENTRAL_DIR_SIG.to_le_bytes());
+        // Disk numbers
+        self.data.extend_from_slice(&[0; 4]);
+        self.data.extend_from_slice(&self.count.to_le_bytes());
+        self.data.extend_from_slice(&self.count.to_le_bytes());
+        self.data.extend_from_slice(&size.to_le_bytes());
+        self.data.extend_from_slice(&offset.to_le_bytes());
+        // Comment length
+        self.data.extend_from_slice(&[0; 2]);
+        Ok(self.data)
+    }
+}
+
+/// Zip archives without the zip64 extension are limited to 4 GiB.
+fn zip_u32(n: usize) -> Result<u32> {
+    u32::try_from(n).map_err(|_| Error::Invalid)
+}
+
+/// Every start tag named `name` in `xml`, without the surrounding `<` and
+/// `>`, along with the offset just past its end.
+fn start_tags<'a>(xml: &'a str, name: &'a str) -> impl Iterator<Item = (&'a str, usize)> + 'a {
+    xml.match_indices('<').filter_map(move |(i, _)| {
+        let tag = &xml[i + 1..];
+        let rest = tag.strip_prefix(name)?;
+        if !rest.starts_with(|c: char| c.is_whitespace() || c == '>' || c == '/') {
+            return None;
+        }
+        let len = tag.find('>')?;
+        Some((&tag[..len], i + len + 2))
+    })
+}
+
+/// The contents of every element named `name` in `xml`. Nested elements of
+/// the same name aren't supported, which is fine for the elements we look
+/// for.
+fn elements<'a>(xml: &'a str, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+    let close = format!("</{}>", name);
+    start_tags(xml, name)
+        .filter(|(tag, _)| !tag.ends_with('/'))
+        .filter_map(move |(_, start)| {
+            let len = xml[start..].find(&close)?;
+            Some(&xml[start..start + len])
+        })
+}
+
+/// The value of the attribute `name` in a start tag.
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let mut rest = tag;
+    while let Some(i) = rest.find(name) {
+        let before = rest[..i].chars().last();
+        rest = &rest[i + name.len()..];
+        if !before.is_some_and(char::is_whitespace) {
+            continue;
+        }
+        let value = rest.trim_start().strip_prefix('=')?.trim_start();
+        let quote = value.chars().next().filter(|&c| c == '"' || c == '\'')?;
+        let value = &value[1..];
+        return Some(&value[..value.find(quote)?]);
+    }
+    None
+}
+
+/// Decode XML entities and CDATA sections.
+fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(i) = rest.find(['&', '<']) {
+        out.push_str(&rest[..i]);
+        rest = &rest[i..];
+        if let Some(cdata) = rest.strip_prefix("<![CDATA[") {
+            let end = cdata.find("]]>").unwrap_or(cdata.len());
+            out.push_str(&cdata[..end]);
+            rest = cdata.get(end + 3..).unwrap_or("");
+            continue;
+        }
+        let end = match rest.find(';') {
+            Some(end) if rest.starts_with('&') => end,
+            _ => {
+                out.push_str(&rest[..1]);
+                rest = &rest[1..];
+                continue;
+            }
+        };
+        let decoded = match &rest[1..end] {
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "amp" => Some('&'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            entity => entity
+                .strip_prefix("#x")
+                .map(|hex| u32::from_str_radix(hex, 16))
+                .or_else(|| entity.strip_prefix('#').map(str::parse))
+                .and_then(|n| n.ok())
+                .and_then(char::from_u32),
+        };
+        match decoded {
+            Some(c) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// A Lua string literal holding `data`. Anything that isn't printable ASCII
+/// is written as a three-digit decimal escape, so the literal survives
+/// being put in XML.
+fn lua_string(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len() + 2);
+    out.push('"');
+    for &b in data {
+        match b {
+            b'"' | b'\\' => {
+                out.push('\\');
+                out.push(b as char);
+            }
+            0x20..=0x7E => out.push(b as char),
+            _ => out.push_str(&format!("\\{:03}", b)),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Encode text for use in XML.
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '&' => out.push_str("&amp;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROBLEM: &str = "<?xml version=\"1.0\" ?><prob><card><wdgt type=\"TI.ScriptApp\">\
+        <sc:script id=\"0\">print(1 &lt; 2)</sc:script></wdgt></card></prob>";
+
+    /// A stored zip entry with just a local header, using `method`.
+    fn local_entry(name: &str, method: u16, data: &[u8]) -> Vec<u8> {
+        let mut crc = Crc::new();
+        crc.update(data);
+        let mut out = LOCAL_HEADER_SIG.to_le_bytes().to_vec();
+        out.extend_from_slice(&ZIP_VERSION.to_le_bytes());
+        out.extend_from_slice(&0u16.to_le_bytes());
+        out.extend_from_slice(&method.to_le_bytes());
+        out.extend_from_slice(&[0; 4]);
+        out.extend_from_slice(&crc.sum().to_le_bytes());
+        out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        out.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        out.extend_from_slice(&0u16.to_le_bytes());
+        out.extend_from_slice(name.as_bytes());
+        out.extend_from_slice(data);
+        out
+    }
+
+    fn document(header: &[u8], method: u16) -> Vec<u8> {
+        [
+            header,
+            &local_entry("Problem1.xml", method, PROBLEM.as_bytes()),
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn header_version() {
+        let doc = Document::from_bytes(&document(b"*TIMLP0500", METHOD_STORED)).unwrap();
+        assert_eq!(doc.version(), "0500");
+        let doc = Document::from_bytes(&document(b"*TIMLP0702\0\0", METHOD_STORED)).unwrap();
+        assert_eq!(doc.version(), "0702");
+        let doc = Document::from_bytes(&document(b"*TIMLP", METHOD_STORED)).unwrap();
+        assert_eq!(doc.version(), "");
+    }
+
+    #[test]
+    fn zip_signature_inside_header() {
+        assert!(matches!(
+            Document::from_bytes(b"*TIMLPK\x03\x04"),
+            Err(Error::InvalidTns)
+        ));
+        assert!(matches!(
+            Document::from_bytes(b"*TIMLP"),
+            Err(Error::InvalidTns)
+        ));
+        assert!(matches!(
+            Document::from_bytes(b"PK\x03\x04"),
+            Err(Error::InvalidTns)
+        ));
+    }
+
+    #[test]
+    fn truncated() {
+        let data = document(b"*TIMLP0500", METHOD_STORED);
+        for len in 0..data.len() {
+            assert!(
+                Document::from_bytes(&data[..len]).is_err(),
+                "parsed {} of {} bytes",
+                len,
+                data.len()
+            );
+        }
+        let doc = Document::from_bytes(&data).unwrap();
+        assert_eq!(doc.entries().len(), 1);
+        assert_eq!(doc.entries()[0].name(), "Problem1.xml");
+    }
+
+    #[test]
+    fn plain_problem() {
+        let doc = Document::from_bytes(&document(b"*TIMLP0500", METHOD_STORED)).unwrap();
+        assert!(!doc.is_encrypted());
+        let problems = doc.problems().unwrap();
+        assert_eq!(problems.len(), 1);
+        assert!(!problems[0].encrypted);
+        assert_eq!(problems[0].pages[0].apps, ["TI.ScriptApp"]);
+        assert_eq!(doc.lua_scripts().unwrap(), ["print(1 < 2)"]);
+    }
+
+    #[test]
+    fn builder_round_trip() {
+        let script = "if a < b && c then print(\"]]> 'x'\") end\n\t-- done";
+        let tns = Builder::new(script).build().unwrap();
+        let doc = Document::from_bytes(&tns).unwrap();
+        assert_eq!(doc.version(), BUILDER_VERSION);
+        let names: Vec<&str> = doc.entries().iter().map(Entry::name).collect();
+        assert_eq!(names, ["Document.xml", "Problem1.xml"]);
+        assert!(!doc.is_encrypted());
+        assert_eq!(doc.lua_scripts().unwrap(), [script]);
+        assert_eq!(doc.problems().unwrap()[0].pages[0].apps, ["TI.ScriptApp"]);
+        let settings = doc.entry("Document.xml").unwrap().contents().unwrap();
+        assert_eq!(settings, DOCUMENT_XML.as_bytes());
+    }
+
+    #[test]
+    fn builder_resources() {
+        let data: Vec<u8> = (0..=255).collect();
+        let tns = Builder::new("print(1)\nprint(2)")
+            .resource("logo", &b"a\"b\\c"[..])
+            .resource("data", data.clone())
+            .build()
+            .unwrap();
+        let doc = Document::from_bytes(&tns).unwrap();
+        assert_eq!(doc.entries().len(), 2);
+        let script = &doc.lua_scripts().unwrap()[0];
+        let (table, rest) = script.split_once("} ").unwrap();
+        // The script keeps its line numbers
+        assert!(!table.contains('\n'));
+        assert_eq!(rest, "print(1)\nprint(2)");
+        assert_eq!(
+            table,
+            format!(
+                "resources = {{[\"logo\"] = \"a\\\"b\\\\c\", [\"data\"] = {}, ",
+                lua_string(&data)
+            )
+        );
+    }
+
+    #[test]
+    fn lua_strings() {
+        assert_eq!(lua_string(b""), "\"\"");
+        assert_eq!(lua_string(b"hi there~"), "\"hi there~\"");
+        assert_eq!(lua_string(b"\"\\"), "\"\\\"\\\\\"");
+        // Always three digits, so a digit after an escape isn't swallowed
+        assert_eq!(lua_string(b"\x001\n\xff"), "\"\\0001\\010\\255\"");
+    }
+
+    #[test]
+    fn images() {
+        let png = b"\x89PNG\r\n\x1a\nnot really";
+        let data = [
+            &b"*TIMLP0500"[..],
+            &local_entry("Problem1.xml", METHOD_STORED, PROBLEM.as_bytes()),
+            &local_entry("image.png", METHOD_STORED, png),
+            &local_entry("data.bin", METHOD_STORED, b"BZ"),
+        ]
+        .concat();
+        let doc = Document::from_bytes(&data).unwrap();
+        assert_eq!(doc.images().unwrap(), [("image.png", png.to_vec())]);
+    }
+
+    #[test]
+    fn builder_escapes_script() {
+        let tns = Builder::new("<b> & ]]> \"").build().unwrap();
+        let doc = Document::from_bytes(&tns).unwrap();
+        let xml = doc.entry("Problem1.xml").unwrap().contents().unwrap();
+        let xml = String::from_utf8(xml).unwrap();
+        let script = elements(&xml, "sc:script").next().unwrap();
+        assert_eq!(script, "&lt;b&gt; &amp; ]]&gt; &quot;");
+        assert_eq!(unescape(script), "<b> & ]]> \"");
+    }
+
+    #[test]
+    fn oversized_entry() {
+        let mut encoder = DeflateEncoder::new(vec![], Compression::default());
+        encoder.write_all(PROBLEM.as_bytes()).unwrap();
+        let mut crc = Crc::new();
+        crc.update(PROBLEM.as_bytes());
+        let mut entry = Entry {
+            name: "Problem1.xml".to_string(),
+            method: METHOD_DEFLATE,
+            crc: crc.sum(),
+            size: PROBLEM.len() as u32,
+            data: encoder.finish().unwrap(),
+        };
+        assert_eq!(entry.contents().unwrap(), PROBLEM.as_bytes());
+        // Claims to be 4 GiB, which mustn't be allocated up front
+        entry.size = u32::MAX;
+        assert!(matches!(entry.contents(), Err(Error::InvalidTns)));
+        entry.size = PROBLEM.len() as u32 + 1;
+        assert!(matches!(entry.contents(), Err(Error::InvalidTns)));
+    }
+
+    #[test]
+    fn encrypted_problem() {
+        // TI's software stores encrypted entries with a method of its own
+        let doc = Document::from_bytes(&document(b"*TIMLP0500", 0x0D)).unwrap();
+        let entry = doc.entry("Problem1.xml").unwrap();
+        assert!(entry.is_encrypted());
+        assert!(matches!(entry.contents(), Err(Error::EncryptedTns)));
+        assert!(doc.is_encrypted());
+        let problems = doc.problems().unwrap();
+        assert!(problems[0].encrypted);
+        assert!(problems[0].pages.is_empty() && problems[0].scripts.is_empty());
+        assert!(doc.lua_scripts().unwrap().is_empty());
+    }
+}
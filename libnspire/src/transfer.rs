@@ -0,0 +1,61 @@
+//! Measuring how fast files move to and from a calculator.
+//!
+//! [`Handle::upload`][crate::Handle::upload] and
+//! [`Handle::download`][crate::Handle::download] work like `write_file` and
+//! `read_file`, but also report how long they took. Each chunk of a file
+//! waits for the calculator to acknowledge it before the next is sent, so
+//! that wait, rather than the size of the packets, limits throughput.
+//! [`HandleBuilder::big_packets`][crate::HandleBuilder::big_packets] sends an
+//! upload in fewer chunks on calculators that accept bigger packets; how much
+//! faster that makes it on a real calculator hasn't been measured.
+//!
+//! ```
+//! use libnspire::{sim::Simulator, Handle};
+//! # fn main() -> libnspire::Result<()> {
+//! let handle = Handle::<rusb::GlobalContext>::from_transport(Simulator::new())?;
+//! let data = vec![0; 10_000];
+//! let upload = handle.upload("/documents/big.tns", &data, &mut |_| {})?;
+//! assert_eq!(upload.bytes, 10_000);
+//! println!("{}", upload);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fmt;
+use std::time::Duration;
+
+/// A finished file transfer.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Transfer {
+    /// The number of bytes of the file transferred.
+    pub bytes: usize,
+    /// How long the transfer took, from opening the file to closing it.
+    pub elapsed: Duration,
+    /// The packet size used. See
+    /// [`Handle::packet_size`][crate::Handle::packet_size].
+    pub packet_size: usize,
+}
+
+impl Transfer {
+    /// The average speed, in bytes per second.
+    pub fn bytes_per_sec(&self) -> f64 {
+        self.bytes as f64 / self.elapsed.as_secs_f64()
+    }
+
+    /// The average speed, in kilobytes (1000 bytes) per second.
+    pub fn kb_per_sec(&self) -> f64 {
+        self.bytes_per_sec() / 1000.0
+    }
+}
+
+impl fmt::Display for Transfer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} bytes in {:.2?} ({:.1} KB/s)",
+            self.bytes,
+            self.elapsed,
+            self.kb_per_sec()
+        )
+    }
+}
@@ -0,0 +1,101 @@
+//! Talking to a calculator over something other than a local USB device.
+//!
+//! [`Handle::new`][crate::Handle::new] drives a USB device directly. Anything
+//! else that can move raw packets to and from a calculator implements
+//! [`Transport`] and is passed to
+//! [`Handle::from_transport`][crate::Handle::from_transport] instead.
+
+use std::time::Duration;
+
+use rusb::{DeviceHandle, Direction, UsbContext};
+
+use crate::{Error, Result, PID_CX2};
+
+/// A way of exchanging raw packets with a calculator.
+///
+/// Each call corresponds to one USB bulk transfer: `write` is given a whole
+/// packet, and `read` returns at most one packet.
+pub trait Transport: Send {
+    /// Read a packet from the calculator into `buf`, returning its length.
+    /// Returns [`Error::Timeout`][crate::Error::Timeout] if nothing arrives
+    /// within `timeout`.
+    fn read(&mut self, buf: &mut [u8], timeout: Duration) -> Result<usize>;
+    /// Send a packet to the calculator, returning the number of bytes sent.
+    fn write(&mut self, buf: &[u8], timeout: Duration) -> Result<usize>;
+    /// Whether the calculator on the other end is a CX II, which uses a
+    /// different protocol. Defaults to `false`.
+    fn is_cx_ii(&self) -> bool {
+        false
+    }
+}
+
+impl<T: Transport + ?Sized> Transport for Box<T> {
+    fn read(&mut self, buf: &mut [u8], timeout: Duration) -> Result<usize> {
+        (**self).read(buf, timeout)
+    }
+    fn write(&mut self, buf: &[u8], timeout: Duration) -> Result<usize> {
+        (**self).write(buf, timeout)
+    }
+    fn is_cx_ii(&self) -> bool {
+        (**self).is_cx_ii()
+    }
+}
+
+/// A calculator attached over USB, driven from Rust rather than by libnspire.
+///
+/// [`Handle::new`][crate::Handle::new] is simpler for local calculators;
+/// this is for relaying raw packets elsewhere, such as over a
+/// [bridge][crate::bridge].
+pub struct UsbTransport<T: UsbContext> {
+    device: DeviceHandle<T>,
+    ep_in: u8,
+    ep_out: u8,
+    is_cx_ii: bool,
+}
+
+impl<T: UsbContext> UsbTransport<T> {
+    /// Reset the calculator and claim its interface. Like a newly plugged in
+    /// calculator, it will then be ready for a new
+    /// [`Handle`][crate::Handle].
+    pub fn new(mut device: DeviceHandle<T>) -> Result<Self> {
+        device.set_active_configuration(1)?;
+        device.reset()?;
+        device.claim_interface(0)?;
+
+        // The endpoints are different in recovery mode, so look them up
+        let config = device.device().active_config_descriptor()?;
+        let iface = config
+            .interfaces()
+            .next()
+            .and_then(|iface| iface.descriptors().next())
+            .ok_or(Error::NoDevice)?;
+        let find = |direction| {
+            iface
+                .endpoint_descriptors()
+                .find(|ep| ep.direction() == direction)
+                .map(|ep| ep.address())
+                .ok_or(Error::NoDevice)
+        };
+        let ep_in = find(Direction::In)?;
+        let ep_out = find(Direction::Out)?;
+        let is_cx_ii = device.device().device_descriptor()?.product_id() == PID_CX2;
+        Ok(UsbTransport {
+            device,
+            ep_in,
+            ep_out,
+            is_cx_ii,
+        })
+    }
+}
+
+impl<T: UsbContext> Transport for UsbTransport<T> {
+    fn read(&mut self, buf: &mut [u8], timeout: Duration) -> Result<usize> {
+        Ok(self.device.read_bulk(self.ep_in, buf, timeout)?)
+    }
+    fn write(&mut self, buf: &[u8], timeout: Duration) -> Result<usize> {
+        Ok(self.device.write_bulk(self.ep_out, buf, timeout)?)
+    }
+    fn is_cx_ii(&self) -> bool {
+        self.is_cx_ii
+    }
+}
@@ -0,0 +1,75 @@
+//! Sending bigger packets to calculators other than the CX II, with
+//! `HandleBuilder::big_packets`.
+
+use libnspire::capture::Direction;
+use libnspire::replay::Recording;
+use libnspire::sim::Simulator;
+use libnspire::{Handle, HandleBuilder};
+
+type UsbHandle = Handle<rusb::GlobalContext>;
+
+fn contents() -> Vec<u8> {
+    (0..20_000).map(|i| i as u8).collect()
+}
+
+/// The number of packets sent to upload a file.
+fn packets_sent(big_packets: bool) -> usize {
+    let sim = Simulator::new();
+    sim.set_big_packets(big_packets);
+    let recording = Recording::default();
+    let handle: UsbHandle = HandleBuilder::new()
+        .big_packets(big_packets)
+        .open_transport(recording.record(sim.clone()))
+        .unwrap();
+    let sent = || {
+        let capture = recording.capture();
+        let frames = capture.frames().iter();
+        frames
+            .filter(|frame| frame.direction == Direction::Sent)
+            .count()
+    };
+
+    let before = sent();
+    handle
+        .upload("/documents/data.tns", &contents(), &mut |_| {})
+        .unwrap();
+    assert_eq!(sim.file("/documents/data.tns"), Some(contents()));
+    sent() - before
+}
+
+#[test]
+fn fewer_packets() {
+    let small = packets_sent(false);
+    let big = packets_sent(true);
+    // 253 or 1439 bytes of the file per packet, plus a few to set up
+    assert!(small > 20_000 / 253, "{}", small);
+    assert!(big < small / 4, "{} vs {}", big, small);
+}
+
+#[test]
+fn usable_after_refusal() {
+    let sim = Simulator::new();
+    let recording = Recording::default();
+    let handle: UsbHandle = HandleBuilder::new()
+        .big_packets(true)
+        .open_transport(recording.record(sim.clone()))
+        .unwrap();
+    assert_eq!(handle.packet_size(), 254);
+    // A NavNet packet's source service is at bytes 4 and 5
+    let refused = recording.capture().frames().iter().any(|frame| {
+        frame.direction == Direction::Received && frame.data.get(4..6) == Some(&[0x00, 0xD3])
+    });
+    assert!(refused, "the probe wasn't refused");
+
+    handle.ping(&[1; 254]).unwrap();
+    handle
+        .write_file("/documents/data.tns", &contents(), &mut |_| {})
+        .unwrap();
+    assert_eq!(sim.file("/documents/data.tns"), Some(contents()));
+    let mut buf = vec![0; contents().len()];
+    let len = handle
+        .read_file("/documents/data.tns", &mut buf, &mut |_| {})
+        .unwrap();
+    assert_eq!(buf[..len], contents()[..]);
+    assert_eq!(handle.list_dir("/documents").unwrap().iter().count(), 1);
+}
@@ -0,0 +1,101 @@
+//! Calls on one handle from several threads at once, which take turns and
+//! must each still get the right answer.
+
+use libnspire::sim::Simulator;
+use libnspire::{Error, Handle};
+
+type UsbHandle = Handle<rusb::GlobalContext>;
+
+const THREADS: usize = 4;
+const ROUNDS: usize = 5;
+
+#[test]
+fn shared_handle() {
+    let handle = UsbHandle::from_transport(Simulator::new()).unwrap();
+    handle.create_dir("/stress").unwrap();
+
+    std::thread::scope(|scope| {
+        for thread in 0..THREADS {
+            let handle = &handle;
+            scope.spawn(move || {
+                let path = format!("/stress/thread{}.tns", thread);
+                let file_name = format!("thread{}.tns", thread);
+                for round in 0..ROUNDS {
+                    let data = format!("thread {} round {}", thread, round).into_bytes();
+                    handle.write_file(&path, &data, &mut |_| {}).unwrap();
+                    let mut buf = vec![0; data.len()];
+                    let len = handle.read_file(&path, &mut buf, &mut |_| {}).unwrap();
+                    assert_eq!(&buf[..len], &data[..], "{} read back wrong", path);
+                    assert_eq!(handle.info().unwrap().name, "Simulator");
+                    handle.ping(&data).unwrap();
+                    let list = handle.list_dir("/stress").unwrap();
+                    assert!(list
+                        .iter()
+                        .any(|item| item.name().to_str() == Ok(&file_name)));
+                }
+                handle.delete_file(&path).unwrap();
+            });
+        }
+    });
+
+    assert!(handle.list_dir("/stress").unwrap().iter().next().is_none());
+    handle.delete_dir("/stress").unwrap();
+}
+
+#[test]
+fn file_sessions() {
+    let sim = Simulator::new();
+    let handle = UsbHandle::from_transport(sim.clone()).unwrap();
+
+    // Sessions take turns as a whole, with other calls waiting for them
+    std::thread::scope(|scope| {
+        for thread in 0..THREADS {
+            let handle = &handle;
+            scope.spawn(move || {
+                for round in 0..ROUNDS {
+                    let dir = format!("/t{}r{}", thread, round);
+                    handle
+                        .file_session(|s| {
+                            s.create_dir(&dir)?;
+                            s.write_file(&format!("{}/a.tns", dir), b"a", &mut |_| {})?;
+                            s.move_file(&format!("{}/a.tns", dir), &format!("{}/b.tns", dir))
+                        })
+                        .unwrap();
+                    assert_eq!(handle.list_dir(&dir).unwrap().iter().count(), 1);
+                }
+            });
+        }
+    });
+
+    for thread in 0..THREADS {
+        for round in 0..ROUNDS {
+            let path = format!("/t{}r{}/b.tns", thread, round);
+            assert_eq!(sim.file(&path).as_deref(), Some(&b"a"[..]));
+        }
+    }
+}
+
+#[test]
+fn reentrant_calls() {
+    let handle = UsbHandle::from_transport(Simulator::new()).unwrap();
+
+    let session = handle.file_session(|_| Ok(handle.info()));
+    assert!(matches!(session, Ok(Err(Error::Busy))), "{:?}", session);
+
+    let mut pings = vec![];
+    let data = vec![0; 1000];
+    handle
+        .write_file("/a.tns", &data, &mut |_| pings.push(handle.ping(b"")))
+        .unwrap();
+    assert!(!pings.is_empty());
+    assert!(pings.iter().all(|ping| matches!(ping, Err(Error::Busy))));
+
+    // Once the calls return, the handle is free again
+    handle.ping(b"").unwrap();
+    assert_eq!(
+        handle
+            .read_file("/a.tns", &mut vec![0; 1000], &mut |_| {})
+            .unwrap(),
+        1000
+    );
+}
@@ -0,0 +1,126 @@
+//! Deploying an OS to simulated calculators.
+
+use libnspire::deploy::{Deployment, Outcome, Report};
+use libnspire::info::Version;
+use libnspire::os_image::OsImage;
+use libnspire::sim::Simulator;
+use libnspire::{Error, Handle, Result};
+
+type UsbHandle = Handle<rusb::GlobalContext>;
+
+/// The version the simulator starts out running.
+const CURRENT: &str = "4.5.0.1180";
+
+fn version(version: &str) -> Version {
+    version.parse().unwrap()
+}
+
+fn image(version: &str) -> OsImage {
+    let header = format!("TI-Nspire.tcc {}\n", version);
+    OsImage::from_bytes([header.as_bytes(), &[0; 600]].concat()).unwrap()
+}
+
+/// Deploy `image` to `sim`, finding it again after the install with
+/// `reconnect`.
+fn deploy(
+    deployment: Deployment,
+    sim: &Simulator,
+    reconnect: impl Fn(&Simulator) -> Result<()> + Sync,
+) -> Report {
+    let deployment = deployment.reconnect_with(|_| {
+        reconnect(sim)?;
+        UsbHandle::from_transport(sim.clone())?.info()
+    });
+    let handle = UsbHandle::from_transport(sim.clone()).unwrap();
+    let mut reports = deployment.run(&rusb::GlobalContext::default(), vec![handle], &|_, _| {});
+    assert_eq!(reports.len(), 1);
+    reports.pop().unwrap()
+}
+
+#[test]
+fn installs() {
+    let sim = Simulator::new();
+    let image = image("4.5.1.0");
+    let report = deploy(Deployment::new(&image), &sim, |_| Ok(()));
+    assert!(matches!(report.outcome, Outcome::Installed), "{:?}", report);
+    assert_eq!(report.before.unwrap().version, version(CURRENT));
+    assert_eq!(report.after.unwrap().version, version("4.5.1.0"));
+    assert_eq!(sim.installed_os().as_deref(), Some(image.data()));
+}
+
+#[test]
+fn skips_current_version() {
+    let sim = Simulator::new();
+    let image = image(CURRENT);
+    let report = deploy(Deployment::new(&image), &sim, |_| {
+        panic!("reconnected to a skipped calculator")
+    });
+    assert!(matches!(report.outcome, Outcome::Skipped), "{:?}", report);
+    assert!(report.after.is_none());
+    assert_eq!(sim.installed_os(), None);
+}
+
+#[test]
+fn reinstalls_current_version() {
+    let sim = Simulator::new();
+    let image = image(CURRENT);
+    let report = deploy(Deployment::new(&image).reinstall(true), &sim, |_| Ok(()));
+    assert!(matches!(report.outcome, Outcome::Installed), "{:?}", report);
+    assert!(sim.installed_os().is_some());
+}
+
+#[test]
+fn refuses_downgrade() {
+    let sim = Simulator::new();
+    let image = image("4.4.0.532");
+    let report = deploy(Deployment::new(&image), &sim, |_| {
+        panic!("reconnected after a refused downgrade")
+    });
+    match report.outcome {
+        Outcome::Failed(Error::OsVersionMismatch { expected, found }) => {
+            assert_eq!(expected, version("4.4.0.532"));
+            assert_eq!(found, version(CURRENT));
+        }
+        outcome => panic!("{:?}", outcome),
+    }
+    assert_eq!(sim.installed_os(), None);
+}
+
+#[test]
+fn allows_downgrade() {
+    let sim = Simulator::new();
+    let image = image("4.4.0.532");
+    let deployment = Deployment::new(&image).allow_downgrade(true);
+    let report = deploy(deployment, &sim, |_| Ok(()));
+    assert!(matches!(report.outcome, Outcome::Installed), "{:?}", report);
+}
+
+#[test]
+fn reports_version_mismatch() {
+    let sim = Simulator::new();
+    let image = image("4.5.1.0");
+    // The calculator comes back up running its old OS
+    let report = deploy(Deployment::new(&image), &sim, |sim| {
+        sim.set_version(version(CURRENT));
+        Ok(())
+    });
+    match report.outcome {
+        Outcome::Failed(Error::OsVersionMismatch { expected, found }) => {
+            assert_eq!(expected, version("4.5.1.0"));
+            assert_eq!(found, version(CURRENT));
+        }
+        outcome => panic!("{:?}", outcome),
+    }
+    assert_eq!(report.after.unwrap().version, version(CURRENT));
+}
+
+#[test]
+fn reports_panic() {
+    let sim = Simulator::new();
+    let image = image("4.5.1.0");
+    let report = deploy(Deployment::new(&image), &sim, |_| panic!("lost it"));
+    match report.outcome {
+        Outcome::Failed(Error::Panicked(message)) => assert_eq!(message, "lost it"),
+        outcome => panic!("{:?}", outcome),
+    }
+}
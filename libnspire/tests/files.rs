@@ -0,0 +1,72 @@
+//! The file service, against the simulator.
+
+use libnspire::sim::Simulator;
+use libnspire::{Error, Handle, VERIFY_ATTEMPTS};
+
+type UsbHandle = Handle<rusb::GlobalContext>;
+
+fn contents() -> Vec<u8> {
+    (0..1000).map(|i| i as u8).collect()
+}
+
+#[test]
+fn short_read() {
+    let sim = Simulator::new();
+    sim.add_file("/documents/data.tns", &contents());
+    let handle = UsbHandle::from_transport(sim).unwrap();
+    let mut buf = [0; 300];
+    let len = handle
+        .read_file("/documents/data.tns", &mut buf, &mut |_| {})
+        .unwrap();
+    assert_eq!(len, buf.len());
+    assert_eq!(buf[..], contents()[..buf.len()]);
+}
+
+#[test]
+fn short_read_in_session() {
+    let sim = Simulator::new();
+    sim.add_file("/documents/data.tns", &contents());
+    let handle = UsbHandle::from_transport(sim).unwrap();
+    handle
+        .file_session(|s| {
+            let mut buf = [0; 300];
+            let len = s.read_file("/documents/data.tns", &mut buf, &mut |_| {})?;
+            assert_eq!(buf[..len], contents()[..300]);
+            // The rest of the file mustn't still be on its way
+            let list = s.list_dir("/documents")?;
+            let names: Vec<_> = list.iter().map(|i| i.name().to_string_lossy()).collect();
+            assert_eq!(names, ["data.tns"]);
+            let mut buf = vec![0; 2000];
+            let len = s.read_file("/documents/data.tns", &mut buf, &mut |_| {})?;
+            assert_eq!(buf[..len], contents()[..]);
+            Ok(())
+        })
+        .unwrap();
+}
+
+#[test]
+fn verification_failed() {
+    let sim = Simulator::new();
+    sim.set_corrupt_writes(VERIFY_ATTEMPTS);
+    let handle = UsbHandle::from_transport(sim.clone()).unwrap();
+    match handle.write_file_verified("/documents/data.tns", &contents()) {
+        Err(Error::VerificationFailed {
+            offset,
+            expected,
+            found,
+        }) => assert_eq!((offset, expected, found), (500, 1000, 1000)),
+        res => panic!("{:?}", res),
+    }
+    assert_ne!(sim.file("/documents/data.tns"), Some(contents()));
+}
+
+#[test]
+fn verification_retries() {
+    let sim = Simulator::new();
+    sim.set_corrupt_writes(1);
+    let handle = UsbHandle::from_transport(sim.clone()).unwrap();
+    handle
+        .write_file_verified("/documents/data.tns", &contents())
+        .unwrap();
+    assert_eq!(sim.file("/documents/data.tns"), Some(contents()));
+}
@@ -0,0 +1,40 @@
+//! Telling the calculator the host's name, against the simulator.
+
+use libnspire::sim::Simulator;
+use libnspire::{Error, HandleBuilder};
+
+#[test]
+fn client_name() {
+    let sim = Simulator::new();
+    let handle = HandleBuilder::new()
+        .client_name("Lab PC 3")
+        .open_transport::<rusb::GlobalContext>(sim.clone())
+        .unwrap();
+    assert_eq!(sim.client_name().as_deref(), Some("Lab PC 3"));
+    // The connection is still usable after the calculator's reply
+    assert_eq!(handle.info().unwrap().name, "Simulator");
+}
+
+#[test]
+fn no_client_name() {
+    let sim = Simulator::new();
+    HandleBuilder::new()
+        .open_transport::<rusb::GlobalContext>(sim.clone())
+        .unwrap();
+    assert_eq!(sim.client_name(), None);
+}
+
+#[test]
+fn long_client_name() {
+    let sim = Simulator::new();
+    let name = "x".repeat(52);
+    let builder = HandleBuilder::new().client_name(name.as_str());
+    builder
+        .open_transport::<rusb::GlobalContext>(sim.clone())
+        .unwrap();
+    assert_eq!(sim.client_name(), Some(name.clone()));
+
+    let builder = HandleBuilder::new().client_name(name + "x");
+    let result = builder.open_transport::<rusb::GlobalContext>(sim.clone());
+    assert!(matches!(result, Err(Error::Invalid)));
+}
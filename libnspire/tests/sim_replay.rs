@@ -0,0 +1,157 @@
+//! Regression tests replaying sessions recorded against the simulator, which
+//! fail if the library says anything on the wire that it didn't when they
+//! were recorded.
+//!
+//! These only catch changes in the library's own behavior: the recordings in
+//! `tests/data/sim` show what the library sends and how the simulator
+//! answers, not how a real calculator does. Traces of real calculators
+//! still need to be captured for that. To record these again after an
+//! intended change in behavior, run
+//! `cargo test --test sim_replay -- --ignored`.
+
+use std::fs::File;
+use std::path::PathBuf;
+
+use libnspire::capture::{Capture, LinkType};
+use libnspire::os_image::OsProgress;
+use libnspire::replay::{Recording, ReplayTransport};
+use libnspire::sim::Simulator;
+use libnspire::{Handle, Image, Result};
+
+type UsbHandle = Handle<rusb::GlobalContext>;
+
+/// A session to record and replay: the calculator it was recorded with, and
+/// what the host does, which checks what it gets back.
+struct Session {
+    name: &'static str,
+    setup: fn(&Simulator),
+    run: fn(&UsbHandle) -> Result<()>,
+}
+
+const LIST_DIR: Session = Session {
+    name: "list_dir",
+    setup: |sim| {
+        sim.add_file("/documents/hello.tns", b"hello");
+        sim.add_dir("/documents/notes");
+    },
+    run: |handle| {
+        let list = handle.list_dir("/documents")?;
+        let mut names: Vec<_> = list.iter().map(|i| i.name().to_string_lossy()).collect();
+        names.sort();
+        assert_eq!(names, ["hello.tns", "notes"]);
+        Ok(())
+    },
+};
+
+/// Enough for several packets.
+fn contents() -> Vec<u8> {
+    (0..600).map(|i| i as u8).collect()
+}
+
+const READ_FILE: Session = Session {
+    name: "read_file",
+    setup: |sim| sim.add_file("/documents/data.tns", &contents()),
+    run: |handle| {
+        let mut buf = vec![0; 1000];
+        let len = handle.read_file("/documents/data.tns", &mut buf, &mut |_| {})?;
+        assert_eq!(buf[..len], contents()[..]);
+        Ok(())
+    },
+};
+
+fn screen() -> Image {
+    Image {
+        width: 4,
+        height: 2,
+        bpp: 16,
+        data: (0..16).collect(),
+    }
+}
+
+const SCREENSHOT: Session = Session {
+    name: "screenshot",
+    setup: |sim| sim.set_screen(screen()),
+    run: |handle| {
+        let image = handle.screenshot()?;
+        let expected = screen();
+        assert_eq!(
+            (image.width, image.height),
+            (expected.width, expected.height)
+        );
+        assert_eq!(image.data, expected.data);
+        Ok(())
+    },
+};
+
+fn os_image() -> Vec<u8> {
+    [&b"TI-Nspire.tcc 4.5.1.0\n"[..], &contents()].concat()
+}
+
+const SEND_OS: Session = Session {
+    name: "send_os",
+    setup: |_| {},
+    run: |handle| {
+        let mut installed = 0;
+        handle.send_os(&os_image(), &mut |progress| {
+            if let OsProgress::Installing(percent) = progress {
+                installed = percent;
+            }
+        })?;
+        assert_eq!(installed, 100);
+        Ok(())
+    },
+};
+
+const SESSIONS: &[Session] = &[LIST_DIR, READ_FILE, SCREENSHOT, SEND_OS];
+
+fn path(session: &Session) -> PathBuf {
+    let name = format!("tests/data/sim/{}.pcapng", session.name);
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(name)
+}
+
+fn replay(session: &Session) {
+    let file = File::open(path(session)).unwrap();
+    let replay = ReplayTransport::new(&Capture::read_pcapng(file).unwrap());
+    let handle = UsbHandle::from_transport(replay.clone()).unwrap();
+    (session.run)(&handle).unwrap();
+    drop(handle);
+    replay.finish().unwrap();
+}
+
+#[test]
+fn list_dir() {
+    replay(&LIST_DIR);
+}
+
+#[test]
+fn read_file() {
+    replay(&READ_FILE);
+}
+
+#[test]
+fn screenshot() {
+    replay(&SCREENSHOT);
+}
+
+#[test]
+fn send_os() {
+    replay(&SEND_OS);
+}
+
+#[test]
+#[ignore = "rewrites the recordings"]
+fn record() {
+    for session in SESSIONS {
+        let sim = Simulator::new();
+        (session.setup)(&sim);
+        let recording = Recording::default();
+        let handle = UsbHandle::from_transport(recording.record(sim)).unwrap();
+        (session.run)(&handle).unwrap();
+        drop(handle);
+        let file = File::create(path(session)).unwrap();
+        recording
+            .capture()
+            .write_pcapng(file, LinkType::User)
+            .unwrap();
+    }
+}
@@ -0,0 +1,741 @@
+//! `nspire-fuse`: mount a calculator's filesystem as a local directory.
+//!
+//! The calculator's file service can only read and write whole files, so
+//! files are read into memory when first accessed and written back in one
+//! piece when they're closed or synced. Editors that save in many small
+//! writes therefore only cause a single transfer.
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::ffi::OsStr;
+use std::os::raw::c_int;
+use std::path::PathBuf;
+use std::process;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use clap::Parser;
+use fuser::{
+    FileAttr, FileType, Filesystem, MountOption, ReplyAttr, ReplyCreate, ReplyData, ReplyDirectory,
+    ReplyEmpty, ReplyEntry, ReplyOpen, ReplyWrite, Request, TimeOrNow,
+};
+use libnspire::dir::{DirItem, EntryType};
+use libnspire::{Error, Handle, Result, PID, PID_CX2, VID};
+use rusb::{Context, UsbContext};
+
+/// How long the kernel may cache attributes and lookups.
+const TTL: Duration = Duration::from_secs(1);
+const ROOT_INO: u64 = 1;
+const BLOCK_SIZE: u32 = 512;
+/// The largest file that can be written, which is more than any calculator
+/// can store. Files are buffered in memory, so this stops a write at a huge
+/// offset from allocating without bound.
+const MAX_FILE_SIZE: u64 = 64 << 20;
+
+#[derive(Parser)]
+#[command(name = "nspire-fuse", version, about = "Mount a TI Nspire calculator")]
+struct Args {
+    /// Where to mount the calculator
+    mountpoint: PathBuf,
+    /// The calculator to use, as `bus:address` from `nspire devices`.
+    /// Defaults to the first one found
+    #[arg(long, short, value_parser = parse_device)]
+    device: Option<(u8, u8)>,
+}
+
+/// A file opened by the kernel, along with its write-back buffer.
+struct OpenFile {
+    path: String,
+    /// The contents of the file, read on first access.
+    data: Option<Vec<u8>>,
+    /// Whether `data` has changes that haven't been sent to the calculator.
+    dirty: bool,
+    /// Whether the file was deleted or replaced while open, so that closing
+    /// it doesn't bring it back.
+    unlinked: bool,
+}
+
+/// The inode numbers given to the kernel and the files it has open. This
+/// doesn't talk to the calculator.
+struct State {
+    paths: HashMap<u64, String>,
+    inodes: HashMap<String, u64>,
+    next_ino: u64,
+    files: HashMap<u64, OpenFile>,
+    next_fh: u64,
+}
+
+struct NspireFs {
+    handle: Handle<Context>,
+    uid: u32,
+    gid: u32,
+    state: State,
+}
+
+fn main() {
+    let args = Args::parse();
+    let handle = match open(args.device) {
+        Ok(handle) => handle,
+        Err(e) => {
+            eprintln!("nspire-fuse: {}", e);
+            process::exit(1);
+        }
+    };
+    let fs = NspireFs::new(handle);
+    let options = [
+        MountOption::FSName("nspire".to_string()),
+        MountOption::NoExec,
+        MountOption::NoAtime,
+    ];
+    if let Err(e) = fuser::mount2(fs, &args.mountpoint, &options) {
+        eprintln!("nspire-fuse: {}", e);
+        process::exit(1);
+    }
+}
+
+fn open(wanted: Option<(u8, u8)>) -> Result<Handle<Context>> {
+    let context = Context::new()?;
+    for device in context.devices()?.iter() {
+        let desc = device.device_descriptor()?;
+        if desc.vendor_id() == VID
+            && matches!(desc.product_id(), PID | PID_CX2)
+            && wanted.is_none_or(|wanted| wanted == (device.bus_number(), device.address()))
+        {
+            return Handle::new(device.open()?);
+        }
+    }
+    Err(Error::NoDevice)
+}
+
+fn parse_device(selector: &str) -> std::result::Result<(u8, u8), String> {
+    let (bus, address) = selector.split_once(':').ok_or("expected `bus:address`")?;
+    let parse = |n: &str| n.parse::<u8>().map_err(|e| e.to_string());
+    Ok((parse(bus)?, parse(address)?))
+}
+
+fn errno(err: &Error) -> c_int {
+    match err {
+        Error::DoesNotExist => libc::ENOENT,
+        Error::Exists => libc::EEXIST,
+        Error::Access => libc::EACCES,
+        Error::Timeout => libc::ETIMEDOUT,
+        Error::Busy => libc::EBUSY,
+        Error::NotSupported => libc::ENOTSUP,
+        Error::OutOfMemory => libc::ENOSPC,
+        Error::Invalid => libc::EINVAL,
+        Error::NoDevice => libc::ENODEV,
+        _ => libc::EIO,
+    }
+}
+
+/// Unwrap a `Result`, or reply with the matching `errno` and return.
+macro_rules! tri {
+    ($reply:expr, $e:expr) => {
+        match $e {
+            Ok(value) => value,
+            Err(e) => {
+                $reply.error(errno(&e));
+                return;
+            }
+        }
+    };
+}
+
+impl State {
+    fn new() -> Self {
+        let mut state = State {
+            paths: HashMap::new(),
+            inodes: HashMap::new(),
+            next_ino: ROOT_INO,
+            files: HashMap::new(),
+            next_fh: 1,
+        };
+        state.ino("/".to_string());
+        state
+    }
+
+    /// The inode number for a path, allocating one if needed.
+    fn ino(&mut self, path: String) -> u64 {
+        if let Some(&ino) = self.inodes.get(&path) {
+            return ino;
+        }
+        let ino = self.next_ino;
+        self.next_ino += 1;
+        self.paths.insert(ino, path.clone());
+        self.inodes.insert(path, ino);
+        ino
+    }
+
+    fn path(&self, ino: u64) -> Result<String> {
+        self.paths.get(&ino).cloned().ok_or(Error::DoesNotExist)
+    }
+
+    fn child(&self, parent: u64, name: &OsStr) -> Result<String> {
+        let parent = self.path(parent)?;
+        let name = name.to_str().ok_or(Error::Invalid)?;
+        Ok(match parent.as_str() {
+            "/" => format!("/{}", name),
+            parent => format!("{}/{}", parent, name),
+        })
+    }
+
+    /// Move the inode numbers of `from` and everything below it to `to`,
+    /// along with any open files.
+    fn rename_inodes(&mut self, from: &str, to: &str) {
+        let prefix = format!("{}/", from);
+        let moved: Vec<(String, u64)> = self
+            .inodes
+            .iter()
+            .filter(|(path, _)| path.as_str() == from || path.starts_with(&prefix))
+            .map(|(path, &ino)| (path.clone(), ino))
+            .collect();
+        for (old, ino) in moved {
+            let new = format!("{}{}", to, &old[from.len()..]);
+            self.inodes.remove(&old);
+            self.inodes.insert(new.clone(), ino);
+            self.paths.insert(ino, new);
+        }
+        for file in self.files.values_mut() {
+            if file.path == from || file.path.starts_with(&prefix) {
+                file.path = format!("{}{}", to, &file.path[from.len()..]);
+            }
+        }
+    }
+
+    fn forget_path(&mut self, path: &str) {
+        if let Some(ino) = self.inodes.remove(path) {
+            self.paths.remove(&ino);
+        }
+    }
+
+    /// Forget a file that was deleted, keeping any open copies readable but
+    /// no longer written back.
+    fn forget_file(&mut self, path: &str) {
+        self.forget_path(path);
+        for file in self.files.values_mut() {
+            if file.path == path {
+                file.unlinked = true;
+            }
+        }
+    }
+
+    /// An open file with this path that has its contents loaded, if any.
+    /// Its contents are newer than what's on the calculator.
+    fn buffered(&self, path: &str) -> Option<&Vec<u8>> {
+        self.files
+            .values()
+            .filter(|file| file.path == path)
+            .find_map(|file| file.data.as_ref())
+    }
+
+    /// Open a file. If `data` is given, it replaces the file's contents when
+    /// written back.
+    fn open_file(&mut self, path: String, data: Option<Vec<u8>>) -> u64 {
+        let fh = self.next_fh;
+        self.next_fh += 1;
+        let dirty = data.is_some();
+        self.files.insert(
+            fh,
+            OpenFile {
+                path,
+                data,
+                dirty,
+                unlinked: false,
+            },
+        );
+        fh
+    }
+
+    /// The open file handle `fh` if there is one, otherwise any open copy of
+    /// `path`.
+    fn opened(&self, fh: Option<u64>, path: &str) -> Option<u64> {
+        fh.filter(|fh| self.files.contains_key(fh)).or_else(|| {
+            self.files
+                .iter()
+                .find(|(_, file)| file.path == path)
+                .map(|(&fh, _)| fh)
+        })
+    }
+
+    /// Where an open file should be written back to, and what with, if it
+    /// has changes that should be.
+    fn pending(&self, fh: u64) -> Result<Option<(&str, &[u8])>> {
+        let file = self.files.get(&fh).ok_or(Error::Invalid)?;
+        Ok(match (file.unlinked, file.dirty, &file.data) {
+            (false, true, Some(data)) => Some((&file.path, data)),
+            _ => None,
+        })
+    }
+}
+
+impl NspireFs {
+    fn new(handle: Handle<Context>) -> Self {
+        NspireFs {
+            handle,
+            // SAFETY: these never fail
+            uid: unsafe { libc::getuid() },
+            gid: unsafe { libc::getgid() },
+            state: State::new(),
+        }
+    }
+
+    /// Move `from` to `to`, replacing `to` if it's a file. The old file is
+    /// moved aside first and only deleted once the move has succeeded, so a
+    /// failure leaves both files where they were.
+    fn replace(&mut self, from: &str, to: &str) -> Result<()> {
+        let replaced = match self.handle.file_attr(to) {
+            Ok(item) => item.entry_type() == EntryType::File,
+            Err(Error::DoesNotExist) => false,
+            Err(e) => return Err(e),
+        };
+        if !replaced {
+            return self.handle.move_file(from, to);
+        }
+        let aside = (1..)
+            .map(|n| format!("{}.{}.old", to, n))
+            .find(|path| matches!(self.handle.file_attr(path), Err(Error::DoesNotExist)))
+            .unwrap();
+        self.handle.move_file(to, &aside)?;
+        if let Err(e) = self.handle.move_file(from, to) {
+            if let Err(restore) = self.handle.move_file(&aside, to) {
+                eprintln!("nspire-fuse: couldn't restore {}: {}", to, restore);
+            }
+            return Err(e);
+        }
+        if let Err(e) = self.handle.delete_file(&aside) {
+            eprintln!("nspire-fuse: couldn't delete {}: {}", aside, e);
+        }
+        self.state.forget_file(to);
+        Ok(())
+    }
+
+    fn attr(&mut self, path: &str) -> Result<FileAttr> {
+        if path == "/" {
+            return Ok(self.make_attr(ROOT_INO, FileType::Directory, 0, UNIX_EPOCH));
+        }
+        if let Some(data) = self.state.buffered(path) {
+            let size = data.len() as u64;
+            let ino = self.state.ino(path.to_string());
+            return Ok(self.make_attr(ino, FileType::RegularFile, size, SystemTime::now()));
+        }
+        let item = self.handle.file_attr(path)?;
+        let ino = self.state.ino(path.to_string());
+        Ok(self.item_attr(ino, &item))
+    }
+
+    fn item_attr(&self, ino: u64, item: &DirItem) -> FileAttr {
+        let (kind, size) = match item.entry_type() {
+            EntryType::Directory => (FileType::Directory, 0),
+            EntryType::File => (FileType::RegularFile, item.size()),
+        };
+        let mtime = UNIX_EPOCH + Duration::from_secs(item.date());
+        self.make_attr(ino, kind, size, mtime)
+    }
+
+    fn make_attr(&self, ino: u64, kind: FileType, size: u64, mtime: SystemTime) -> FileAttr {
+        let (perm, nlink) = match kind {
+            FileType::Directory => (0o755, 2),
+            _ => (0o644, 1),
+        };
+        FileAttr {
+            ino,
+            size,
+            blocks: size.div_ceil(u64::from(BLOCK_SIZE)),
+            atime: mtime,
+            mtime,
+            ctime: mtime,
+            crtime: mtime,
+            kind,
+            perm,
+            nlink,
+            uid: self.uid,
+            gid: self.gid,
+            rdev: 0,
+            blksize: BLOCK_SIZE,
+            flags: 0,
+        }
+    }
+
+    /// The contents of an open file, read from the calculator if needed.
+    fn load(&mut self, fh: u64) -> Result<&mut Vec<u8>> {
+        let file = self.state.files.get_mut(&fh).ok_or(Error::Invalid)?;
+        if file.data.is_none() {
+            let size = self.handle.file_attr(&file.path)?.size() as usize;
+            let mut buf = vec![0; size];
+            let len = self.handle.read_file(&file.path, &mut buf, &mut |_| {})?;
+            buf.truncate(len);
+            file.data = Some(buf);
+        }
+        Ok(file.data.as_mut().unwrap())
+    }
+
+    /// Send an open file's contents to the calculator if they've changed.
+    fn write_back(&mut self, fh: u64) -> Result<()> {
+        if let Some((path, data)) = self.state.pending(fh)? {
+            self.handle.write_file(path, data, &mut |_| {})?;
+            self.state.files.get_mut(&fh).unwrap().dirty = false;
+        }
+        Ok(())
+    }
+
+    /// Resize a file that isn't open, by rewriting it.
+    fn truncate_closed(&mut self, path: &str, size: u64) -> Result<()> {
+        let fh = self.state.open_file(path.to_string(), None);
+        let result = self.load(fh).map(|data| data.resize(size as usize, 0));
+        if let Some(file) = self.state.files.get_mut(&fh) {
+            file.dirty = true;
+        }
+        let result = result.and_then(|_| self.write_back(fh));
+        self.state.files.remove(&fh);
+        result
+    }
+}
+
+impl Filesystem for NspireFs {
+    fn lookup(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        let path = tri!(reply, self.state.child(parent, name));
+        let attr = tri!(reply, self.attr(&path));
+        reply.entry(&TTL, &attr, 0);
+    }
+
+    fn getattr(&mut self, _req: &Request<'_>, ino: u64, _fh: Option<u64>, reply: ReplyAttr) {
+        let path = tri!(reply, self.state.path(ino));
+        let attr = tri!(reply, self.attr(&path));
+        reply.attr(&TTL, &attr);
+    }
+
+    fn setattr(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        _mode: Option<u32>,
+        _uid: Option<u32>,
+        _gid: Option<u32>,
+        size: Option<u64>,
+        _atime: Option<TimeOrNow>,
+        _mtime: Option<TimeOrNow>,
+        _ctime: Option<SystemTime>,
+        fh: Option<u64>,
+        _crtime: Option<SystemTime>,
+        _chgtime: Option<SystemTime>,
+        _bkuptime: Option<SystemTime>,
+        _flags: Option<u32>,
+        reply: ReplyAttr,
+    ) {
+        let path = tri!(reply, self.state.path(ino));
+        // Only the size can be changed; other attributes are silently kept
+        // so that tools like `cp -p` don't fail.
+        if let Some(size) = size {
+            if size > MAX_FILE_SIZE {
+                reply.error(libc::EFBIG);
+                return;
+            }
+            match self.state.opened(fh, &path) {
+                Some(fh) => {
+                    tri!(reply, self.load(fh)).resize(size as usize, 0);
+                    self.state.files.get_mut(&fh).unwrap().dirty = true;
+                }
+                None => tri!(reply, self.truncate_closed(&path, size)),
+            }
+        }
+        let attr = tri!(reply, self.attr(&path));
+        reply.attr(&TTL, &attr);
+    }
+
+    fn mkdir(
+        &mut self,
+        _req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        _mode: u32,
+        _umask: u32,
+        reply: ReplyEntry,
+    ) {
+        let path = tri!(reply, self.state.child(parent, name));
+        tri!(reply, self.handle.create_dir(&path));
+        let attr = tri!(reply, self.attr(&path));
+        reply.entry(&TTL, &attr, 0);
+    }
+
+    fn unlink(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        let path = tri!(reply, self.state.child(parent, name));
+        tri!(reply, self.handle.delete_file(&path));
+        self.state.forget_file(&path);
+        reply.ok();
+    }
+
+    fn rmdir(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        let path = tri!(reply, self.state.child(parent, name));
+        tri!(reply, self.handle.delete_dir(&path));
+        self.state.forget_path(&path);
+        reply.ok();
+    }
+
+    fn rename(
+        &mut self,
+        _req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        newparent: u64,
+        newname: &OsStr,
+        flags: u32,
+        reply: ReplyEmpty,
+    ) {
+        if flags != 0 {
+            reply.error(libc::EINVAL);
+            return;
+        }
+        let from = tri!(reply, self.state.child(parent, name));
+        let to = tri!(reply, self.state.child(newparent, newname));
+        // Editors save by writing a temporary file and renaming it over the
+        // original, which the calculator refuses to do.
+        tri!(reply, self.replace(&from, &to));
+        self.state.rename_inodes(&from, &to);
+        reply.ok();
+    }
+
+    fn open(&mut self, _req: &Request<'_>, ino: u64, flags: i32, reply: ReplyOpen) {
+        let path = tri!(reply, self.state.path(ino));
+        let data = if flags & libc::O_TRUNC != 0 {
+            Some(vec![])
+        } else {
+            None
+        };
+        let fh = self.state.open_file(path, data);
+        reply.opened(fh, 0);
+    }
+
+    fn create(
+        &mut self,
+        _req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        _mode: u32,
+        _umask: u32,
+        _flags: i32,
+        reply: ReplyCreate,
+    ) {
+        let path = tri!(reply, self.state.child(parent, name));
+        // The file is created on the calculator when it's first written back
+        let fh = self.state.open_file(path.clone(), Some(vec![]));
+        let attr = tri!(reply, self.attr(&path));
+        reply.created(&TTL, &attr, 0, fh, 0);
+    }
+
+    fn read(
+        &mut self,
+        _req: &Request<'_>,
+        _ino: u64,
+        fh: u64,
+        offset: i64,
+        size: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyData,
+    ) {
+        let data = tri!(reply, self.load(fh));
+        let start = (offset as usize).min(data.len());
+        let end = (start + size as usize).min(data.len());
+        reply.data(&data[start..end]);
+    }
+
+    fn write(
+        &mut self,
+        _req: &Request<'_>,
+        _ino: u64,
+        fh: u64,
+        offset: i64,
+        data: &[u8],
+        _write_flags: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyWrite,
+    ) {
+        let end = u64::try_from(offset)
+            .ok()
+            .and_then(|start| start.checked_add(data.len() as u64))
+            .filter(|&end| end <= MAX_FILE_SIZE);
+        let end = match end {
+            Some(end) => end as usize,
+            None => {
+                reply.error(libc::EFBIG);
+                return;
+            }
+        };
+        let buf = tri!(reply, self.load(fh));
+        let start = end - data.len();
+        if buf.len() < end {
+            buf.resize(end, 0);
+        }
+        buf[start..end].copy_from_slice(data);
+        self.state.files.get_mut(&fh).unwrap().dirty = true;
+        reply.written(data.len() as u32);
+    }
+
+    fn flush(&mut self, _req: &Request<'_>, _ino: u64, fh: u64, _lock: u64, reply: ReplyEmpty) {
+        tri!(reply, self.write_back(fh));
+        reply.ok();
+    }
+
+    fn fsync(&mut self, _req: &Request<'_>, _ino: u64, fh: u64, _data: bool, reply: ReplyEmpty) {
+        tri!(reply, self.write_back(fh));
+        reply.ok();
+    }
+
+    fn release(
+        &mut self,
+        _req: &Request<'_>,
+        _ino: u64,
+        fh: u64,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        _flush: bool,
+        reply: ReplyEmpty,
+    ) {
+        let result = self.write_back(fh);
+        self.state.files.remove(&fh);
+        tri!(reply, result);
+        reply.ok();
+    }
+
+    fn readdir(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        mut reply: ReplyDirectory,
+    ) {
+        let path = tri!(reply, self.state.path(ino));
+        let list = tri!(reply, self.handle.list_dir(&path));
+        let parent = match path.rfind('/') {
+            Some(0) | None => ROOT_INO,
+            Some(i) => self.state.ino(path[..i].to_string()),
+        };
+        let mut entries = vec![
+            (ino, FileType::Directory, ".".to_string()),
+            (parent, FileType::Directory, "..".to_string()),
+        ];
+        for item in list.iter() {
+            let name = item.name().to_string_lossy().into_owned();
+            let kind = match item.entry_type() {
+                EntryType::Directory => FileType::Directory,
+                EntryType::File => FileType::RegularFile,
+            };
+            let child = match path.as_str() {
+                "/" => format!("/{}", name),
+                path => format!("{}/{}", path, name),
+            };
+            entries.push((self.state.ino(child), kind, name));
+        }
+        for (i, (ino, kind, name)) in entries.into_iter().enumerate().skip(offset as usize) {
+            if reply.add(ino, i as i64 + 1, kind, name) {
+                break;
+            }
+        }
+        reply.ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state() -> State {
+        let mut state = State::new();
+        for path in [
+            "/a",
+            "/a/file.tns",
+            "/a/sub",
+            "/a/sub/deep.tns",
+            "/ab",
+            "/b",
+        ] {
+            state.ino(path.to_string());
+        }
+        state
+    }
+
+    #[test]
+    fn inode_numbers() {
+        let mut state = state();
+        assert_eq!(state.ino("/".to_string()), ROOT_INO);
+        let ino = state.ino("/a/file.tns".to_string());
+        assert_eq!(state.ino("/a/file.tns".to_string()), ino);
+        assert_eq!(state.path(ino).unwrap(), "/a/file.tns");
+        assert!(matches!(state.path(1000), Err(Error::DoesNotExist)));
+        let a = state.ino("/a".to_string());
+        assert_eq!(state.child(ROOT_INO, OsStr::new("x")).unwrap(), "/x");
+        assert_eq!(state.child(a, OsStr::new("x")).unwrap(), "/a/x");
+    }
+
+    #[test]
+    fn rename_dir_with_dirty_file() {
+        let mut state = state();
+        let dir = state.ino("/a".to_string());
+        let file = state.ino("/a/file.tns".to_string());
+        let deep = state.ino("/a/sub/deep.tns".to_string());
+        let other = state.ino("/ab".to_string());
+        let fh = state.open_file("/a/file.tns".to_string(), Some(b"new".to_vec()));
+        let clean = state.open_file("/a/sub/deep.tns".to_string(), None);
+
+        state.rename_inodes("/a", "/c");
+        assert_eq!(state.path(dir).unwrap(), "/c");
+        assert_eq!(state.path(file).unwrap(), "/c/file.tns");
+        assert_eq!(state.path(deep).unwrap(), "/c/sub/deep.tns");
+        assert_eq!(state.ino("/c/file.tns".to_string()), file);
+        // Only paths below the directory move, not ones sharing its prefix
+        assert_eq!(state.path(other).unwrap(), "/ab");
+        assert!(!state.inodes.contains_key("/a/file.tns"));
+        // The open file is written back to where it is now
+        let pending = state.pending(fh).unwrap();
+        assert_eq!(pending, Some(("/c/file.tns", &b"new"[..])));
+        assert_eq!(state.buffered("/c/file.tns"), Some(&b"new".to_vec()));
+        assert_eq!(state.buffered("/a/file.tns"), None);
+        assert_eq!(state.pending(clean).unwrap(), None);
+        assert_eq!(state.opened(None, "/c/sub/deep.tns"), Some(clean));
+    }
+
+    #[test]
+    fn rename_file() {
+        let mut state = state();
+        let ino = state.ino("/b".to_string());
+        let fh = state.open_file("/b".to_string(), Some(vec![]));
+        state.rename_inodes("/b", "/a/b");
+        assert_eq!(state.path(ino).unwrap(), "/a/b");
+        assert_eq!(state.pending(fh).unwrap(), Some(("/a/b", &b""[..])));
+    }
+
+    #[test]
+    fn forget_open_file() {
+        let mut state = state();
+        let ino = state.ino("/b".to_string());
+        let fh = state.open_file("/b".to_string(), Some(b"data".to_vec()));
+        state.forget_file("/b");
+        assert!(state.path(ino).is_err());
+        // Still readable, but closing it mustn't bring it back
+        assert_eq!(state.buffered("/b"), Some(&b"data".to_vec()));
+        assert_eq!(state.pending(fh).unwrap(), None);
+        // A new file with the same name gets a new inode
+        assert_ne!(state.ino("/b".to_string()), ino);
+    }
+
+    #[test]
+    fn forget_dir() {
+        let mut state = state();
+        let ino = state.ino("/a/sub".to_string());
+        state.forget_path("/a/sub");
+        assert!(state.path(ino).is_err());
+        assert!(!state.inodes.contains_key("/a/sub"));
+    }
+
+    #[test]
+    fn opened() {
+        let mut state = state();
+        let fh = state.open_file("/b".to_string(), None);
+        assert_eq!(state.opened(Some(fh), "/b"), Some(fh));
+        assert_eq!(state.opened(Some(fh + 1), "/b"), Some(fh));
+        assert_eq!(state.opened(None, "/b"), Some(fh));
+        assert_eq!(state.opened(None, "/ab"), None);
+        assert!(matches!(state.pending(fh + 1), Err(Error::Invalid)));
+    }
+}
@@ -0,0 +1,405 @@
+//! `nspire`: a command-line tool for TI Nspire calculators.
+
+use std::convert::TryFrom;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use clap::{Parser, Subcommand};
+use indicatif::{ProgressBar, ProgressStyle};
+use libnspire::dir::{DirList, EntryType};
+use libnspire::info::{Info, Model};
+use libnspire::os_image::{OsCompat, OsImage, OsProgress};
+use libnspire::{Error, Handle, Result, PID, PID_CX2, VID};
+use rusb::{Context, Device, UsbContext};
+use serde::Serialize;
+
+mod shell;
+
+const EXIT_CODES: &str = "\
+Exit codes:
+  0  success
+  1  other error
+  2  invalid arguments
+  3  no calculator found
+  4  file or directory does not exist
+  5  file or directory already exists
+  6  permission denied
+  7  timeout
+  8  calculator busy
+  9  OS image rejected or installation failed";
+
+#[derive(Parser)]
+#[command(name = "nspire", version, about, after_help = EXIT_CODES)]
+struct Cli {
+    /// Print machine-readable JSON instead of human-readable text
+    #[arg(long, global = true)]
+    json: bool,
+    /// The calculator to use, as `bus:address` from `nspire devices`.
+    /// Defaults to the first one found
+    #[arg(long, short, global = true, value_parser = parse_device)]
+    device: Option<(u8, u8)>,
+    /// Log what's happening to stderr. Repeat to include every packet
+    #[arg(long, short, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List connected calculators
+    Devices,
+    /// Show information about the calculator
+    Info,
+    /// Check that the calculator responds, and how quickly
+    Ping,
+    /// List the contents of a directory
+    Ls {
+        #[arg(default_value = "/")]
+        path: String,
+        /// Show sizes and modification dates
+        #[arg(short)]
+        long: bool,
+    },
+    /// Download a file from the calculator
+    Get {
+        remote: String,
+        /// Defaults to the name of the remote file
+        local: Option<PathBuf>,
+    },
+    /// Upload a file to the calculator
+    Put {
+        local: PathBuf,
+        /// Defaults to the name of the local file in /documents
+        remote: Option<String>,
+    },
+    /// Move or rename a file or directory
+    Mv { src: String, dest: String },
+    /// Copy a file
+    Cp { src: String, dest: String },
+    /// Delete a file
+    Rm { path: String },
+    /// Create a directory
+    Mkdir { path: String },
+    /// Delete an empty directory
+    Rmdir { path: String },
+    /// Save a screenshot as a PNG
+    Screenshot {
+        #[arg(default_value = "screenshot.png")]
+        output: PathBuf,
+    },
+    /// Type text on the calculator's keypad
+    Type { text: String },
+    /// Install an OS upgrade (.tno, .tnc, .tco, .tcc, .tco2, .tcc2 or .tct2)
+    OsInstall {
+        image: PathBuf,
+        /// Install even if the calculator already runs this version or a
+        /// newer one
+        #[arg(long)]
+        force: bool,
+    },
+    /// Start an interactive shell for browsing the calculator's files
+    Shell,
+}
+
+/// A connected calculator, as listed by `nspire devices`.
+#[derive(Serialize)]
+struct DeviceSummary {
+    bus: u8,
+    address: u8,
+    model: &'static str,
+}
+
+fn main() {
+    let cli = Cli::parse();
+    if cli.verbose > 0 {
+        let level = match cli.verbose {
+            1 => tracing_subscriber::filter::LevelFilter::DEBUG,
+            _ => tracing_subscriber::filter::LevelFilter::TRACE,
+        };
+        tracing_subscriber::fmt()
+            .with_max_level(level)
+            .with_writer(std::io::stderr)
+            .init();
+    }
+    if let Err(e) = run(&cli) {
+        eprintln!("nspire: {}", e);
+        process::exit(exit_code(&e));
+    }
+}
+
+fn exit_code(err: &Error) -> i32 {
+    match err {
+        Error::NoDevice | Error::Usb(rusb::Error::NoDevice) => 3,
+        Error::DoesNotExist => 4,
+        Error::Exists => 5,
+        Error::Access | Error::Usb(rusb::Error::Access) => 6,
+        Error::Timeout | Error::Usb(rusb::Error::Timeout) => 7,
+        Error::Busy | Error::Usb(rusb::Error::Busy) => 8,
+        Error::InvalidOsImage
+        | Error::OsMismatch { .. }
+        | Error::OsInstallFailed(_)
+        | Error::OsVersionMismatch { .. } => 9,
+        _ => 1,
+    }
+}
+
+fn run(cli: &Cli) -> Result<()> {
+    let context = Context::new()?;
+    if let Command::Devices = cli.command {
+        let devices: Vec<_> = list_devices(&context)?
+            .into_iter()
+            .map(|(_, summary)| summary)
+            .collect();
+        if cli.json {
+            print_json(&devices);
+        } else if devices.is_empty() {
+            println!("No calculators found");
+        } else {
+            for dev in devices {
+                println!("{:03}:{:03}  {}", dev.bus, dev.address, dev.model);
+            }
+        }
+        return Ok(());
+    }
+
+    let handle = open(&context, cli.device)?;
+    match &cli.command {
+        Command::Devices => unreachable!(),
+        Command::Shell => shell::run(&handle)?,
+        Command::Info => {
+            let info = handle.info()?;
+            if cli.json {
+                print_json(&info);
+            } else {
+                print_info(&info, handle.is_cx_ii()?);
+            }
+        }
+        Command::Ping => println!("Reply in {:?}", handle.ping(b"nspire-cli")?),
+        Command::Ls { path, long } => {
+            let list = handle.list_dir(path)?;
+            if cli.json {
+                print_json(&*list);
+            } else {
+                print_listing(&list, *long);
+            }
+        }
+        Command::Get { remote, local } => {
+            let buf = download(&handle, remote, cli.json)?;
+            let local = match local {
+                Some(local) => local.clone(),
+                None => PathBuf::from(base_name(remote)),
+            };
+            fs::write(local, buf).map_err(|_| Error::Io)?;
+        }
+        Command::Put { local, remote } => {
+            let buf = read_local(local)?;
+            let remote = match remote {
+                Some(remote) => remote.clone(),
+                None => format!("/documents/{}", file_name(local)),
+            };
+            upload(&handle, &remote, &buf, cli.json)?;
+        }
+        Command::Mv { src, dest } => handle.move_file(src, dest)?,
+        Command::Cp { src, dest } => handle.copy_file(src, dest)?,
+        Command::Rm { path } => handle.delete_file(path)?,
+        Command::Mkdir { path } => handle.create_dir(path)?,
+        Command::Rmdir { path } => handle.delete_dir(path)?,
+        Command::Screenshot { output } => {
+            let image = image::DynamicImage::try_from(handle.screenshot()?)?;
+            image.save(output).map_err(|_| Error::Io)?;
+        }
+        Command::Type { text } => handle.type_text(text)?,
+        Command::OsInstall { image, force } => {
+            let image = OsImage::from_bytes(read_local(image)?)?;
+            match handle.check_os_compat(&image)? {
+                OsCompat::Upgrade => {}
+                _ if *force => {}
+                OsCompat::Reinstall | OsCompat::Downgrade => {
+                    return Err(Error::OsVersionMismatch {
+                        expected: image.version(),
+                        found: handle.info()?.version,
+                    });
+                }
+            }
+            let upload = progress_bar(cli.json, image.size() as u64);
+            let install = progress_bar(cli.json, 100);
+            install.set_style(ProgressStyle::with_template("Installing {bar:40} {pos}%").unwrap());
+            handle.send_os(image.data(), &mut |progress| match progress {
+                OsProgress::Uploading { remaining, total } => {
+                    upload.set_position((total - remaining) as u64)
+                }
+                OsProgress::Installing(percent) => {
+                    upload.finish_and_clear();
+                    install.set_position(u64::from(percent.min(100)))
+                }
+            })?;
+            install.finish_and_clear();
+        }
+    }
+    Ok(())
+}
+
+/// Every calculator connected over USB, along with its model name.
+fn list_devices(context: &Context) -> Result<Vec<(Device<Context>, DeviceSummary)>> {
+    let mut devices = vec![];
+    for device in context.devices()?.iter() {
+        let desc = device.device_descriptor()?;
+        if desc.vendor_id() != VID {
+            continue;
+        }
+        let model = match desc.product_id() {
+            PID => "TI-Nspire",
+            PID_CX2 => "TI-Nspire CX II",
+            _ => continue,
+        };
+        let summary = DeviceSummary {
+            bus: device.bus_number(),
+            address: device.address(),
+            model,
+        };
+        devices.push((device, summary));
+    }
+    Ok(devices)
+}
+
+/// Open the calculator selected with `--device`, or the first one found.
+fn open(context: &Context, wanted: Option<(u8, u8)>) -> Result<Handle<Context>> {
+    let (device, _) = list_devices(context)?
+        .into_iter()
+        .find(|(_, dev)| wanted.is_none_or(|wanted| wanted == (dev.bus, dev.address)))
+        .ok_or(Error::NoDevice)?;
+    Handle::new(device.open()?)
+}
+
+fn parse_device(selector: &str) -> std::result::Result<(u8, u8), String> {
+    let (bus, address) = selector.split_once(':').ok_or("expected `bus:address`")?;
+    let parse = |n: &str| n.parse::<u8>().map_err(|e| e.to_string());
+    Ok((parse(bus)?, parse(address)?))
+}
+
+/// Read a whole file from the calculator, showing a progress bar unless
+/// `quiet` is set.
+fn download(handle: &Handle<Context>, remote: &str, quiet: bool) -> Result<Vec<u8>> {
+    let size = handle.file_attr(remote)?.size() as usize;
+    let mut buf = vec![0; size];
+    let bar = progress_bar(quiet, size as u64);
+    let len = handle.read_file(remote, &mut buf, &mut |remaining| {
+        bar.set_position((size - remaining) as u64)
+    })?;
+    bar.finish_and_clear();
+    buf.truncate(len);
+    Ok(buf)
+}
+
+/// Write a whole file to the calculator, showing a progress bar unless
+/// `quiet` is set.
+fn upload(handle: &Handle<Context>, remote: &str, buf: &[u8], quiet: bool) -> Result<()> {
+    let bar = progress_bar(quiet, buf.len() as u64);
+    handle.write_file(remote, buf, &mut |remaining| {
+        bar.set_position((buf.len() - remaining) as u64)
+    })?;
+    bar.finish_and_clear();
+    Ok(())
+}
+
+fn progress_bar(quiet: bool, len: u64) -> ProgressBar {
+    if quiet {
+        return ProgressBar::hidden();
+    }
+    let bar = ProgressBar::new(len);
+    bar.set_style(
+        ProgressStyle::with_template("{bar:40} {bytes}/{total_bytes} ({binary_bytes_per_sec})")
+            .unwrap(),
+    );
+    bar
+}
+
+fn print_info(info: &Info, is_cx_ii: bool) {
+    println!("Name:          {}", info.name);
+    if let Some(model) = Model::detect(info.hw_type, &info.os_extension, is_cx_ii) {
+        println!("Model:         {}", model);
+    }
+    println!("ID:            {}", info.id);
+    println!("Short ID:      {}", info.short_id);
+    println!(
+        "Hardware:      {:?}, revision {:#x}",
+        info.hw_type, info.hw_version
+    );
+    println!("OS:            {}", info.version);
+    println!("Boot1:         {}", info.boot1_version);
+    println!("Boot2:         {}", info.boot2_version);
+    println!("Run level:     {:?}", info.run_level);
+    println!(
+        "Storage:       {} free of {}",
+        bytes(info.free_storage),
+        bytes(info.total_storage)
+    );
+    println!(
+        "RAM:           {} free of {}",
+        bytes(info.free_ram),
+        bytes(info.total_ram)
+    );
+    println!(
+        "Battery:       {:?}{}",
+        info.battery,
+        if info.is_charging { ", charging" } else { "" }
+    );
+    println!(
+        "Screen:        {}x{}, {} bpp",
+        info.lcd.width, info.lcd.height, info.lcd.bpp
+    );
+    println!("Clock speed:   {} MHz", info.clock_speed);
+    println!("OS extension:  .{}", info.os_extension);
+    println!("File extension: .{}", info.file_extension);
+}
+
+fn print_listing(list: &DirList, long: bool) {
+    for item in list.iter() {
+        let name = item.name().to_string_lossy();
+        let suffix = match item.entry_type() {
+            EntryType::Directory => "/",
+            EntryType::File => "",
+        };
+        if long {
+            println!(
+                "{:>10}  {:>10}  {}{}",
+                item.size(),
+                item.date(),
+                name,
+                suffix
+            );
+        } else {
+            println!("{}{}", name, suffix);
+        }
+    }
+}
+
+fn print_json<T: Serialize + ?Sized>(value: &T) {
+    println!("{}", serde_json::to_string_pretty(value).unwrap());
+}
+
+fn read_local(path: &Path) -> Result<Vec<u8>> {
+    fs::read(path).map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => Error::DoesNotExist,
+        std::io::ErrorKind::PermissionDenied => Error::Access,
+        _ => Error::Io,
+    })
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// The last component of a calculator path.
+fn base_name(remote: &str) -> &str {
+    remote.rsplit('/').next().unwrap_or(remote)
+}
+
+/// Format a size for humans.
+fn bytes(n: u64) -> String {
+    indicatif::BinaryBytes(n).to_string()
+}
@@ -0,0 +1,344 @@
+//! `nspire shell`: an interactive prompt that keeps one connection to the
+//! calculator open.
+
+use std::fs;
+use std::path::PathBuf;
+
+use libnspire::dir::EntryType;
+use libnspire::{Error, Handle, Result};
+use rusb::Context;
+use rustyline::completion::{Completer, FilenameCompleter, Pair};
+use rustyline::error::ReadlineError;
+use rustyline::history::DefaultHistory;
+use rustyline::{Editor, Helper, Highlighter, Hinter, Validator};
+
+use crate::{base_name, download, file_name, print_info, print_listing, read_local, upload};
+
+/// Files larger than this are not printed by `cat`.
+const MAX_CAT_SIZE: u64 = 64 * 1024;
+
+const COMMANDS: &[&str] = &[
+    "cat", "cd", "cp", "exit", "get", "help", "info", "ls", "mkdir", "mv", "put", "pwd", "rm",
+    "rmdir",
+];
+
+const HELP: &str = "\
+Commands:
+  cd [dir]                 change the current directory (default /)
+  pwd                      print the current directory
+  ls [-l] [dir]            list a directory
+  cat <file>               print a small text file
+  get <remote> [local]     download a file
+  put <local> [remote]     upload a file
+  mv <src> <dest>          move or rename a file or directory
+  cp <src> <dest>          copy a file
+  rm <file>                delete a file
+  mkdir <dir>              create a directory
+  rmdir <dir>              delete an empty directory
+  info                     show information about the calculator
+  help                     show this message
+  exit                     leave the shell
+Paths are relative to the current directory. Quote names containing spaces.";
+
+/// The shell's state, which doubles as the line editor's helper so that
+/// completion can see the connection and current directory.
+#[derive(Helper, Highlighter, Hinter, Validator)]
+struct Shell<'a> {
+    handle: &'a Handle<Context>,
+    cwd: String,
+    local: FilenameCompleter,
+}
+
+/// Run the shell until the user exits or the input ends.
+pub fn run(handle: &Handle<Context>) -> Result<()> {
+    let mut editor: Editor<Shell, DefaultHistory> = Editor::new().map_err(|_| Error::Io)?;
+    editor.set_helper(Some(Shell {
+        handle,
+        cwd: "/".to_string(),
+        local: FilenameCompleter::new(),
+    }));
+    let history = history_path();
+    if let Some(history) = &history {
+        // There's no history yet the first time the shell runs
+        let _ = editor.load_history(history);
+    }
+
+    loop {
+        let prompt = format!("nspire:{}> ", editor.helper().unwrap().cwd);
+        let line = match editor.readline(&prompt) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(_) => return Err(Error::Io),
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let _ = editor.add_history_entry(line.as_str());
+        let args = match split_words(&line) {
+            Some(args) => args,
+            None => {
+                eprintln!("error: unterminated quote");
+                continue;
+            }
+        };
+        if matches!(args[0].as_str(), "exit" | "quit") {
+            break;
+        }
+        let shell = editor.helper_mut().unwrap();
+        if let Err(e) = shell.execute(&args) {
+            eprintln!("error: {}", e);
+        }
+    }
+
+    if let Some(history) = &history {
+        let _ = editor.save_history(history);
+    }
+    Ok(())
+}
+
+impl Shell<'_> {
+    fn execute(&mut self, args: &[String]) -> Result<()> {
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        match args.as_slice() {
+            ["help"] => println!("{}", HELP),
+            ["pwd"] => println!("{}", self.cwd),
+            ["cd"] => self.cwd = "/".to_string(),
+            ["cd", dir] => {
+                let dir = self.resolve(dir);
+                if dir != "/" && self.handle.file_attr(&dir)?.entry_type() != EntryType::Directory {
+                    return Err(Error::Invalid);
+                }
+                self.cwd = dir;
+            }
+            ["ls"] => print_listing(&self.handle.list_dir(&self.cwd)?, false),
+            ["ls", "-l"] => print_listing(&self.handle.list_dir(&self.cwd)?, true),
+            ["ls", dir] => print_listing(&self.handle.list_dir(&self.resolve(dir))?, false),
+            ["ls", "-l", dir] => print_listing(&self.handle.list_dir(&self.resolve(dir))?, true),
+            ["cat", file] => {
+                let file = self.resolve(file);
+                if self.handle.file_attr(&file)?.size() > MAX_CAT_SIZE {
+                    eprintln!("cat: file is too large, use `get` instead");
+                    return Ok(());
+                }
+                let buf = download(self.handle, &file, true)?;
+                print!("{}", String::from_utf8_lossy(&buf));
+                if !buf.ends_with(b"\n") {
+                    println!();
+                }
+            }
+            ["get", remote] => self.get(remote, PathBuf::from(base_name(remote)))?,
+            ["get", remote, local] => self.get(remote, PathBuf::from(local))?,
+            ["put", local] => {
+                let remote = self.resolve(&file_name(local.as_ref()));
+                upload(self.handle, &remote, &read_local(local.as_ref())?, false)?;
+            }
+            ["put", local, remote] => {
+                let remote = self.resolve(remote);
+                upload(self.handle, &remote, &read_local(local.as_ref())?, false)?;
+            }
+            ["mv", src, dest] => self
+                .handle
+                .move_file(&self.resolve(src), &self.resolve(dest))?,
+            ["cp", src, dest] => self
+                .handle
+                .copy_file(&self.resolve(src), &self.resolve(dest))?,
+            ["rm", file] => self.handle.delete_file(&self.resolve(file))?,
+            ["mkdir", dir] => self.handle.create_dir(&self.resolve(dir))?,
+            ["rmdir", dir] => self.handle.delete_dir(&self.resolve(dir))?,
+            ["info"] => print_info(&self.handle.info()?, self.handle.is_cx_ii()?),
+            [command, ..] if COMMANDS.contains(command) => {
+                eprintln!("{}: wrong number of arguments, see `help`", command)
+            }
+            [command, ..] => eprintln!("{}: unknown command, see `help`", command),
+            [] => {}
+        }
+        Ok(())
+    }
+
+    fn get(&self, remote: &str, local: PathBuf) -> Result<()> {
+        let buf = download(self.handle, &self.resolve(remote), false)?;
+        fs::write(local, buf).map_err(|_| Error::Io)
+    }
+
+    /// Turn a path typed by the user into an absolute calculator path.
+    fn resolve(&self, path: &str) -> String {
+        resolve(&self.cwd, path)
+    }
+}
+
+/// Turn `path` into an absolute path, relative to `cwd` unless it starts with
+/// a `/`.
+fn resolve(cwd: &str, path: &str) -> String {
+    let mut parts: Vec<&str> = if path.starts_with('/') {
+        vec![]
+    } else {
+        cwd.split('/').filter(|part| !part.is_empty()).collect()
+    };
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+    format!("/{}", parts.join("/"))
+}
+
+impl Completer for Shell<'_> {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        ctx: &rustyline::Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let start = line[..pos].rfind(' ').map_or(0, |i| i + 1);
+        let word = &line[start..pos];
+        let mut previous = line[..start].split_whitespace();
+        let command = match previous.next() {
+            Some(command) => command,
+            None => {
+                let commands = COMMANDS
+                    .iter()
+                    .filter(|command| command.starts_with(word))
+                    .map(|command| Pair {
+                        display: command.to_string(),
+                        replacement: format!("{} ", command),
+                    })
+                    .collect();
+                return Ok((start, commands));
+            }
+        };
+        let index = previous.count();
+        // The local side of a transfer is on this computer
+        if (command == "put" && index == 0) || (command == "get" && index == 1) {
+            return self.local.complete(line, pos, ctx);
+        }
+
+        let (dir, prefix) = match word.rfind('/') {
+            Some(i) => (&word[..=i], &word[i + 1..]),
+            None => ("", word),
+        };
+        let list = match self.handle.list_dir(&self.resolve(dir)) {
+            Ok(list) => list,
+            // Completion is best-effort; don't interrupt typing with errors
+            Err(_) => return Ok((start, vec![])),
+        };
+        let mut candidates: Vec<Pair> = list
+            .iter()
+            .map(|item| (item.name().to_string_lossy(), item.entry_type()))
+            .filter(|(name, _)| name.starts_with(prefix))
+            .map(|(name, entry_type)| {
+                let suffix = match entry_type {
+                    EntryType::Directory => "/",
+                    EntryType::File => "",
+                };
+                Pair {
+                    display: format!("{}{}", name, suffix),
+                    replacement: format!("{}{}{}", dir, name, suffix),
+                }
+            })
+            .collect();
+        candidates.sort_by(|a, b| a.display.cmp(&b.display));
+        Ok((start, candidates))
+    }
+}
+
+/// Split a command line into words, honoring double and single quotes.
+/// Returns `None` if a quote is left open.
+fn split_words(line: &str) -> Option<Vec<String>> {
+    let mut words = vec![];
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote = None;
+    for c in line.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => word.push(c),
+            (None, '"') | (None, '\'') => {
+                quote = Some(c);
+                in_word = true;
+            }
+            (None, c) if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            (None, c) => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if quote.is_some() {
+        return None;
+    }
+    if in_word {
+        words.push(word);
+    }
+    Some(words)
+}
+
+fn history_path() -> Option<PathBuf> {
+    let home = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"))?;
+    Some(PathBuf::from(home).join(".nspire_history"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_paths() {
+        let cases = [
+            ("/", "", "/"),
+            ("/", ".", "/"),
+            ("/", "..", "/"),
+            ("/", "documents", "/documents"),
+            ("/documents", "a.tns", "/documents/a.tns"),
+            ("/documents", "./a.tns", "/documents/a.tns"),
+            ("/documents", ".", "/documents"),
+            ("/documents", "..", "/"),
+            ("/documents/notes", "../a.tns", "/documents/a.tns"),
+            ("/documents/notes", "../../..", "/"),
+            ("/documents", "notes/../b/./c/", "/documents/b/c"),
+            ("/documents", "/", "/"),
+            ("/documents", "/other/x.tns", "/other/x.tns"),
+            ("/documents", "/other/../x.tns", "/x.tns"),
+            ("/documents", "//a//b", "/a/b"),
+        ];
+        for &(cwd, path, expected) in &cases {
+            assert_eq!(resolve(cwd, path), expected, "{:?} in {:?}", path, cwd);
+        }
+    }
+
+    #[test]
+    fn split() {
+        let cases: &[(&str, &[&str])] = &[
+            ("", &[]),
+            ("   ", &[]),
+            ("ls", &["ls"]),
+            ("  mv  a   b  ", &["mv", "a", "b"]),
+            ("put \"my file.tns\" dest", &["put", "my file.tns", "dest"]),
+            ("put 'it\"s' x", &["put", "it\"s", "x"]),
+            ("cd \"\"", &["cd", ""]),
+            ("get a\"b c\"d", &["get", "ab cd"]),
+            ("rm \t a", &["rm", "a"]),
+        ];
+        for &(line, expected) in cases {
+            assert_eq!(split_words(line).unwrap(), expected, "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn split_unclosed_quotes() {
+        assert_eq!(split_words("cd \"my documents"), None);
+        assert_eq!(split_words("rm 'a"), None);
+        assert_eq!(split_words("put \"a'"), None);
+    }
+}
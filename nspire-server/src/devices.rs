@@ -0,0 +1,218 @@
+//! Keeping track of the calculators the server can talk to.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use libnspire::os_image::{OsCompat, OsImage};
+use libnspire::sim::Simulator;
+use libnspire::{Error, Handle, Result, PID, PID_CX2, VID};
+use rusb::{Context, UsbContext};
+use serde::Serialize;
+
+use crate::install::Install;
+
+/// A calculator, as listed by `GET /devices`.
+#[derive(Clone, Serialize)]
+pub struct DeviceSummary {
+    /// `bus-address` for USB calculators, `simN` for simulated ones.
+    pub id: String,
+    pub model: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bus: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<u8>,
+}
+
+struct Device {
+    summary: DeviceSummary,
+    handle: Handle<Context>,
+}
+
+/// Every calculator the server has opened. Each one keeps a single
+/// connection that requests take turns using.
+pub struct Devices {
+    context: Option<Context>,
+    devices: Mutex<BTreeMap<String, Arc<Device>>>,
+    /// Held while looking for new calculators, so that two requests don't
+    /// both try to open one. Opening a calculator takes a while, so this is
+    /// kept apart from `devices` to not hold up requests to the others.
+    scanning: Mutex<()>,
+    /// The most recent installation on each calculator. These are kept after
+    /// the calculator disappears so clients can still see how it ended.
+    installs: Mutex<HashMap<String, Arc<Install>>>,
+}
+
+impl Devices {
+    /// `context` is `None` if USB isn't available, in which case only the
+    /// `simulated` calculators are offered.
+    pub fn new(context: Option<Context>, simulated: usize) -> Result<Self> {
+        let mut devices = BTreeMap::new();
+        for i in 0..simulated {
+            let sim = Simulator::new();
+            sim.set_name(&format!("Simulator {}", i));
+            sim.set_id(&format!("{:025}", i));
+            let summary = DeviceSummary {
+                id: format!("sim{}", i),
+                model: "Simulator",
+                bus: None,
+                address: None,
+            };
+            let device = Device {
+                summary,
+                handle: Handle::from_transport(sim)?,
+            };
+            devices.insert(device.summary.id.clone(), Arc::new(device));
+        }
+        Ok(Devices {
+            context,
+            devices: Mutex::new(devices),
+            scanning: Mutex::new(()),
+            installs: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Look for calculators that were plugged in or unplugged, and list all
+    /// of them.
+    pub fn list(&self) -> Vec<DeviceSummary> {
+        self.scan();
+        let devices = self.devices.lock().unwrap();
+        devices.values().map(|dev| dev.summary.clone()).collect()
+    }
+
+    fn scan(&self) {
+        let context = match &self.context {
+            Some(context) => context,
+            None => return,
+        };
+        let found: Vec<_> = match context.devices() {
+            Ok(list) => list
+                .iter()
+                .filter_map(|device| {
+                    let desc = device.device_descriptor().ok()?;
+                    let model = match (desc.vendor_id(), desc.product_id()) {
+                        (VID, PID) => "TI-Nspire",
+                        (VID, PID_CX2) => "TI-Nspire CX II",
+                        _ => return None,
+                    };
+                    Some((device, model))
+                })
+                .collect(),
+            Err(e) => {
+                eprintln!("nspire-server: can't list USB devices: {}", e);
+                return;
+            }
+        };
+
+        let _scanning = self.scanning.lock().unwrap();
+        let new: Vec<_> = {
+            let mut devices = self.devices.lock().unwrap();
+            // Forget calculators that were unplugged
+            devices.retain(|_, dev| match (dev.summary.bus, dev.summary.address) {
+                (Some(bus), Some(address)) => found
+                    .iter()
+                    .any(|(device, _)| (device.bus_number(), device.address()) == (bus, address)),
+                _ => true,
+            });
+            found
+                .into_iter()
+                .map(|(device, model)| {
+                    let id = format!("{}-{}", device.bus_number(), device.address());
+                    (id, device, model)
+                })
+                .filter(|(id, _, _)| !devices.contains_key(id))
+                .collect()
+        };
+        // Open new calculators without holding up requests to the others
+        for (id, device, model) in new {
+            let handle = match device.open().map_err(Error::from).and_then(Handle::new) {
+                Ok(handle) => handle,
+                Err(e) => {
+                    eprintln!("nspire-server: can't open calculator {}: {}", id, e);
+                    continue;
+                }
+            };
+            let summary = DeviceSummary {
+                id: id.clone(),
+                model,
+                bus: Some(device.bus_number()),
+                address: Some(device.address()),
+            };
+            let device = Arc::new(Device { summary, handle });
+            self.devices.lock().unwrap().insert(id, device);
+        }
+    }
+
+    fn get(&self, id: &str) -> Result<Arc<Device>> {
+        if let Some(device) = self.devices.lock().unwrap().get(id) {
+            return Ok(device.clone());
+        }
+        // It may have just been plugged in
+        self.scan();
+        let devices = self.devices.lock().unwrap();
+        devices.get(id).cloned().ok_or(Error::NoDevice)
+    }
+
+    /// Run `f` with the handle of calculator `id`. Fails with
+    /// [`Error::Busy`] while an OS is being installed, since that takes
+    /// minutes.
+    ///
+    /// Requests take turns on the handle one call at a time, so the calls
+    /// made by `f` may be interleaved with other requests'. An installation
+    /// that starts while `f` is running makes it wait until the
+    /// installation is done.
+    pub fn with<R>(&self, id: &str, f: impl FnOnce(&Handle<Context>) -> Result<R>) -> Result<R> {
+        let device = self.get(id)?;
+        if self.installing(id) {
+            return Err(Error::Busy);
+        }
+        f(&device.handle)
+    }
+
+    fn installing(&self, id: &str) -> bool {
+        let installs = self.installs.lock().unwrap();
+        installs
+            .get(id)
+            .is_some_and(|install| !install.is_finished())
+    }
+
+    /// Start installing `image` on calculator `id` in the background. Unless
+    /// `force` is set, the image must be newer than the installed OS.
+    pub fn start_install(&self, id: &str, image: OsImage, force: bool) -> Result<Arc<Install>> {
+        let device = self.get(id)?;
+        self.with(id, |handle| match handle.check_os_compat(&image)? {
+            OsCompat::Upgrade => Ok(()),
+            _ if force => Ok(()),
+            OsCompat::Reinstall | OsCompat::Downgrade => Err(Error::OsVersionMismatch {
+                expected: image.version(),
+                found: handle.info()?.version,
+            }),
+        })?;
+
+        let install = Arc::new(Install::default());
+        {
+            let mut installs = self.installs.lock().unwrap();
+            // Another installation may have started while checking
+            if installs
+                .get(id)
+                .is_some_and(|install| !install.is_finished())
+            {
+                return Err(Error::Busy);
+            }
+            installs.insert(id.to_string(), install.clone());
+        }
+        let progress = install.clone();
+        thread::spawn(move || {
+            let result = device
+                .handle
+                .send_os(image.data(), &mut |p| progress.progress(p));
+            progress.finish(result);
+        });
+        Ok(install)
+    }
+
+    /// The most recent installation on calculator `id`.
+    pub fn install(&self, id: &str) -> Option<Arc<Install>> {
+        self.installs.lock().unwrap().get(id).cloned()
+    }
+}
@@ -0,0 +1,102 @@
+//! OS installations running in the background.
+
+use std::sync::{Condvar, Mutex};
+
+use libnspire::os_image::OsProgress;
+use libnspire::Result;
+use serde::Serialize;
+
+/// Something that happened during an installation, sent to clients as a
+/// server-sent event named after the phase.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "phase", rename_all = "lowercase")]
+pub enum Event {
+    /// The image is being sent to the calculator.
+    Uploading { sent: usize, total: usize },
+    /// The calculator is installing the image.
+    Installing { percent: u8 },
+    /// The installation finished. The calculator reboots afterwards, so it
+    /// will show up under a new ID.
+    Done,
+    /// The installation failed.
+    Error { error: String },
+}
+
+impl Event {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::Uploading { .. } => "uploading",
+            Event::Installing { .. } => "installing",
+            Event::Done => "done",
+            Event::Error { .. } => "error",
+        }
+    }
+}
+
+/// The progress of one installation, shared between the thread doing it and
+/// any number of clients watching it.
+#[derive(Default)]
+pub struct Install {
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+#[derive(Default)]
+struct State {
+    events: Vec<Event>,
+    finished: bool,
+}
+
+impl Install {
+    fn push(&self, event: Event) {
+        self.state.lock().unwrap().events.push(event);
+        self.changed.notify_all();
+    }
+
+    /// Record progress reported by
+    /// [`Handle::send_os`][libnspire::Handle::send_os]. Uploads are reported
+    /// per packet, so only whole-percent steps are kept.
+    pub fn progress(&self, progress: OsProgress) {
+        let event = match progress {
+            OsProgress::Uploading { remaining, total } => {
+                let sent = total - remaining;
+                let last = self.state.lock().unwrap().events.last().cloned();
+                if let Some(Event::Uploading { sent: last, .. }) = last {
+                    if sent != total && last * 100 / total == sent * 100 / total {
+                        return;
+                    }
+                }
+                Event::Uploading { sent, total }
+            }
+            OsProgress::Installing(percent) => Event::Installing { percent },
+        };
+        self.push(event);
+    }
+
+    pub fn finish(&self, result: Result<()>) {
+        self.push(match result {
+            Ok(()) => Event::Done,
+            Err(e) => Event::Error {
+                error: e.to_string(),
+            },
+        });
+        self.state.lock().unwrap().finished = true;
+        self.changed.notify_all();
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.state.lock().unwrap().finished
+    }
+
+    /// Wait for events after the first `seen` ones. Returns an empty list
+    /// once the installation has finished and every event has been seen.
+    pub fn wait(&self, seen: usize) -> Vec<Event> {
+        let state = self
+            .changed
+            .wait_while(self.state.lock().unwrap(), |state| {
+                !state.finished && state.events.len() <= seen
+            })
+            .unwrap();
+        state.events[seen.min(state.events.len())..].to_vec()
+    }
+}
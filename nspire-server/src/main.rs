@@ -0,0 +1,449 @@
+//! `nspire-server`: an HTTP/JSON API for the calculators connected to this
+//! computer.
+
+use std::convert::TryFrom;
+use std::io::{Read, Write};
+use std::process;
+use std::sync::Arc;
+use std::thread;
+
+use clap::Parser;
+use libnspire::dir::EntryType;
+use libnspire::os_image::OsImage;
+use libnspire::{Error, Result};
+use rusb::Context;
+use serde::Serialize;
+use tiny_http::{Header, Method, Request, Server};
+
+use crate::devices::Devices;
+
+mod devices;
+mod install;
+
+type Response = tiny_http::Response<Box<dyn Read + Send>>;
+
+/// The largest file that can be uploaded, which is more than any calculator
+/// can store.
+const MAX_FILE_SIZE: usize = 64 << 20;
+/// The largest OS image that can be uploaded.
+const MAX_OS_SIZE: usize = 256 << 20;
+
+#[derive(Parser)]
+#[command(name = "nspire-server", version, about)]
+struct Args {
+    /// The address to listen on
+    #[arg(long, default_value = "127.0.0.1:8080")]
+    listen: String,
+    /// Offer this many simulated calculators, for trying out the API
+    /// without hardware
+    #[arg(long, default_value_t = 0)]
+    simulate: usize,
+}
+
+fn main() {
+    let args = Args::parse();
+    let context = match Context::new() {
+        Ok(context) => Some(context),
+        Err(e) => {
+            eprintln!("nspire-server: USB is unavailable: {}", e);
+            None
+        }
+    };
+    let devices = match Devices::new(context, args.simulate) {
+        Ok(devices) => Arc::new(devices),
+        Err(e) => {
+            eprintln!("nspire-server: {}", e);
+            process::exit(1);
+        }
+    };
+    let server = match Server::http(&args.listen) {
+        Ok(server) => server,
+        Err(e) => {
+            eprintln!("nspire-server: can't listen on {}: {}", args.listen, e);
+            process::exit(1);
+        }
+    };
+    eprintln!("nspire-server: listening on http://{}", args.listen);
+    serve(&server, &devices);
+}
+
+fn serve(server: &Server, devices: &Arc<Devices>) {
+    for request in server.incoming_requests() {
+        let devices = devices.clone();
+        // Transfers and progress streams can take a while
+        thread::spawn(move || handle(&devices, request));
+    }
+}
+
+fn handle(devices: &Devices, mut request: Request) {
+    let url = request.url().to_string();
+    let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+    let segments: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+
+    if let (Method::Get, ["devices", id, "os", "progress"]) = (request.method(), &segments[..]) {
+        match devices.install(&decode(id)) {
+            Some(install) => stream_install(request, &install),
+            None => {
+                let response = json(404, &ErrorBody::new("no OS installation was started"));
+                let _ = request.respond(response);
+            }
+        }
+        return;
+    }
+
+    let response = match route(devices, &mut request, &segments, query) {
+        Ok(response) => response,
+        Err(e) => json(status(&e), &ErrorBody::new(e)),
+    };
+    // The client may have gone away; there's no one to tell
+    let _ = request.respond(response);
+}
+
+fn route(
+    devices: &Devices,
+    request: &mut Request,
+    segments: &[&str],
+    query: &str,
+) -> Result<Response> {
+    let method = request.method().clone();
+    Ok(match (&method, segments) {
+        (Method::Get, ["devices"]) => json(200, &devices.list()),
+        (Method::Get, ["devices", id, "info"]) => {
+            json(200, &devices.with(&decode(id), |handle| handle.info())?)
+        }
+        (Method::Get, ["devices", id, "screenshot"]) => {
+            let screenshot = devices.with(&decode(id), |handle| handle.screenshot())?;
+            let mut png = vec![];
+            image::DynamicImage::try_from(screenshot)?
+                .write_to(&mut png, image::ImageOutputFormat::Png)
+                .map_err(|_| Error::Io)?;
+            bytes(200, png, "image/png")
+        }
+        (Method::Post, ["devices", id, "os"]) => {
+            let image = match body(request, MAX_OS_SIZE)? {
+                Some(image) => OsImage::from_bytes(image)?,
+                None => return Ok(too_large(MAX_OS_SIZE)),
+            };
+            let force = query.split('&').any(|param| param == "force");
+            devices.start_install(&decode(id), image, force)?;
+            let progress = format!("/devices/{}/os/progress", id);
+            json(202, &serde_json::json!({ "progress": progress }))
+        }
+        (_, ["devices", id, "files", path @ ..]) => {
+            let path = format!(
+                "/{}",
+                path.iter().map(|s| decode(s)).collect::<Vec<_>>().join("/")
+            );
+            let id = decode(id);
+            match method {
+                Method::Get => devices.with(&id, |handle| {
+                    let path = path.trim_end_matches('/');
+                    if path.is_empty()
+                        || handle.file_attr(path)?.entry_type() == EntryType::Directory
+                    {
+                        let list = handle.list_dir(if path.is_empty() { "/" } else { path })?;
+                        return Ok(json(200, &*list));
+                    }
+                    let mut buf = vec![0; handle.file_attr(path)?.size() as usize];
+                    let len = handle.read_file(path, &mut buf, &mut |_| {})?;
+                    buf.truncate(len);
+                    Ok(bytes(200, buf, "application/octet-stream"))
+                })?,
+                // A trailing slash creates a directory instead of a file
+                Method::Put if path.ends_with('/') => {
+                    let path = path.trim_end_matches('/');
+                    devices.with(&id, |handle| handle.create_dir(path))?;
+                    empty(201)
+                }
+                Method::Put => {
+                    let buf = match body(request, MAX_FILE_SIZE)? {
+                        Some(buf) => buf,
+                        None => return Ok(too_large(MAX_FILE_SIZE)),
+                    };
+                    devices.with(&id, |handle| handle.write_file(&path, &buf, &mut |_| {}))?;
+                    empty(201)
+                }
+                Method::Delete => {
+                    devices.with(&id, |handle| {
+                        let path = path.trim_end_matches('/');
+                        match handle.file_attr(path)?.entry_type() {
+                            EntryType::Directory => handle.delete_dir(path),
+                            EntryType::File => handle.delete_file(path),
+                        }
+                    })?;
+                    empty(204)
+                }
+                _ => json(405, &ErrorBody::new("method not allowed")),
+            }
+        }
+        _ => json(404, &ErrorBody::new("not found")),
+    })
+}
+
+/// Send installation progress as server-sent events until it finishes or the
+/// client disconnects.
+fn stream_install(request: Request, install: &install::Install) {
+    // tiny_http buffers chunked responses, so write the stream by hand to
+    // deliver each event as it happens
+    let mut writer = request.into_writer();
+    let header = "HTTP/1.1 200 OK\r\n\
+                  Content-Type: text/event-stream\r\n\
+                  Cache-Control: no-cache\r\n\
+                  Transfer-Encoding: chunked\r\n\r\n";
+    if writer.write_all(header.as_bytes()).is_err() {
+        return;
+    }
+    let mut seen = 0;
+    loop {
+        let events = install.wait(seen);
+        seen += events.len();
+        let mut chunk = String::new();
+        for event in &events {
+            let data = serde_json::to_string(event).unwrap();
+            chunk += &format!("event: {}\ndata: {}\n\n", event.name(), data);
+        }
+        // An empty chunk ends the response
+        let sent = write!(writer, "{:x}\r\n{}\r\n", chunk.len(), chunk);
+        if sent.and_then(|_| writer.flush()).is_err() || events.is_empty() {
+            return;
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    error: String,
+}
+
+impl ErrorBody {
+    fn new(error: impl ToString) -> Self {
+        ErrorBody {
+            error: error.to_string(),
+        }
+    }
+}
+
+/// The HTTP status code for an error.
+fn status(err: &Error) -> u16 {
+    match err {
+        Error::NoDevice | Error::DoesNotExist | Error::Usb(rusb::Error::NoDevice) => 404,
+        Error::Exists | Error::Busy | Error::Usb(rusb::Error::Busy) => 409,
+        Error::Invalid | Error::NulError(_) => 400,
+        Error::Access | Error::Usb(rusb::Error::Access) => 403,
+        Error::InvalidOsImage | Error::OsMismatch { .. } | Error::OsVersionMismatch { .. } => 422,
+        Error::Timeout | Error::Usb(rusb::Error::Timeout) => 504,
+        _ => 500,
+    }
+}
+
+fn json<T: Serialize + ?Sized>(status: u16, value: &T) -> Response {
+    let body = serde_json::to_vec(value).unwrap();
+    bytes(status, body, "application/json")
+}
+
+fn bytes(status: u16, body: Vec<u8>, content_type: &str) -> Response {
+    let header = Header::from_bytes("Content-Type", content_type).unwrap();
+    tiny_http::Response::from_data(body)
+        .with_status_code(status)
+        .with_header(header)
+        .boxed()
+}
+
+fn empty(status: u16) -> Response {
+    tiny_http::Response::empty(status).boxed()
+}
+
+fn too_large(limit: usize) -> Response {
+    let message = format!("request body is larger than {} bytes", limit);
+    json(413, &ErrorBody::new(message))
+}
+
+/// Read the request body, or return `None` if it's longer than `limit`.
+fn body(request: &mut Request, limit: usize) -> Result<Option<Vec<u8>>> {
+    if request.body_length().is_some_and(|len| len > limit) {
+        return Ok(None);
+    }
+    // The length may not be known up front, if the body is chunked
+    let mut buf = vec![];
+    request
+        .as_reader()
+        .take(limit as u64 + 1)
+        .read_to_end(&mut buf)
+        .map_err(|_| Error::Io)?;
+    Ok(Some(buf).filter(|buf| buf.len() <= limit))
+}
+
+/// Undo percent-encoding in a URL path segment.
+fn decode(segment: &str) -> String {
+    let bytes = segment.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::{SocketAddr, TcpStream};
+    use std::sync::Arc;
+    use std::thread;
+
+    use serde_json::Value;
+    use tiny_http::Server;
+
+    use super::*;
+
+    /// A server offering one simulated calculator, `sim0`.
+    fn server() -> SocketAddr {
+        let devices = Arc::new(Devices::new(None, 1).unwrap());
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let addr = server.server_addr().to_ip().unwrap();
+        thread::spawn(move || serve(&server, &devices));
+        addr
+    }
+
+    fn request(addr: SocketAddr, method: &str, path: &str, body: &[u8]) -> (u16, Vec<u8>) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: {}\r\n\r\n",
+            method,
+            path,
+            body.len()
+        )
+        .unwrap();
+        stream.write_all(body).unwrap();
+        let mut response = vec![];
+        stream.read_to_end(&mut response).unwrap();
+        let end = response.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+        let head = String::from_utf8_lossy(&response[..end]).into_owned();
+        let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+        (status, response[end + 4..].to_vec())
+    }
+
+    fn get_json(addr: SocketAddr, path: &str) -> Value {
+        let (status, body) = request(addr, "GET", path, &[]);
+        assert_eq!(status, 200, "{}", String::from_utf8_lossy(&body));
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[test]
+    fn list() {
+        let addr = server();
+        let list = get_json(addr, "/devices");
+        assert_eq!(list[0]["id"], "sim0");
+        assert_eq!(list[0]["model"], "Simulator");
+        assert_eq!(list.as_array().unwrap().len(), 1);
+        assert_eq!(request(addr, "GET", "/devices/sim1/info", &[]).0, 404);
+    }
+
+    #[test]
+    fn info() {
+        let info = get_json(server(), "/devices/sim0/info");
+        assert_eq!(info["name"], "Simulator 0");
+        assert_eq!(info["os_extension"], "tcc");
+    }
+
+    #[test]
+    fn files() {
+        let addr = server();
+        assert_eq!(
+            request(addr, "PUT", "/devices/sim0/files/notes/", &[]).0,
+            201
+        );
+        let path = "/devices/sim0/files/notes/a%20b.tns";
+        assert_eq!(request(addr, "PUT", path, b"hello").0, 201);
+
+        let list = get_json(addr, "/devices/sim0/files/notes");
+        let names: Vec<_> = list
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|i| &i["name"])
+            .collect();
+        assert_eq!(names, ["a b.tns"]);
+        assert_eq!(request(addr, "GET", path, &[]), (200, b"hello".to_vec()));
+
+        assert_eq!(request(addr, "DELETE", path, &[]).0, 204);
+        assert_eq!(request(addr, "GET", path, &[]).0, 404);
+    }
+
+    #[test]
+    fn too_large() {
+        let addr = server();
+        for (path, limit) in [
+            ("/devices/sim0/files/big.tns", MAX_FILE_SIZE),
+            ("/devices/sim0/os", MAX_OS_SIZE),
+        ] {
+            let method = if path.ends_with("os") { "POST" } else { "PUT" };
+            // Refused from the headers alone, without sending the body
+            let mut stream = TcpStream::connect(addr).unwrap();
+            write!(
+                stream,
+                "{} {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\
+                 Content-Length: {}\r\n\r\n",
+                method,
+                path,
+                limit + 1
+            )
+            .unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            assert!(response.starts_with("HTTP/1.1 413 "), "{}", response);
+        }
+        assert_eq!(
+            request(addr, "GET", "/devices/sim0/files/big.tns", &[]).0,
+            404
+        );
+    }
+
+    #[test]
+    fn screenshot() {
+        let (status, png) = request(server(), "GET", "/devices/sim0/screenshot", &[]);
+        assert_eq!(status, 200);
+        let image = image::load_from_memory(&png).unwrap().to_rgb8();
+        assert_eq!(image.dimensions(), (320, 240));
+        assert!(image.pixels().all(|p| p.0 == [255, 255, 255]));
+    }
+
+    #[test]
+    fn install() {
+        let addr = server();
+        let image = [&b"TI-Nspire.tcc 4.5.1.0\n"[..], &[0; 1000]].concat();
+        let (status, body) = request(addr, "POST", "/devices/sim0/os", &image);
+        assert_eq!(status, 202, "{}", String::from_utf8_lossy(&body));
+
+        let (status, events) = request(addr, "GET", "/devices/sim0/os/progress", &[]);
+        assert_eq!(status, 200);
+        let events = String::from_utf8_lossy(&events);
+        assert!(events.contains("event: uploading\n"));
+        assert!(events.contains(r#"data: {"phase":"installing","percent":100}"#));
+        assert!(events.contains("event: done\n"));
+
+        let version = &get_json(addr, "/devices/sim0/info")["version"];
+        assert_eq!(
+            (&version["minor"], &version["patch"]),
+            (&5.into(), &1.into())
+        );
+        // Installing the same version again needs `force`
+        assert_eq!(request(addr, "POST", "/devices/sim0/os", &image).0, 422);
+        let (status, _) = request(addr, "POST", "/devices/sim0/os?force", &image);
+        assert_eq!(status, 202);
+    }
+}